@@ -0,0 +1,2541 @@
+use bropt_core::bfasm::assemble;
+use bropt_core::corpus::{self, CORPUS};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::OsString;
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use bropt_core::brainfuck::{
+    BaseInst, ByteRead, ByteWrite, CellWidth, Eof, Escaped, ExecAudit, ExecHooks, Extensions, FlushPolicy, HexDump,
+    Inst, InstType, IoEvent, OutputMatch, Seed, SliceReader, SourceMap, SplitOutput, Stdin, Stdout, Utf8Lossy,
+    decompile, try_parse_with,
+};
+use bropt_core::diff::diff_ir;
+use bropt_core::engine::{Program, Tape};
+use bropt_core::exectrace::{TraceWriter, diff_traces};
+use bropt_core::obfuscate::obfuscate;
+use bropt_core::report::folded_stacks;
+use bropt_core::rustgen::gen_rust;
+use bropt_core::snapshot::{self, Snapshot};
+use bropt_core::textgen::gen_text;
+use bropt_core::{Bropt, TapeKind};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CellArg {
+    #[value(name = "8")]
+    U8,
+    #[value(name = "16")]
+    U16,
+    #[value(name = "32")]
+    U32,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum EofArg {
+    /// Store 0
+    Zero,
+    /// Store the maximum cell value (-1)
+    MinusOne,
+    /// Leave the cell unchanged
+    Unchanged,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TapeArg {
+    /// Allocate every cell up front
+    Dense,
+    /// Allocate pages of cells on first write
+    Sparse,
+    /// Map the cells between guard pages and commit them as they are touched
+    #[cfg(unix)]
+    Mapped,
+    /// Map the cells like mapped, backed by transparent huge pages where the kernel has them
+    #[cfg(unix)]
+    Hugepages,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OutputArg {
+    /// Write the bytes unchanged
+    Raw,
+    /// Decode the bytes as UTF-8, replacing invalid sequences with U+FFFD
+    Utf8,
+    /// Write each byte as a hex pair, sixteen to a line
+    Hex,
+    /// Escape bytes that are not printable ASCII like Rust does, breaking lines after \n
+    Escaped,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CellFormat {
+    /// Decimal numbers
+    Dec,
+    /// Hex digits, zero-padded to the width of a cell
+    Hex,
+    /// Printable ASCII as a quoted character, anything else in decimal
+    Char,
+}
+
+impl CellFormat {
+    /// Renders the value of a cell `bytes` wide.
+    fn render(self, value: u32, bytes: usize) -> String {
+        match self {
+            CellFormat::Hex => format!("{:0digits$x}", value, digits = bytes * 2),
+            CellFormat::Char => match char::from_u32(value) {
+                Some(ch) if ch.is_ascii_graphic() || ch == ' ' => format!("'{}'", ch),
+                _ => value.to_string(),
+            },
+            CellFormat::Dec => value.to_string(),
+        }
+    }
+
+    /// The widest rendering of a cell `bytes` wide, for lining up columns.
+    fn width(self, bytes: usize) -> usize {
+        let max = u32::MAX >> (32 - bytes * 8);
+        match self {
+            CellFormat::Hex => bytes * 2,
+            CellFormat::Char => max.to_string().len().max(3),
+            CellFormat::Dec => max.to_string().len(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum EmitArg {
+    /// The optimized tree IR, or the instructions of a .bop file, as JSON
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum ExtArg {
+    /// ? stores a random byte in the current cell
+    Rand,
+    /// : writes the current cell to stderr
+    Stderr,
+}
+
+#[cfg(unix)]
+const DEFAULT_TAPE: TapeArg = TapeArg::Mapped;
+#[cfg(not(unix))]
+const DEFAULT_TAPE: TapeArg = TapeArg::Dense;
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write an HTML page showing what each loop of a program compiles to
+    Report {
+        /// Path to the Brainfuck program file to report on
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Path of the HTML file to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Print the loop structure of a program with what each loop compiles to
+    Outline {
+        /// Path to the Brainfuck program file to outline
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Print a Graphviz graph of which cells of a program feed which
+    Graph {
+        /// Path to the Brainfuck program file to graph
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Print whether a program is proven to stay on the tape, what keeps it from being proven, and which bounds checks its runs keep
+    Analyze {
+        /// Path to the Brainfuck program file to analyze, a bfasm program if it ends in .bfasm, or a .bop file
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Print a brainfuck program that outputs the given text
+    GenText {
+        /// Text the generated program prints
+        text: String,
+    },
+    /// Translate a bfasm program to brainfuck
+    Asm {
+        /// Path to the bfasm program file to translate
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Print an equivalent but harder to read version of a program
+    Obfuscate {
+        /// Path to the Brainfuck program file to obfuscate
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Check that two programs produce the same output for an input
+    Verify {
+        /// Path to the first Brainfuck program file
+        #[arg(value_name = "FILE", required_unless_present = "corpus")]
+        original: Option<String>,
+
+        /// Path to the second Brainfuck program file
+        #[arg(value_name = "OTHER", required_unless_present = "corpus")]
+        other: Option<String>,
+
+        /// Path to a file fed to both programs as input [default: no input]
+        #[arg(short, long)]
+        input: Option<String>,
+
+        /// Instead, check that every program of the built-in benchmark corpus prints its expected output
+        #[arg(long, conflicts_with_all = ["original", "other", "input"])]
+        corpus: bool,
+    },
+    /// Show how the optimized IR of two programs differs
+    Diff {
+        /// Path to the first Brainfuck program file, or a bfasm program if it ends in .bfasm
+        #[arg(value_name = "FILE")]
+        original: String,
+
+        /// Path to the second program file
+        #[arg(value_name = "OTHER")]
+        other: String,
+    },
+    /// Show where two traces written by --trace first differ, reading them a line at a time
+    TraceDiff {
+        /// Path to the first trace
+        #[arg(value_name = "FILE")]
+        original: String,
+
+        /// Path to the second trace
+        #[arg(value_name = "OTHER")]
+        other: String,
+
+        /// Lines of context shown around the first difference
+        #[arg(long, value_name = "LINES", default_value_t = 5)]
+        context: usize,
+    },
+    /// List the cells that differ between two snapshots written by --checkpoint of the same program
+    TapeDiff {
+        /// Path to the first snapshot
+        #[arg(value_name = "FILE")]
+        original: String,
+
+        /// Path to the second snapshot
+        #[arg(value_name = "OTHER")]
+        other: String,
+    },
+    /// Run a program under the debugger, executing the commands in a script: step [N],
+    /// continue, break BYTE [if CELL == VALUE], break-output STR, state, cell N, save NAME,
+    /// diff NAME and quit
+    Debug {
+        /// Path to the Brainfuck program file to debug, a bfasm program if it ends in .bfasm, or a .bop file
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// File of debugger commands, one per line; blank lines and lines starting with # are skipped
+        #[arg(long, value_name = "FILE")]
+        script: String,
+    },
+    /// Compile a program to a .bop file that runs without recompiling and can be linked
+    Build {
+        /// Path to the Brainfuck program file to compile, or a bfasm program if it ends in .bfasm
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Path of the .bop file to write
+        #[arg(short, long)]
+        output: String,
+
+        /// Compile for a tape that may start out nonzero, so the program can follow another in `bropt link`
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        linkable: bool,
+    },
+    /// Join .bop files into one that runs them one after another on the same tape
+    Link {
+        /// Paths to the .bop files in the order they run; all but the first must be built with --linkable
+        #[arg(value_name = "FILES", required = true)]
+        files: Vec<String>,
+
+        /// Path of the .bop file to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Write a standalone executable that runs a program, feeding its arguments to ,
+    Bundle {
+        /// Path to the Brainfuck program file to bundle, or a bfasm program if it ends in .bfasm
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Path of the executable to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Write a Cargo project whose main.rs is the optimized program translated to Rust
+    Scaffold {
+        /// Path to the Brainfuck program file to translate, or a bfasm program if it ends in .bfasm
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Directory of the project to create
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Time programs on bropt and other interpreters, checking that their outputs match
+    Compare {
+        /// Command line of another interpreter, split at spaces, with {file} replaced by the
+        /// program's path; may be repeated
+        #[arg(long, value_name = "COMMAND", required = true)]
+        against: Vec<String>,
+
+        /// Times to run each program on each interpreter, keeping the fastest
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+
+        /// Steady the times: pin to one CPU unless --pin-cpu picks it, pre-fault bropt's tape,
+        /// which then defaults to 65536 cells, and run each program once untimed first
+        #[arg(long)]
+        bench_mode: bool,
+
+        /// Paths to the programs; a file with the same name ending in .in is fed to them as input
+        #[arg(value_name = "FILES", required = true)]
+        files: Vec<String>,
+    },
+    /// Time the programs of the built-in benchmark corpus, checking that each prints what it should
+    Bench {
+        /// Times to run each program, keeping the fastest
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+
+        /// Steady the times as for compare
+        #[arg(long)]
+        bench_mode: bool,
+
+        /// Names of the programs to run [default: all of them]
+        #[arg(value_name = "NAMES")]
+        names: Vec<String>,
+    },
+    /// Print a script that completes bropt's subcommands and options in the given shell
+    Completions {
+        /// Shell the script is for
+        shell: Shell,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "bropt")]
+#[command(about = "An optimizing brainfuck interpreter")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the Brainfuck program file to execute, a bfasm program if it ends in .bfasm, a
+    /// program compiled by `bropt build` if it ends in .bop, or tree IR written by --emit json if
+    /// it ends in .json
+    #[arg(value_name = "FILE", required = true)]
+    file: Option<String>,
+
+    /// Number of cells in the memory tape [default: 4294967296 for a mapped or hugepages tape, 65536 otherwise]
+    #[arg(short, long, global = true)]
+    length: Option<usize>,
+
+    /// When to flush stdout besides before each , reads: never (until 8 KiB are buffered), byte,
+    /// line, input (the same as never) or every N bytes; -f alone is byte [default: line on a
+    /// terminal, never otherwise]
+    #[arg(short, long, value_name = "POLICY", num_args = 0..=1, require_equals = true, value_parser = parse_flush)]
+    flush: Option<Option<FlushPolicy>>,
+
+    /// Pass each keypress to , without waiting for Enter or echoing it; implies --flush=byte
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    raw_input: bool,
+
+    /// Copy every byte , reads to stderr, so transcripts show the input alongside the output
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    echo_input: bool,
+
+    /// Feed STR to , before the rest of the input
+    #[arg(long, value_name = "STR")]
+    input_string: Option<String>,
+
+    /// Feed the value of the environment variable VAR to , after --input-string
+    #[arg(long, value_name = "VAR")]
+    input_env: Option<String>,
+
+    /// Skip bounds checks even for programs that are not proven to stay on the tape; a program
+    /// that leaves it may crash or corrupt memory
+    #[arg(long, action = clap::ArgAction::SetTrue, global = true)]
+    unchecked: bool,
+
+    /// Check bounds, which is the default; kept for existing scripts
+    #[arg(short, long, action = clap::ArgAction::SetTrue, hide = true, conflicts_with = "unchecked")]
+    safe: bool,
+
+    /// Width of a memory cell in bits
+    #[arg(short, long, value_enum, default_value = "8", global = true)]
+    cell: CellArg,
+
+    /// Value stored by , at end of input [default: zero, or unchanged with --conformance]
+    #[arg(short, long, value_enum, global = true)]
+    eof: Option<EofArg>,
+
+    /// Use the semantics nested interpreters such as dbfi rely on: 8-bit wrapping cells, , leaving
+    /// the cell unchanged at end of input unless --eof says otherwise, and a sparse tape reaching
+    /// as far right as the program goes
+    #[arg(long, global = true, conflicts_with_all = ["cell", "length", "max_memory"])]
+    conformance: bool,
+
+    /// Enable commands beyond the standard eight; may be repeated or comma-separated
+    #[arg(long, value_enum, value_delimiter = ',', global = true)]
+    ext: Vec<ExtArg>,
+
+    /// Seed for everything left to chance, such as the bytes ? stores and the rewrites obfuscate picks, to make runs reproducible [default: 0 for obfuscate, otherwise a different seed every run, printed with -v]
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Preload the tape with the bytes of FILE, one per cell, from cell OFFSET of the program [default offset: 0]
+    #[arg(long, value_name = "FILE[:OFFSET]", value_parser = parse_tape_init, conflicts_with = "resume")]
+    tape_init: Option<TapeInit>,
+
+    /// How the memory tape is stored
+    #[arg(short, long, value_enum, default_value_t = DEFAULT_TAPE, global = true)]
+    tape: TapeArg,
+
+    /// How output bytes are written to stdout
+    #[arg(long, value_enum, default_value_t = OutputArg::Raw)]
+    output_format: OutputArg,
+
+    /// Refuse to run with a tape that could grow past this many bytes; also shortens the default length to fit
+    #[arg(long, value_name = "BYTES", global = true)]
+    max_memory: Option<usize>,
+
+    /// Log every rewrite the optimizer makes to stderr
+    #[arg(long, action = clap::ArgAction::SetTrue, global = true)]
+    verbose_opt: bool,
+
+    /// Let the optimizer make only its first N rewrites, to bisect which one breaks a program
+    #[arg(long, value_name = "N", global = true)]
+    opt_fuel: Option<usize>,
+
+    /// Run only on CPU N, so the scheduler does not move runs between cores; the interpreters compare starts inherit it
+    #[arg(long, value_name = "N", global = true)]
+    pin_cpu: Option<usize>,
+
+    /// Only print errors to stderr, leaving out warnings, reports and --progress
+    #[arg(short, long, action = clap::ArgAction::SetTrue, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print compile statistics and timing to stderr; twice adds every rewrite the optimizer makes
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Run bounds-checked and, on Ctrl-C, print where the program was and the tape around the pointer
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    dump_on_interrupt: bool,
+
+    /// Stop as soon as the output so far ends with STR and print where the program was, as
+    /// Ctrl-C does under --dump-on-interrupt; with --checkpoint, also write a snapshot
+    #[arg(long, value_name = "STR", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    break_on_output: Option<String>,
+
+    /// Stop after exactly N instructions of the compiled program, counted as the instruction
+    /// counts bropt reports are, and print where the program was as --break-on-output does
+    #[arg(long, value_name = "N")]
+    run_to_step: Option<u64>,
+
+    /// Like --dump-on-interrupt, and also write a snapshot to this file that --resume continues from
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<String>,
+
+    /// Continue from a snapshot written by --checkpoint, reading the rest of the input from stdin
+    #[arg(long, value_name = "FILE")]
+    resume: Option<String>,
+
+    /// Also write a snapshot to FILE every N instructions, replacing the old one atomically, so a crashed run can be resumed
+    #[arg(long, num_args = 2, value_names = ["N", "FILE"])]
+    checkpoint_every: Option<Vec<String>>,
+
+    /// Run bounds-checked and keep a line on stderr updated with the instructions executed and the speed
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    progress: bool,
+
+    /// Run bounds-checked, sampling which loops are running, and write them to FILE as folded
+    /// stacks for inferno or flamegraph.pl, one frame per enclosing loop
+    #[arg(long, value_name = "FILE")]
+    flamegraph: Option<String>,
+
+    /// Run bounds-checked alongside a slow model that takes every loop from its matching brackets
+    /// instead of the fused and threaded jumps, and stop with an error where the two first disagree
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["tape_init", "resume", "checkpoint", "checkpoint_every", "dump_on_interrupt", "progress", "flamegraph", "json", "break_on_output", "run_to_step"])]
+    check_exec: bool,
+
+    /// Run bounds-checked and write a line to FILE for every instruction executed and every byte
+    /// read or written, for `bropt trace-diff`
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tape_init", "resume", "checkpoint", "checkpoint_every", "dump_on_interrupt", "progress", "flamegraph", "json", "check_exec", "break_on_output", "run_to_step"])]
+    trace: Option<String>,
+
+    /// After the program finishes, print a hex and ASCII dump of the tape and the pointer to stderr
+    /// [default range: the first 256 cells, extended to the pointer]
+    #[arg(long, value_name = "START..END", num_args = 0..=1, require_equals = true, value_parser = parse_range)]
+    dump_tape: Option<Option<Range<usize>>>,
+
+    /// When the program finishes, exit with the low 8 bits of this cell as the status [default:
+    /// the cell under the pointer]
+    #[arg(long, value_name = "OFFSET", num_args = 0..=1, require_equals = true, value_parser = parse_number)]
+    exit_cell: Option<Option<usize>>,
+
+    /// How cells are shown by --dump-tape, tape-diff and when a run is interrupted [default: hex
+    /// in the columns of --dump-tape, decimal elsewhere]
+    #[arg(long, value_enum, value_name = "FORMAT", global = true)]
+    cell_format: Option<CellFormat>,
+
+    /// Instead of writing the output, print a JSON object with the output and the bytes written by
+    /// : in base64, how the run ended, the instructions executed and the time taken
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "output_format")]
+    json: bool,
+
+    /// Add a hash of the final tape's nonzero cells to the --json result, to compare runs
+    #[arg(long, action = clap::ArgAction::SetTrue, requires = "json")]
+    tape_digest: bool,
+
+    /// After the program finishes, print the sizes of its IR and tape and the peak memory of
+    /// compiling and running it to stderr
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    mem_stats: bool,
+
+    /// Print the program's IR in this format instead of running it
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    emit: Option<EmitArg>,
+
+    /// Optimization level (0-2)
+    #[arg(short = 'O', long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=2), global = true)]
+    opt_level: u8,
+
+    /// Arguments fed to , each followed by a newline, after --input-string and --input-env and before stdin
+    #[arg(value_name = "ARGS", last = true)]
+    program_args: Vec<OsString>,
+}
+
+/// Prints diagnostics to stderr up to the level `-q` and `-v` choose, and the optimizer's
+/// decisions at `-vv` or with `--verbose-opt`.
+struct Diagnostics {
+    level: log::LevelFilter,
+    decisions: bool,
+}
+
+impl Diagnostics {
+    fn install(args: &Args) {
+        let level = match (args.quiet, args.verbose) {
+            (true, _) => log::LevelFilter::Error,
+            (false, 0) => log::LevelFilter::Warn,
+            (false, 1) => log::LevelFilter::Info,
+            (false, _) => log::LevelFilter::Debug,
+        };
+        let decisions = args.verbose_opt || level >= log::LevelFilter::Debug;
+        log::set_max_level(if decisions {
+            level.max(log::LevelFilter::Debug)
+        } else {
+            level
+        });
+        log::set_logger(Box::leak(Box::new(Diagnostics { level, decisions }))).expect("Failed to install the logger.");
+    }
+}
+
+impl log::Log for Diagnostics {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        if metadata.target() == "bropt::opt" {
+            self.decisions
+        } else {
+            metadata.level() <= self.level
+        }
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// The system allocator, counting the bytes in use and their peak for --mem-stats.
+struct CountingAlloc;
+
+static HEAP_IN_USE: AtomicUsize = AtomicUsize::new(0);
+static HEAP_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+fn heap_grew(bytes: usize) {
+    let in_use = HEAP_IN_USE.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    HEAP_PEAK.fetch_max(in_use, Ordering::Relaxed);
+}
+
+/// Returns the most heap in use at once since the last call, and starts counting again from what
+/// is in use now.
+fn take_heap_peak() -> usize {
+    HEAP_PEAK.swap(HEAP_IN_USE.load(Ordering::Relaxed), Ordering::Relaxed)
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            heap_grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            heap_grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        HEAP_IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            HEAP_IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+            heap_grew(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Peak resident set size of the process in bytes, which also counts the touched pages of a
+/// mapped tape.
+#[cfg(unix)]
+fn peak_resident() -> Option<usize> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    // Linux reports kilobytes and macOS bytes.
+    let unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    Some(usage.ru_maxrss as usize * unit)
+}
+
+#[cfg(not(unix))]
+fn peak_resident() -> Option<usize> {
+    None
+}
+
+/// Prints what --mem-stats reports: the sizes of the compiled program and of the tape, and the
+/// heap peaks of compiling and of running, to stderr.
+fn print_mem_stats(engine: &Bropt, prog: &Program, output: Option<&SplitOutput>, compile_peak: usize, run_peak: usize) {
+    let bytes = |count: usize| format!("{}B", si(count as f64));
+    eprintln!("{:<24}{}", "source", bytes(prog.source_len()));
+    if prog.tree_len() > 0 {
+        eprintln!(
+            "{:<24}{} nodes, {}",
+            "tree IR",
+            si(prog.tree_len() as f64),
+            bytes(prog.tree_len() * size_of::<BaseInst>())
+        );
+    }
+    eprintln!(
+        "{:<24}{}, {} with the source map",
+        "instructions",
+        si(prog.insts().len() as f64),
+        bytes(prog.insts().len() * (size_of::<Inst>() + size_of::<Option<u32>>()))
+    );
+    eprintln!("{:<24}{}", "heap peak compiling", bytes(compile_peak));
+    let tape = engine.tape_len().saturating_mul(engine.cell().bytes());
+    let tape = match engine.tape() {
+        TapeKind::Dense => format!("{}, allocated up front", bytes(tape)),
+        TapeKind::Sparse => format!("up to {}, allocated as touched", bytes(tape)),
+        #[cfg(unix)]
+        TapeKind::Mapped => format!("up to {}, mapped and committed as touched", bytes(tape)),
+        #[cfg(unix)]
+        TapeKind::HugePages => format!("up to {}, mapped and committed in huge pages as touched", bytes(tape)),
+    };
+    eprintln!("{:<24}{}", "tape", tape);
+    if let Some(output) = output {
+        let buffered = output.out.capacity() + output.err.capacity();
+        eprintln!("{:<24}{}", "output buffer", bytes(buffered));
+    }
+    eprintln!("{:<24}{}", "heap peak running", bytes(run_peak));
+    if let Some(resident) = peak_resident() {
+        eprintln!("{:<24}{}", "peak resident", bytes(resident));
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static REPORT_REQUESTED: AtomicBool = AtomicBool::new(false);
+static MONITORED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    // A second Ctrl-C exits right away, in case the program is blocked reading input.
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        restore_terminal();
+        unsafe { libc::_exit(130) };
+    }
+}
+
+#[cfg(unix)]
+fn install_interrupt_handler() -> Result<(), String> {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    if unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) } == libc::SIG_ERR {
+        return Err("Failed to install the interrupt handler.".into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn install_interrupt_handler() -> Result<(), String> {
+    Err("Dumping state on interrupt is only supported on unix.".into())
+}
+
+#[cfg(unix)]
+extern "C" fn on_report_signal(_signal: libc::c_int) {
+    if MONITORED.load(Ordering::Relaxed) {
+        REPORT_REQUESTED.store(true, Ordering::Relaxed);
+    } else {
+        // Only async-signal-safe calls are allowed here, so the note is a fixed string.
+        let note = b"bropt: progress is only tracked with --progress, --dump-on-interrupt, --checkpoint, --checkpoint-every or --resume\n";
+        unsafe { libc::write(libc::STDERR_FILENO, note.as_ptr().cast(), note.len()) };
+    }
+}
+
+/// Answers SIGUSR1 with a progress report instead of letting it terminate the run.
+#[cfg(unix)]
+fn install_report_handler() {
+    let handler = on_report_signal as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGUSR1, handler as libc::sighandler_t) };
+}
+
+#[cfg(not(unix))]
+fn install_report_handler() {}
+
+/// The clock is read once every `PROGRESS_MASK + 1` instructions while showing progress.
+const PROGRESS_MASK: u64 = (1 << 20) - 1;
+
+/// The running instruction is sampled for --flamegraph once every `FLAME_INTERVAL` instructions,
+/// a prime so the samples do not keep landing on the same step of a loop.
+const FLAME_INTERVAL: u64 = 1009;
+
+/// Shortest time between two progress updates.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// State of the `--progress` line.
+struct Progress {
+    start: Instant,
+    last: Instant,
+    tty: bool,
+}
+
+/// Terminal settings from before `--raw-input` changed them.
+#[cfg(unix)]
+static ORIGINAL_TERMIOS: std::sync::OnceLock<libc::termios> = std::sync::OnceLock::new();
+
+/// Puts the terminal on stdin into non-canonical, no-echo mode until the process exits.
+/// Does nothing when stdin is not a terminal, so piped input still works.
+#[cfg(unix)]
+fn enable_raw_input() -> Result<(), String> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+        return Err("Failed to read the terminal settings.".into());
+    }
+    let _ = ORIGINAL_TERMIOS.set(termios);
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+    // Restore on exit, and on the signals that would otherwise kill the process with the
+    // terminal still raw. Ctrl-C keeps working since ISIG is left on.
+    let handler = on_terminating_signal as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::atexit(restore_terminal_at_exit);
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+        return Err("Failed to switch the terminal to raw input.".into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn enable_raw_input() -> Result<(), String> {
+    Err("Raw terminal input is only supported on unix.".into())
+}
+
+/// Puts back the terminal settings saved by [`enable_raw_input`], if any. Async-signal-safe.
+#[cfg(unix)]
+fn restore_terminal() {
+    if let Some(termios) = ORIGINAL_TERMIOS.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn restore_terminal_at_exit() {
+    restore_terminal();
+}
+
+#[cfg(unix)]
+extern "C" fn on_terminating_signal(signal: libc::c_int) {
+    restore_terminal();
+    unsafe { libc::_exit(128 + signal) };
+}
+
+/// Counts instructions, shows progress, samples the running loops, reports on SIGUSR1 and stops the run once Ctrl-C is pressed,
+/// a periodic checkpoint is due or `--run-to-step` is reached.
+struct Monitor<'a> {
+    steps: u64,
+    /// Step count at which to stop for the next `--checkpoint-every` snapshot.
+    stop_at: u64,
+    /// Step count at which to stop for good, from `--run-to-step`.
+    run_to: u64,
+    source_map: &'a SourceMap,
+    progress: Option<Progress>,
+    /// Samples taken of each instruction for `--flamegraph`.
+    samples: Option<Vec<u64>>,
+    /// The `--break-on-output` pattern.
+    output_match: Option<OutputMatch>,
+}
+
+impl ExecHooks for Monitor<'_> {
+    #[inline(always)]
+    fn on_instruction(&mut self, ip: usize, _inst: &Inst, dp: usize) {
+        self.steps += 1;
+        if self.steps & PROGRESS_MASK == 0 && self.progress.is_some() {
+            self.tick();
+        }
+        if let Some(samples) = &mut self.samples
+            && self.steps.is_multiple_of(FLAME_INTERVAL)
+        {
+            samples[ip] += 1;
+        }
+        if REPORT_REQUESTED.load(Ordering::Relaxed) {
+            self.report(ip, dp);
+        }
+    }
+
+    #[inline(always)]
+    fn on_io(&mut self, ip: usize, dp: usize, event: IoEvent) {
+        if let Some(output_match) = &mut self.output_match {
+            output_match.on_io(ip, dp, event);
+        }
+    }
+
+    #[inline(always)]
+    fn should_stop(&mut self) -> bool {
+        INTERRUPTED.load(Ordering::Relaxed) || self.steps >= self.stop_at.min(self.run_to) || self.output_matched()
+    }
+}
+
+impl Monitor<'_> {
+    /// Whether the output matched `--break-on-output`.
+    #[inline(always)]
+    fn output_matched(&self) -> bool {
+        self.output_match.as_ref().is_some_and(OutputMatch::found)
+    }
+
+    #[cold]
+    fn report(&self, ip: usize, dp: usize) {
+        REPORT_REQUESTED.store(false, Ordering::Relaxed);
+        let _ = std::io::stdout().flush();
+        log::warn!(
+            "{} instructions executed, ip {} ({}), dp {}",
+            self.steps,
+            ip,
+            location(self.source_map, ip),
+            dp
+        );
+    }
+
+    #[cold]
+    fn tick(&mut self) {
+        let steps = self.steps;
+        let Some(progress) = &mut self.progress else {
+            return;
+        };
+        let now = Instant::now();
+        if now - progress.last < PROGRESS_INTERVAL {
+            return;
+        }
+        progress.last = now;
+        let elapsed = (now - progress.start).as_secs_f64();
+        let line = format!(
+            "{} instructions, {}/s, {:.1}s elapsed",
+            si(steps as f64),
+            si(steps as f64 / elapsed),
+            elapsed
+        );
+        if progress.tty {
+            eprint!("\r\x1b[2K{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Clears the progress line from the terminal so it does not mix with what follows.
+    fn finish(&self) {
+        if self.progress.as_ref().is_some_and(|progress| progress.tty) {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+/// Formats `value` with a K, M or G suffix.
+fn si(value: f64) -> String {
+    match value {
+        _ if value >= 1e9 => format!("{:.2}G", value / 1e9),
+        _ if value >= 1e6 => format!("{:.2}M", value / 1e6),
+        _ if value >= 1e3 => format!("{:.2}K", value / 1e3),
+        _ => format!("{:.0}", value),
+    }
+}
+
+/// Describes where instruction `ip` came from in the source.
+fn location(source_map: &SourceMap, ip: usize) -> String {
+    match source_map.describe_loop(ip) {
+        Some(place) => format!("in {}", place),
+        None => "outside any loop".to_string(),
+    }
+}
+
+/// Writes the `--flamegraph` samples to `path` as folded stacks rooted at the program's file name.
+fn write_flamegraph(path: &str, prog: &Program, samples: &[u64], file: &str) -> Result<(), String> {
+    let name = std::path::Path::new(file)
+        .file_name()
+        .map_or(file.into(), |name| name.to_string_lossy())
+        .replace(';', "_");
+    let stacks = folded_stacks(prog.insts(), prog.source_map(), samples, &name);
+    std::fs::write(path, stacks).map_err(|err| format!("{}: {}", path, err))
+}
+
+/// Cells shown on each side of the pointer when an interrupted run is dumped.
+const DUMP_WINDOW: usize = 8;
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over `path`, so a crash leaves
+/// either the old file or the new one, never a torn mix.
+fn write_atomically(path: &str, bytes: &[u8]) -> Result<(), String> {
+    let temp = format!("{}.tmp", path);
+    let write = || -> std::io::Result<()> {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    };
+    write().map_err(|err| format!("{}: {}", path, err))
+}
+
+/// Parses the `N FILE` given to `--checkpoint-every`.
+fn parse_checkpoint_every(values: &[String]) -> Result<(u64, &str), String> {
+    let every = parse_number(&values[0])?;
+    if every == 0 {
+        return Err("--checkpoint-every needs a positive number of instructions".into());
+    }
+    Ok((every as u64, &values[1]))
+}
+
+/// How a run ended: the tape and pointer it left, the instructions executed when they were
+/// counted, and whether Ctrl-C stopped it, which only returns for `--json`.
+struct Ended {
+    tape: Tape,
+    dp: usize,
+    steps: Option<u64>,
+    interrupted: bool,
+}
+
+/// Runs with the [`Monitor`], writing a snapshot every `--checkpoint-every` instructions, and if
+/// Ctrl-C stops the program, prints its state and writes the checkpoint before exiting.
+fn run_monitored<R: ByteRead, W: ByteWrite>(
+    engine: &Bropt,
+    prog: &Program,
+    args: &Args,
+    init: Option<(Vec<u8>, usize)>,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Ended, String> {
+    let every = args
+        .checkpoint_every
+        .as_deref()
+        .map(parse_checkpoint_every)
+        .transpose()?;
+    if args.dump_on_interrupt || args.checkpoint.is_some() || args.resume.is_some() || every.is_some() || args.json {
+        install_interrupt_handler()?;
+    }
+    MONITORED.store(true, Ordering::Relaxed);
+    let now = Instant::now();
+    let mut monitor = Monitor {
+        steps: 0,
+        stop_at: every.map_or(u64::MAX, |(every, _)| every),
+        run_to: args.run_to_step.unwrap_or(u64::MAX),
+        source_map: prog.source_map(),
+        progress: (args.progress && !args.quiet).then(|| Progress {
+            start: now,
+            last: now,
+            tty: std::io::stderr().is_terminal(),
+        }),
+        samples: args.flamegraph.as_ref().map(|_| vec![0; prog.insts().len()]),
+        output_match: args
+            .break_on_output
+            .as_ref()
+            .map(|text| OutputMatch::new(text.as_bytes())),
+    };
+    let start = match (&args.resume, init) {
+        (Some(path), _) => {
+            let bytes = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+            Some(engine.load_snapshot(prog, &bytes)?)
+        }
+        (None, Some((data, at))) => Some(engine.preload(prog, &data, at)?),
+        (None, None) => None,
+    };
+    let mut snapshot = match start {
+        Some(snapshot) => engine.resume(prog, snapshot, input, output, &mut monitor)?,
+        None => engine.run_resumable(prog, input, output, &mut monitor)?,
+    };
+    if let Some((every, path)) = every {
+        while !snapshot.finished(prog)
+            && !INTERRUPTED.load(Ordering::Relaxed)
+            && !monitor.output_matched()
+            && monitor.steps < monitor.run_to
+        {
+            // The snapshot resumes after everything printed so far, so that output must not be lost.
+            output.flush();
+            write_atomically(path, &snapshot.to_bytes(prog))?;
+            monitor.stop_at = monitor.steps.saturating_add(every);
+            snapshot = engine.resume(prog, snapshot, input, output, &mut monitor)?;
+        }
+    }
+    monitor.finish();
+    if let (Some(path), Some(samples)) = (&args.flamegraph, &monitor.samples) {
+        write_flamegraph(path, prog, samples, args.file.as_deref().unwrap_or("-"))?;
+    }
+    let finished = snapshot.finished(prog);
+    if !finished && !args.json {
+        output.flush();
+        if monitor.output_matched() {
+            log::warn!("\nThe output matched after {} instructions.", monitor.steps);
+        } else if monitor.steps >= monitor.run_to {
+            log::warn!("\nStopped after {} instructions.", monitor.steps);
+        } else {
+            log::warn!("\nInterrupted after {} instructions.", monitor.steps);
+        }
+        log::warn!(
+            "ip {} ({}), dp {}",
+            snapshot.ip,
+            location(prog.source_map(), snapshot.ip),
+            snapshot.dp
+        );
+        for line in cells_around(
+            prog,
+            &snapshot.tape,
+            snapshot.dp,
+            args.cell_format.unwrap_or(CellFormat::Dec),
+        ) {
+            log::warn!("{}", line);
+        }
+    }
+    if !finished && let Some(path) = args.checkpoint.as_deref().or(every.map(|(_, path)| path)) {
+        write_atomically(path, &snapshot.to_bytes(prog))?;
+        log::warn!("Wrote a checkpoint to {}; continue with --resume {}", path, path);
+    }
+    if !finished && !args.json {
+        std::process::exit(130);
+    }
+    Ok(Ended {
+        tape: snapshot.tape,
+        dp: snapshot.dp,
+        steps: Some(monitor.steps),
+        interrupted: !finished,
+    })
+}
+
+/// Describes the cells on each side of the pointer, and the cells the source names if it names
+/// any, a line each.
+fn cells_around(prog: &Program, tape: &Tape, dp: usize, format: CellFormat) -> Vec<String> {
+    let start = dp.saturating_sub(DUMP_WINDOW);
+    let end = (dp + DUMP_WINDOW + 1).min(tape.len());
+    let bytes = tape.cell().bytes();
+    let cells: Vec<String> = (start..end)
+        .map(|pos| match format.render(tape.get(pos), bytes) {
+            value if pos == dp => format!("[{}]", value),
+            value => value,
+        })
+        .collect();
+    let mut lines = vec![format!("tape[{}..{}]: {}", start, end, cells.join(" "))];
+    let named: Vec<String> = prog
+        .source_map()
+        .labels()
+        .cells()
+        .filter(|&(pos, _)| pos < tape.len())
+        .map(|(pos, name)| format!("{} {}", name, format.render(tape.get(pos), bytes)))
+        .collect();
+    if !named.is_empty() {
+        lines.push(format!("named cells: {}", named.join(", ")));
+    }
+    lines
+}
+
+/// Runs the program read from FILE, monitored when any option needs it, and returns how it ended.
+fn run_program<R: ByteRead, W: ByteWrite>(
+    engine: &Bropt,
+    prog: &Program,
+    args: &Args,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Ended, String> {
+    let init = match &args.tape_init {
+        Some(TapeInit { path, offset }) => Some((
+            std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?,
+            *offset,
+        )),
+        None => None,
+    };
+    if args.check_exec {
+        return run_audited(engine, prog, input, output);
+    }
+    if let Some(path) = &args.trace {
+        return run_traced(engine, prog, path, input, output);
+    }
+    if args.progress
+        || args.dump_on_interrupt
+        || args.checkpoint.is_some()
+        || args.resume.is_some()
+        || args.checkpoint_every.is_some()
+        || args.json
+        || args.flamegraph.is_some()
+        || args.break_on_output.is_some()
+        || args.run_to_step.is_some()
+    {
+        return run_monitored(engine, prog, args, init, input, output);
+    }
+    let (tape, dp) = match init {
+        Some((data, at)) => engine.run_preloaded(prog, &data, at, input, output)?,
+        None => engine.run(prog, input, output)?,
+    };
+    Ok(Ended {
+        tape,
+        dp,
+        steps: None,
+        interrupted: false,
+    })
+}
+
+/// Runs `prog` under `--trace`, writing the trace to `path`.
+fn run_traced<R: ByteRead, W: ByteWrite>(
+    engine: &Bropt,
+    prog: &Program,
+    path: &str,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Ended, String> {
+    let file = std::fs::File::create(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut trace = TraceWriter::new(std::io::BufWriter::new(file));
+    let result = engine.run_with_hooks(prog, input, output, &mut trace);
+    trace.finish().map_err(|err| format!("{}: {}", path, err))?;
+    let (tape, dp) = result?;
+    Ok(Ended {
+        tape,
+        dp,
+        steps: None,
+        interrupted: false,
+    })
+}
+
+/// Runs `prog` under `--check-exec`, failing at the first step where it and the model disagree.
+fn run_audited<R: ByteRead, W: ByteWrite>(
+    engine: &Bropt,
+    prog: &Program,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Ended, String> {
+    let mut audit = ExecAudit::new(prog.insts(), engine.cell(), engine.eof(), engine.seed());
+    let snapshot = engine.run_resumable(prog, input, output, &mut audit)?;
+    if let Some(divergence) = audit.finish(snapshot.ip, snapshot.dp, |pos| snapshot.tape.get(pos)) {
+        output.flush();
+        return Err(format!(
+            "execution check failed {}, {}",
+            location(prog.source_map(), divergence.ip),
+            divergence
+        ));
+    }
+    log::warn!("Execution check passed.");
+    Ok(Ended {
+        tape: snapshot.tape,
+        dp: snapshot.dp,
+        steps: None,
+        interrupted: false,
+    })
+}
+
+/// Why a `bropt debug` run last stopped.
+#[derive(Clone, Copy)]
+enum Stop {
+    Steps,
+    Breakpoint,
+    Output,
+}
+
+/// When a `bropt debug` breakpoint stops the run.
+#[derive(Clone)]
+enum Breakpoint {
+    Never,
+    Always,
+    /// When any of these cells holds the value paired with it.
+    When(Vec<(usize, u32)>),
+}
+
+impl Breakpoint {
+    #[inline(always)]
+    fn hit(&self, cell: impl Fn(usize) -> Option<u32>) -> bool {
+        match self {
+            Breakpoint::Never => false,
+            Breakpoint::Always => true,
+            Breakpoint::When(conditions) => conditions.iter().any(|&(pos, value)| cell(pos) == Some(value)),
+        }
+    }
+}
+
+/// Stops a `bropt debug` run after a number of instructions, before an iteration of a loop with
+/// a breakpoint, or once its output matches.
+struct Debugger {
+    steps: u64,
+    stop_at: u64,
+    /// When to stop before each instruction, which is set for the first of the body of each loop
+    /// with a breakpoint.
+    breakpoints: Vec<Breakpoint>,
+    output_match: Option<OutputMatch>,
+    /// Set on resuming, so the breakpoint the run stopped at lets it go on.
+    resumed: bool,
+    stop: Option<Stop>,
+    /// Whether the program's output so far ends partway through a line.
+    mid_line: bool,
+}
+
+impl ExecHooks for Debugger {
+    #[inline(always)]
+    fn on_instruction(&mut self, _ip: usize, _inst: &Inst, _dp: usize) {
+        self.steps += 1;
+    }
+
+    #[inline(always)]
+    fn on_io(&mut self, ip: usize, dp: usize, event: IoEvent) {
+        if let IoEvent::Write(byte) = event {
+            self.mid_line = byte != b'\n';
+        }
+        if let Some(output_match) = &mut self.output_match {
+            output_match.on_io(ip, dp, event);
+        }
+    }
+
+    #[inline(always)]
+    fn break_before(&mut self, ip: usize, _dp: usize, cell: impl Fn(usize) -> Option<u32>) -> bool {
+        let resumed = std::mem::take(&mut self.resumed);
+        self.stop = if self.steps >= self.stop_at {
+            Some(Stop::Steps)
+        } else if !resumed && self.breakpoints[ip].hit(cell) {
+            Some(Stop::Breakpoint)
+        } else if self.output_match.as_ref().is_some_and(OutputMatch::found) {
+            Some(Stop::Output)
+        } else {
+            None
+        };
+        self.stop.is_some()
+    }
+}
+
+/// Runs `prog` under the debugger, executing the commands in `script`, read from `path`, and
+/// printing what they show. The program reads stdin, and its output is flushed before each
+/// result, on a line of its own, so the two come out in order.
+fn debug(engine: &Bropt, prog: &Program, path: &str, script: &str, format: CellFormat) -> Result<(), String> {
+    let mut input = Stdin;
+    let mut output = Stdout::new(FlushPolicy::Never);
+    let mut debugger = Debugger {
+        steps: 0,
+        stop_at: 0,
+        breakpoints: vec![Breakpoint::Never; prog.insts().len()],
+        output_match: None,
+        resumed: false,
+        stop: None,
+        mid_line: false,
+    };
+    let mut snapshot = engine.run_resumable(prog, &mut input, &mut output, &mut debugger)?;
+    let mut saved = std::collections::HashMap::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (command, operand) = line
+            .split_once(' ')
+            .map_or((line, ""), |(command, rest)| (command, rest.trim()));
+        let fail = |message: String| format!("{}:{}: {}", path, number + 1, message);
+        let number = || parse_number(operand).map_err(fail);
+        let at = |snapshot: &Snapshot| {
+            format!(
+                "ip {} ({}), dp {}",
+                snapshot.ip,
+                location(prog.source_map(), snapshot.ip),
+                snapshot.dp
+            )
+        };
+        match command {
+            "break" | "break-output" | "cell" | "save" | "diff" if operand.is_empty() => {
+                return Err(fail(format!("{} needs an operand", command)));
+            }
+            "step" | "continue" => {
+                if snapshot.finished(prog) {
+                    println!("The program has finished.");
+                    continue;
+                }
+                debugger.stop_at = match command {
+                    "step" if operand.is_empty() => debugger.steps + 1,
+                    "step" => debugger.steps.saturating_add(number()?.max(1) as u64),
+                    _ => u64::MAX,
+                };
+                debugger.resumed = true;
+                snapshot = engine.resume(prog, snapshot, &mut input, &mut output, &mut debugger)?;
+                output.flush();
+                if std::mem::take(&mut debugger.mid_line) {
+                    println!();
+                }
+                let steps = debugger.steps;
+                match debugger.stop {
+                    _ if snapshot.finished(prog) => println!("The program finished after {} instructions.", steps),
+                    Some(Stop::Breakpoint) => println!("Breakpoint after {} instructions at {}", steps, at(&snapshot)),
+                    Some(Stop::Output) => {
+                        debugger.output_match.as_mut().unwrap().clear();
+                        println!("The output matched after {} instructions at {}", steps, at(&snapshot));
+                    }
+                    Some(Stop::Steps) | None => println!("Stopped after {} instructions at {}", steps, at(&snapshot)),
+                }
+            }
+            "break" => {
+                let (pos, condition) = match operand.split_once(" if ") {
+                    Some((pos, condition)) => (pos, Some(parse_condition(condition).map_err(fail)?)),
+                    None => (operand, None),
+                };
+                let pos = parse_number(pos.trim()).map_err(fail)?;
+                let mut found = None;
+                for (ip, inst) in prog.insts().iter().enumerate() {
+                    if inst.cmd() == InstType::Open && prog.source_map().loop_at(ip) == u32::try_from(pos).ok() {
+                        let breakpoint = &mut debugger.breakpoints[ip + 1];
+                        match (&mut *breakpoint, condition) {
+                            (Breakpoint::Always, _) => {}
+                            (Breakpoint::When(conditions), Some(condition)) => conditions.push(condition),
+                            (_, Some(condition)) => *breakpoint = Breakpoint::When(vec![condition]),
+                            (_, None) => *breakpoint = Breakpoint::Always,
+                        }
+                        found = Some(ip);
+                    }
+                }
+                let ip = found.ok_or_else(|| fail(format!("no loop at byte {} is left after optimizing", pos)))?;
+                let place = prog.source_map().describe_loop(ip).unwrap();
+                match condition {
+                    Some((cell, value)) => println!("Breakpoint at {} when cell {} is {}", place, cell, value),
+                    None => println!("Breakpoint at {}", place),
+                }
+            }
+            "break-output" => {
+                debugger.output_match = Some(OutputMatch::new(operand.as_bytes()));
+                println!("Stopping when the output ends with {:?}", operand);
+            }
+            "state" => {
+                println!("{} instructions executed, {}", debugger.steps, at(&snapshot));
+                for line in cells_around(prog, &snapshot.tape, snapshot.dp, format) {
+                    println!("{}", line);
+                }
+            }
+            "cell" => {
+                let pos = number()?;
+                if pos >= snapshot.tape.len() {
+                    return Err(fail(format!("cell {} is past the end of the tape", pos)));
+                }
+                println!(
+                    "cell {}: {}",
+                    pos,
+                    format.render(snapshot.tape.get(pos), snapshot.tape.cell().bytes())
+                );
+            }
+            "save" => {
+                saved.insert(operand.to_string(), snapshot.to_bytes(prog));
+                println!("Saved the state as {}", operand);
+            }
+            "diff" => {
+                let old = saved
+                    .get(operand)
+                    .ok_or_else(|| fail(format!("no state saved as {:?}", operand)))?;
+                let diff = snapshot::diff(old, &snapshot.to_bytes(prog)).map_err(fail)?;
+                println!(
+                    "ip {} -> {}, dp {} -> {}",
+                    diff.old.0, diff.new.0, diff.old.1, diff.new.1
+                );
+                for change in &diff.cells {
+                    println!(
+                        "cell {}: {} -> {}",
+                        change.pos,
+                        format.render(change.old, diff.width),
+                        format.render(change.new, diff.width)
+                    );
+                }
+            }
+            "quit" => break,
+            _ => return Err(fail(format!("unknown command {:?}", line))),
+        }
+    }
+    Ok(())
+}
+
+/// Parses the `CELL == VALUE` of a conditional breakpoint.
+fn parse_condition(text: &str) -> Result<(usize, u32), String> {
+    let (cell, value) = text.split_once("==").ok_or("expected CELL == VALUE")?;
+    let value = parse_number(value.trim())?;
+    let value = u32::try_from(value).map_err(|_| format!("{}: too large for a cell", value))?;
+    Ok((parse_number(cell.trim())?, value))
+}
+
+/// [`run_program`] writing to stdout, flushed as requested.
+fn run_to_stdout<R: ByteRead>(engine: &Bropt, prog: &Program, args: &Args, input: &mut R) -> Result<Ended, String> {
+    run_formatted(engine, prog, args, input, Stdout::new(flush_policy(args)))
+}
+
+/// The `--flush` policy, or the default for where stdout goes.
+fn flush_policy(args: &Args) -> FlushPolicy {
+    match args.flush {
+        Some(policy) => policy.unwrap_or(FlushPolicy::PerByte),
+        None if args.raw_input => FlushPolicy::PerByte,
+        None if std::io::stdout().is_terminal() => FlushPolicy::PerLine,
+        None => FlushPolicy::Never,
+    }
+}
+
+/// [`run_program`] writing to `output` in the chosen `--output-format`.
+fn run_formatted<R: ByteRead, W: ByteWrite>(
+    engine: &Bropt,
+    prog: &Program,
+    args: &Args,
+    input: &mut R,
+    output: W,
+) -> Result<Ended, String> {
+    match args.output_format {
+        OutputArg::Raw => {
+            let mut output = output;
+            run_program(engine, prog, args, input, &mut output)
+        }
+        OutputArg::Utf8 => {
+            let mut output = Utf8Lossy::new(output);
+            let result = run_program(engine, prog, args, input, &mut output);
+            output.finish();
+            result
+        }
+        OutputArg::Hex => {
+            let mut output = HexDump::new(output);
+            let result = run_program(engine, prog, args, input, &mut output);
+            output.finish();
+            result
+        }
+        OutputArg::Escaped => {
+            let mut output = Escaped::new(output);
+            let result = run_program(engine, prog, args, input, &mut output);
+            output.finish();
+            result
+        }
+    }
+}
+
+/// The `--json` result of a run that took `elapsed`. `steps` is null when the run failed, and
+/// `tape_digest` is added on request.
+fn json_result(
+    result: &Result<Ended, String>,
+    output: &SplitOutput,
+    elapsed: Duration,
+    digest: bool,
+) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "exit": match result {
+            Ok(Ended { interrupted: true, .. }) => "interrupted",
+            Ok(_) => "finished",
+            Err(_) => "error",
+        },
+        "output": base64(&output.out),
+        "stderr": base64(&output.err),
+        "steps": result.as_ref().ok().and_then(|ended| ended.steps),
+        "seconds": elapsed.as_secs_f64(),
+    });
+    match result {
+        Ok(ended) if digest => json["tape_digest"] = format!("{:016x}", ended.tape.digest()).into(),
+        Ok(_) => {}
+        Err(err) => json["error"] = err.as_str().into(),
+    }
+    json
+}
+
+/// Encodes `bytes` as base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (idx, &byte)| group | (byte as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            text.push(if idx <= chunk.len() {
+                ALPHABET[(group >> (18 - 6 * idx) & 63) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    text
+}
+
+/// Input fed to `file` by `bropt compare`: the file next to it ending in .in, if there is one.
+fn compare_input(file: &str) -> Option<String> {
+    let input = std::path::Path::new(file).with_extension("in");
+    input.is_file().then(|| input.to_string_lossy().into_owned())
+}
+
+/// One interpreter's result on one program in `bropt compare`: its output and fastest time, or
+/// why it has none.
+type Timed = Result<(Vec<u8>, Duration), String>;
+
+/// Runs the program `load` compiles in this process `runs` times with the engine's settings,
+/// timing compiling and running together as an external interpreter's time includes both.
+/// `warmup` adds a first run that is not timed.
+fn time_bropt(
+    engine: &Bropt,
+    load: impl Fn() -> Result<Program, String>,
+    input: &[u8],
+    runs: u32,
+    warmup: bool,
+) -> Timed {
+    let (mut output, mut best) = (Vec::new(), Duration::MAX);
+    for run in 0..runs + warmup as u32 {
+        let start = Instant::now();
+        output.clear();
+        let prog = load()?;
+        engine.run(&prog, &mut SliceReader::new(input), &mut output)?;
+        if run > 0 || !warmup {
+            best = best.min(start.elapsed());
+        }
+    }
+    Ok((output, best))
+}
+
+/// Runs `command` with `{file}` replaced by `file` and `input` on stdin `runs` times, and once
+/// more untimed first with `warmup`.
+fn time_command(command: &str, file: &str, input: Option<&str>, runs: u32, warmup: bool) -> Timed {
+    let words: Vec<String> = command
+        .split_whitespace()
+        .map(|word| word.replace("{file}", file))
+        .collect();
+    let (program, rest) = words.split_first().ok_or("empty command")?;
+    let (mut output, mut best) = (Vec::new(), Duration::MAX);
+    for run in 0..runs + warmup as u32 {
+        let stdin = match input {
+            Some(path) => std::fs::File::open(path)
+                .map_err(|err| format!("{}: {}", path, err))?
+                .into(),
+            None => std::process::Stdio::null(),
+        };
+        let start = Instant::now();
+        let result = std::process::Command::new(program)
+            .args(rest)
+            .stdin(stdin)
+            .output()
+            .map_err(|err| format!("{}: {}", program, err))?;
+        if run > 0 || !warmup {
+            best = best.min(start.elapsed());
+        }
+        if !result.status.success() {
+            return Err(format!("{}", result.status));
+        }
+        output = result.stdout;
+    }
+    Ok((output, best))
+}
+
+/// Runs every file through bropt and each command in `against`, then prints a table of the times,
+/// each relative to bropt's, with a note where an interpreter failed or printed something else.
+/// Returns whether every interpreter printed what bropt did. `warmup` runs each program once on
+/// each interpreter before timing it.
+fn compare(engine: &Bropt, against: &[String], runs: u32, warmup: bool, files: &[String]) -> bool {
+    let mut header = vec![String::from("program"), String::from("bropt")];
+    // Each command is labeled by its program's name and the options it passes, without the path.
+    header.extend(against.iter().map(|command| {
+        let mut words = command.split_whitespace().filter(|word| !word.contains("{file}"));
+        let program = words.next().unwrap_or_default();
+        let program = program.rsplit('/').next().unwrap_or(program);
+        core::iter::once(program).chain(words).collect::<Vec<_>>().join(" ")
+    }));
+    let mut rows = vec![header];
+    let mut same = true;
+    for file in files {
+        let input_path = compare_input(file);
+        let input = match &input_path {
+            Some(path) => std::fs::read(path).expect("Failed to read the input."),
+            None => Vec::new(),
+        };
+        let mut row = vec![file.clone()];
+        let bropt = time_bropt(engine, || load_program(engine, file), &input, runs, warmup);
+        row.push(match &bropt {
+            Ok((_, time)) => format!("{:.3}s", time.as_secs_f64()),
+            Err(err) => {
+                log::error!("{}: {}", file, err);
+                same = false;
+                String::from("failed")
+            }
+        });
+        for command in against {
+            let other = time_command(command, file, input_path.as_deref(), runs, warmup);
+            row.push(match (&bropt, other) {
+                (_, Err(err)) => {
+                    same = false;
+                    format!("failed: {}", err)
+                }
+                (Ok((expected, base)), Ok((output, time))) if output == *expected => format!(
+                    "{:.3}s ({:.2}x)",
+                    time.as_secs_f64(),
+                    time.as_secs_f64() / base.as_secs_f64()
+                ),
+                (Ok((expected, _)), Ok((output, _))) => {
+                    same = false;
+                    format!("output differs at byte {}", diverge(expected, &output))
+                }
+                (Err(_), Ok((_, time))) => format!("{:.3}s", time.as_secs_f64()),
+            });
+        }
+        rows.push(row);
+    }
+    print_table(rows);
+    same
+}
+
+/// Runs the corpus programs called `names`, or all of them, `runs` times each and prints a table
+/// of their fastest times, with a note where one failed or printed something other than it should.
+/// Returns whether every program printed its expected output.
+fn bench(engine: &Bropt, runs: u32, warmup: bool, names: &[String]) -> Result<bool, String> {
+    let entries = if names.is_empty() {
+        CORPUS.iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| corpus::get(name).ok_or_else(|| format!("no program called {} in the corpus", name)))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    let mut rows = vec![vec![
+        String::from("program"),
+        String::from("time"),
+        String::from("output"),
+    ]];
+    let mut same = true;
+    for entry in entries {
+        let mut row = vec![String::from(entry.name)];
+        match time_bropt(engine, || engine.compile(entry.code), entry.input, runs, warmup) {
+            Ok((output, time)) => {
+                row.push(format!("{:.3}s", time.as_secs_f64()));
+                if output == entry.output {
+                    row.push(String::from("ok"));
+                } else {
+                    same = false;
+                    row.push(format!("differs at byte {}", diverge(entry.output, &output)));
+                }
+            }
+            Err(err) => {
+                same = false;
+                row.push(String::from("failed"));
+                row.push(err);
+            }
+        }
+        rows.push(row);
+    }
+    print_table(rows);
+    Ok(same)
+}
+
+/// Length of the prefix `expected` and `output` share.
+fn diverge(expected: &[u8], output: &[u8]) -> usize {
+    expected.iter().zip(output).take_while(|(a, b)| a == b).count()
+}
+
+/// Prints `rows` as columns padded to their widest cell.
+fn print_table(rows: Vec<Vec<String>>) {
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap())
+        .collect();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:width$}", cell))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/// Restricts this process to CPU `cpu`. Processes it starts inherit the restriction.
+#[cfg(target_os = "linux")]
+fn pin_cpu(cpu: usize) -> Result<(), String> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(format!(
+            "cannot pin to CPU {}: the highest CPU is {}",
+            cpu,
+            libc::CPU_SETSIZE - 1
+        ));
+    }
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+    if unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EINVAL) {
+            return Err(format!(
+                "cannot pin to CPU {}: it is not one this process may run on",
+                cpu
+            ));
+        }
+        return Err(format!("cannot pin to CPU {}: {}", cpu, err));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_cpu(_cpu: usize) -> Result<(), String> {
+    Err("pinning to a CPU is only supported on Linux".into())
+}
+
+/// The CPU this thread is running on, for bench mode to pin to.
+#[cfg(target_os = "linux")]
+fn current_cpu() -> Option<usize> {
+    usize::try_from(unsafe { libc::sched_getcpu() }).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_cpu() -> Option<usize> {
+    None
+}
+
+/// Marks an executable written by `bropt bundle`, at its very end.
+const BUNDLE_MAGIC: &[u8; 8] = b"BROPTBND";
+
+/// Bytes after the program in a bundle: the tape length, the end-of-input value, the length of
+/// the program and [`BUNDLE_MAGIC`].
+const BUNDLE_TRAILER: usize = 8 + 1 + 8 + BUNDLE_MAGIC.len();
+
+/// Writes a copy of this executable to `output` with `prog` and the tape length and end-of-input
+/// value it runs with appended, so the copy runs `prog` instead of reading its arguments.
+fn write_bundle(prog: &Program, length: usize, eof: Eof, output: &str) -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|err| format!("cannot find the bropt executable: {}", err))?;
+    let mut bytes = std::fs::read(&exe).map_err(|err| format!("{}: {}", exe.display(), err))?;
+    let code = prog.to_bytes();
+    let code_len = code.len() as u64;
+    bytes.extend(code);
+    bytes.extend_from_slice(&(length as u64).to_le_bytes());
+    bytes.push(eof as u8);
+    bytes.extend_from_slice(&code_len.to_le_bytes());
+    bytes.extend_from_slice(BUNDLE_MAGIC);
+    std::fs::write(output, bytes).map_err(|err| format!("{}: {}", output, err))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(output, std::fs::Permissions::from_mode(0o755))
+            .map_err(|err| format!("{}: {}", output, err))?;
+    }
+    Ok(())
+}
+
+/// The program, tape length and end-of-input value `bropt bundle` appended to this executable,
+/// or `None` if it is not a bundle.
+fn bundled_program() -> Option<Result<(Program, usize, Eof), String>> {
+    let mut file = std::fs::File::open(std::env::current_exe().ok()?).ok()?;
+    let end = file.seek(SeekFrom::End(-(BUNDLE_TRAILER as i64))).ok()?;
+    let mut trailer = [0; BUNDLE_TRAILER];
+    file.read_exact(&mut trailer).ok()?;
+    if &trailer[17..] != BUNDLE_MAGIC {
+        return None;
+    }
+    let length = u64::from_le_bytes(trailer[..8].try_into().unwrap()) as usize;
+    let eof = match trailer[8] {
+        0 => Eof::Zero,
+        1 => Eof::MinusOne,
+        _ => Eof::Unchanged,
+    };
+    let code_len = u64::from_le_bytes(trailer[9..17].try_into().unwrap());
+    let mut code = Vec::new();
+    let read = match end.checked_sub(code_len) {
+        Some(start) => file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| file.take(code_len).read_to_end(&mut code)),
+        None => return Some(Err("the bundled program is truncated".to_string())),
+    };
+    Some(
+        read.map_err(|err| err.to_string())
+            .and_then(|_| Program::from_bytes(&code))
+            .map(|prog| (prog, length, eof)),
+    )
+}
+
+/// Runs the program of a bundle with its arguments fed to `,` and exits.
+fn run_bundle(prog: Program, length: usize, eof: Eof) -> ! {
+    let tape = match DEFAULT_TAPE {
+        // Longer tapes, such as that of --conformance, are only ever sparse.
+        _ if length > 1 << 32 => TapeKind::Sparse,
+        TapeArg::Dense => TapeKind::Dense,
+        TapeArg::Sparse => TapeKind::Sparse,
+        #[cfg(unix)]
+        TapeArg::Mapped => TapeKind::Mapped,
+        #[cfg(unix)]
+        TapeArg::Hugepages => TapeKind::HugePages,
+    };
+    let engine = Bropt::builder()
+        .tape_len(length)
+        .cell(prog.cell())
+        .eof(eof)
+        .tape(tape)
+        .extensions(prog.extensions())
+        .seed(Seed::from_clock())
+        .build();
+    let mut prefix = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        prefix.extend_from_slice(arg.as_encoded_bytes());
+        prefix.push(b'\n');
+    }
+    let mut input = PrefixedInput {
+        prefix,
+        pos: 0,
+        rest: Stdin,
+    };
+    let flush = if std::io::stdout().is_terminal() {
+        FlushPolicy::PerLine
+    } else {
+        FlushPolicy::Never
+    };
+    let result = engine.run(&prog, &mut input, &mut Stdout::new(flush));
+    let _ = std::io::stdout().flush();
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+/// Writes a Cargo project to `dir` that runs `prog`, the optimized tree of `file`, translated to
+/// Rust with the engine's cell width and end-of-input value on a tape of `length` cells.
+fn write_scaffold(engine: &Bropt, prog: &[BaseInst], file: &str, length: usize, dir: &str) -> Result<(), String> {
+    let dir = std::path::Path::new(dir);
+    if dir.join("Cargo.toml").exists() {
+        return Err(format!("{} already holds a Cargo project", dir.display()));
+    }
+    // Package names are letters, digits, - and _, starting with a letter.
+    let stem = std::path::Path::new(file)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let mut name: String = stem
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' {
+                ch.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !name.starts_with(|ch: char| ch.is_ascii_alphabetic()) {
+        name.insert_str(0, "bf-");
+    }
+    let manifest = format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n[dependencies]\n",
+        name
+    );
+    let main = format!(
+        "//! Generated by `bropt scaffold` from {} at -O{}.\n\n{}",
+        file,
+        engine.opt_level(),
+        gen_rust(prog, engine.cell(), engine.eof(), length)
+    );
+    let write = |path: std::path::PathBuf, contents: &str| {
+        std::fs::write(&path, contents).map_err(|err| format!("{}: {}", path.display(), err))
+    };
+    std::fs::create_dir_all(dir.join("src")).map_err(|err| format!("{}: {}", dir.display(), err))?;
+    write(dir.join("Cargo.toml"), &manifest)?;
+    write(dir.join(".gitignore"), "/target\n")?;
+    write(dir.join("src").join("main.rs"), &main)
+}
+
+/// Parses a decimal number, or a hex one with `0x`.
+fn parse_number(text: &str) -> Result<usize, String> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|err| format!("{}: {}", text, err))
+}
+
+/// Parses `START..END`, where either end may be left out.
+fn parse_range(text: &str) -> Result<Range<usize>, String> {
+    let (start, end) = text.split_once("..").ok_or("expected START..END")?;
+    let start = if start.is_empty() { 0 } else { parse_number(start)? };
+    let end = if end.is_empty() { usize::MAX } else { parse_number(end)? };
+    Ok(start..end)
+}
+
+/// Parses a `--flush` policy: `never`, `byte`, `line`, `input` or a number of bytes.
+fn parse_flush(text: &str) -> Result<FlushPolicy, String> {
+    match text {
+        "never" => Ok(FlushPolicy::Never),
+        "byte" => Ok(FlushPolicy::PerByte),
+        "line" => Ok(FlushPolicy::PerLine),
+        "input" => Ok(FlushPolicy::OnInput),
+        _ => match parse_number(text) {
+            Ok(0) => Err("flushing every 0 bytes is not a policy; use never".into()),
+            Ok(bytes) => Ok(FlushPolicy::EveryNBytes(bytes)),
+            Err(_) => Err(format!(
+                "{}: expected never, byte, line, input or a number of bytes",
+                text
+            )),
+        },
+    }
+}
+
+/// The file and cell given to `--tape-init`.
+#[derive(Clone, Debug)]
+struct TapeInit {
+    path: String,
+    offset: usize,
+}
+
+/// Parses `FILE[:OFFSET]`. A colon not followed by a number is part of the file name.
+fn parse_tape_init(text: &str) -> Result<TapeInit, String> {
+    let (path, offset) = match text.rsplit_once(':') {
+        Some((path, offset)) if parse_number(offset).is_ok() => (path, parse_number(offset)?),
+        _ => (text, 0),
+    };
+    Ok(TapeInit {
+        path: path.to_string(),
+        offset,
+    })
+}
+
+/// Cells dumped by `--dump-tape` without a range, unless the pointer is further along.
+const DEFAULT_DUMP_CELLS: usize = 256;
+
+/// Bytes of cells shown on each row of a tape dump, as `hexdump -C` does.
+const DUMP_ROW_BYTES: usize = 16;
+
+/// Prints `range` of the tape to stderr as offset, cell and ASCII columns, collapsing repeated
+/// rows into `*` like `hexdump -C`, followed by the pointer. The cells are in hex unless
+/// `format` says otherwise.
+fn dump_tape(tape: &Tape, dp: usize, range: Option<Range<usize>>, format: Option<CellFormat>) {
+    let bytes = tape.cell().bytes();
+    let columns = format.unwrap_or(CellFormat::Hex);
+    let digits = columns.width(bytes);
+    let per_row = DUMP_ROW_BYTES / tape.cell().bytes();
+    let range = range.unwrap_or(0..(dp + 1).max(DEFAULT_DUMP_CELLS).next_multiple_of(per_row));
+    let end = range.end.min(tape.len());
+    let mut previous = None;
+    let mut collapsed = false;
+    for start in (range.start.min(end)..end).step_by(per_row) {
+        let row: Vec<u32> = (start..(start + per_row).min(end)).map(|pos| tape.get(pos)).collect();
+        if previous.as_ref() == Some(&row) {
+            if !collapsed {
+                eprintln!("*");
+                collapsed = true;
+            }
+            continue;
+        }
+        let cells: Vec<String> = row
+            .iter()
+            .map(|&value| format!("{:>digits$}", columns.render(value, bytes)))
+            .collect();
+        let ascii: String = row
+            .iter()
+            .map(|&value| match char::from_u32(value) {
+                Some(ch) if ch.is_ascii_graphic() || ch == ' ' => ch,
+                _ => '.',
+            })
+            .collect();
+        eprintln!(
+            "{:08x}  {:width$}  |{}|",
+            start,
+            cells.join(" "),
+            ascii,
+            width = per_row * (digits + 1) - 1
+        );
+        previous = Some(row);
+        collapsed = false;
+    }
+    eprintln!("{:08x}", end);
+    if dp < tape.len() {
+        let cell = format.unwrap_or(CellFormat::Dec).render(tape.get(dp), bytes);
+        eprintln!("dp {} ({:#x}), cell {}", dp, dp, cell);
+    }
+}
+
+/// Copies every byte read from the inner reader to stderr for `--echo-input`.
+struct EchoInput<R>(R);
+
+impl<R: ByteRead> ByteRead for EchoInput<R> {
+    #[inline]
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.0.read_byte();
+        if let Some(byte) = byte {
+            let _ = std::io::stderr().write_all(&[byte]);
+        }
+        byte
+    }
+}
+
+/// Reads `prefix` before falling back to the inner reader, for the input given on the command line.
+struct PrefixedInput<R> {
+    prefix: Vec<u8>,
+    pos: usize,
+    rest: R,
+}
+
+impl<R: ByteRead> ByteRead for PrefixedInput<R> {
+    #[inline]
+    fn read_byte(&mut self) -> Option<u8> {
+        match self.prefix.get(self.pos) {
+            Some(&byte) => {
+                self.pos += 1;
+                Some(byte)
+            }
+            None => self.rest.read_byte(),
+        }
+    }
+}
+
+/// The input from `--input-string`, `--input-env` and the arguments after `--`, in that order.
+fn input_prefix(args: &Args) -> Result<Vec<u8>, String> {
+    let mut prefix = Vec::new();
+    if let Some(text) = &args.input_string {
+        prefix.extend_from_slice(text.as_bytes());
+    }
+    if let Some(var) = &args.input_env {
+        let value = std::env::var_os(var).ok_or_else(|| format!("environment variable {} is not set", var))?;
+        prefix.extend(value.into_encoded_bytes());
+    }
+    for arg in &args.program_args {
+        prefix.extend_from_slice(arg.as_encoded_bytes());
+        prefix.push(b'\n');
+    }
+    Ok(prefix)
+}
+
+/// Compiles `file` as brainfuck, as bfasm if it ends in .bfasm or as JSON tree IR if it ends in
+/// .json, or loads it if it ends in .bop.
+fn load_program(engine: &Bropt, file: &str) -> Result<Program, String> {
+    if file.ends_with(".bop") {
+        let bytes = std::fs::read(file).expect("Failed to read the file.");
+        return Program::from_bytes(&bytes);
+    }
+    if file.ends_with(".json") {
+        return engine.compile_ir(load_tree(engine, file)?);
+    }
+    let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+    if file.ends_with(".bfasm") {
+        engine.compile_asm(&code)
+    } else {
+        engine.compile(&code)
+    }
+}
+
+/// Parses `file` into the tree IR without optimizing it, reading it the way [`load_program`] does.
+fn load_tree(engine: &Bropt, file: &str) -> Result<Vec<BaseInst>, String> {
+    let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+    if file.ends_with(".json") {
+        serde_json::from_str(&code).map_err(|err| err.to_string())
+    } else if file.ends_with(".bfasm") {
+        assemble(&code)
+    } else {
+        try_parse_with(&code, engine.extensions())
+    }
+}
+
+fn main() {
+    match bundled_program() {
+        Some(Ok((prog, length, eof))) => run_bundle(prog, length, eof),
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        None => {}
+    }
+    let args = Args::parse();
+    Diagnostics::install(&args);
+    let bench_mode = matches!(
+        args.command,
+        Some(Command::Compare { bench_mode: true, .. } | Command::Bench { bench_mode: true, .. })
+    );
+    if let Some(cpu) = args.pin_cpu {
+        if let Err(err) = pin_cpu(cpu) {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+    } else if bench_mode && let Err(err) = current_cpu().map_or(Err(String::from("no CPU to pin to")), pin_cpu) {
+        log::warn!("Running unpinned: {}", err);
+    }
+    if let Some(Command::GenText { text }) = &args.command {
+        println!("{}", gen_text(text.as_bytes()));
+        return;
+    }
+    if let Some(Command::Completions { shell }) = &args.command {
+        clap_complete::generate(*shell, &mut Args::command(), "bropt", &mut std::io::stdout());
+        return;
+    }
+    if let Some(Command::Asm { file }) = &args.command {
+        let source = std::fs::read_to_string(file).expect("Failed to read the file.");
+        let code = assemble(&source)
+            .and_then(|prog| decompile(&prog))
+            .unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+        println!("{}", code);
+        return;
+    }
+    if let Some(Command::Obfuscate { file }) = &args.command {
+        // Obfuscating stays reproducible without --seed, as scripts relying on it expect.
+        let seed = Seed::new(args.seed.unwrap_or(0));
+        let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+        let code = obfuscate(&code, seed).unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        });
+        println!("{}", code);
+        return;
+    }
+    let seed = args.seed.map_or_else(Seed::from_clock, Seed::new);
+    let tape = match args.tape {
+        TapeArg::Dense => TapeKind::Dense,
+        TapeArg::Sparse => TapeKind::Sparse,
+        #[cfg(unix)]
+        TapeArg::Mapped => TapeKind::Mapped,
+        #[cfg(unix)]
+        TapeArg::Hugepages => TapeKind::HugePages,
+    };
+    let cell = match args.cell {
+        CellArg::U8 => CellWidth::U8,
+        CellArg::U16 => CellWidth::U16,
+        CellArg::U32 => CellWidth::U32,
+    };
+    let length = args.length.unwrap_or_else(|| {
+        // A pre-faulted tape costs its full size, so bench mode keeps it small.
+        let length = match tape {
+            #[cfg(unix)]
+            TapeKind::Mapped | TapeKind::HugePages if !bench_mode => 1 << 32,
+            _ => 65536,
+        };
+        args.max_memory.map_or(length, |bytes| length.min(bytes / cell.bytes()))
+    });
+    let mut builder = Bropt::builder().tape_len(length).cell(cell).tape(tape);
+    if let Some(bytes) = args.max_memory {
+        builder = builder.max_memory(bytes);
+    }
+    if let Some(fuel) = args.opt_fuel {
+        builder = builder.opt_fuel(fuel);
+    }
+    if args.conformance {
+        builder = builder.conformance();
+    }
+    if let Some(eof) = args.eof {
+        builder = builder.eof(match eof {
+            EofArg::Zero => Eof::Zero,
+            EofArg::MinusOne => Eof::MinusOne,
+            EofArg::Unchanged => Eof::Unchanged,
+        });
+    }
+    let engine = builder
+        .prefault(bench_mode)
+        .opt_level(args.opt_level)
+        .safe(!args.unchecked)
+        .preloaded(args.tape_init.is_some() || matches!(args.command, Some(Command::Build { linkable: true, .. })))
+        .extensions(Extensions {
+            rand: args.ext.contains(&ExtArg::Rand),
+            stderr: args.ext.contains(&ExtArg::Stderr),
+        })
+        .seed(seed)
+        .build();
+    match &args.command {
+        Some(Command::Report { file, output }) => {
+            let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+            let html = engine.report(&code).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            std::fs::write(output, html).expect("Failed to write the report.");
+            return;
+        }
+        Some(Command::Outline { file }) => {
+            let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+            let text = engine.outline(&code).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            print!("{}", text);
+            return;
+        }
+        Some(Command::Analyze { file }) => {
+            let prog = load_program(&engine, file).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            print!("{}", engine.analyze(&prog));
+            return;
+        }
+        Some(Command::Graph { file }) => {
+            let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+            let dot = engine.graph(&code).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            print!("{}", dot);
+            return;
+        }
+        Some(Command::Verify { corpus: true, .. }) => {
+            let mut same = true;
+            for entry in CORPUS {
+                let mut output = Vec::new();
+                let result = engine
+                    .compile(entry.code)
+                    .and_then(|prog| engine.run(&prog, &mut SliceReader::new(entry.input), &mut output));
+                if let Err(err) = result {
+                    log::error!("{}: {}", entry.name, err);
+                    same = false;
+                } else if output != entry.output {
+                    log::error!(
+                        "{}: output differs at byte {} ({} bytes expected, {} written)",
+                        entry.name,
+                        diverge(entry.output, &output),
+                        entry.output.len(),
+                        output.len()
+                    );
+                    same = false;
+                }
+            }
+            if !same {
+                std::process::exit(1);
+            }
+            println!("All {} corpus programs wrote their expected output.", CORPUS.len());
+            return;
+        }
+        Some(Command::Verify {
+            original, other, input, ..
+        }) => {
+            let input = input
+                .as_ref()
+                .map(|path| std::fs::read(path).expect("Failed to read the input."))
+                .unwrap_or_default();
+            // Without --corpus, clap requires both files.
+            let outputs: Vec<Vec<u8>> = [original, other]
+                .iter()
+                .filter_map(|file| file.as_ref())
+                .map(|file| {
+                    let code = std::fs::read_to_string(file).expect("Failed to read the file.");
+                    let mut output = Vec::new();
+                    let result = engine
+                        .compile(&code)
+                        .and_then(|prog| engine.run(&prog, &mut SliceReader::new(&input), &mut output));
+                    if let Err(err) = result {
+                        log::error!("{}: {}", file, err);
+                        std::process::exit(1);
+                    }
+                    output
+                })
+                .collect();
+            if outputs[0] == outputs[1] {
+                println!("Both programs wrote the same {} bytes.", outputs[0].len());
+                return;
+            }
+            eprintln!(
+                "Outputs differ at byte {} ({} and {} bytes written).",
+                diverge(&outputs[0], &outputs[1]),
+                outputs[0].len(),
+                outputs[1].len()
+            );
+            std::process::exit(1);
+        }
+        Some(Command::Diff { original, other }) => {
+            let trees: Vec<Vec<BaseInst>> = [original, other]
+                .iter()
+                .map(|file| {
+                    load_tree(&engine, file)
+                        .and_then(|prog| engine.optimize(prog))
+                        .unwrap_or_else(|err| {
+                            log::error!("{}: {}", file, err);
+                            std::process::exit(1);
+                        })
+                })
+                .collect();
+            match diff_ir(&trees[0], &trees[1], original, other) {
+                Some(diff) => {
+                    print!("{}", diff);
+                    std::process::exit(1);
+                }
+                None => println!("Both programs optimize to the same IR."),
+            }
+            return;
+        }
+        Some(Command::TraceDiff {
+            original,
+            other,
+            context,
+        }) => {
+            let open = |path: &String| {
+                std::fs::File::open(path)
+                    .map(std::io::BufReader::new)
+                    .unwrap_or_else(|err| {
+                        log::error!("{}: {}", path, err);
+                        std::process::exit(1);
+                    })
+            };
+            match diff_traces(open(original), open(other), *context) {
+                Ok(Some(divergence)) => {
+                    print!("--- {}\n+++ {}\n{}", original, other, divergence);
+                    std::process::exit(1);
+                }
+                Ok(None) => println!("Both traces are the same."),
+                Err(err) => {
+                    log::error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::TapeDiff { original, other }) => {
+            let read = |path: &String| {
+                std::fs::read(path).unwrap_or_else(|err| {
+                    log::error!("{}: {}", path, err);
+                    std::process::exit(1);
+                })
+            };
+            let diff = snapshot::diff(&read(original), &read(other)).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            if diff.old == diff.new && diff.cells.is_empty() {
+                println!("Both snapshots are the same.");
+                return;
+            }
+            let format = args.cell_format.unwrap_or(CellFormat::Dec);
+            println!("--- {}\n+++ {}", original, other);
+            println!(
+                "ip {} -> {}, dp {} -> {}",
+                diff.old.0, diff.new.0, diff.old.1, diff.new.1
+            );
+            for change in &diff.cells {
+                println!(
+                    "cell {}: {} -> {}",
+                    change.pos,
+                    format.render(change.old, diff.width),
+                    format.render(change.new, diff.width)
+                );
+            }
+            std::process::exit(1);
+        }
+        Some(Command::Debug { file, script }) => {
+            let prog = load_program(&engine, file).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            let commands = std::fs::read_to_string(script).unwrap_or_else(|err| {
+                log::error!("{}: {}", script, err);
+                std::process::exit(1);
+            });
+            if let Err(err) = debug(
+                &engine,
+                &prog,
+                script,
+                &commands,
+                args.cell_format.unwrap_or(CellFormat::Dec),
+            ) {
+                log::error!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Build { file, output, .. }) => {
+            let prog = load_program(&engine, file).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            std::fs::write(output, prog.to_bytes()).expect("Failed to write the program.");
+            return;
+        }
+        Some(Command::Bundle { file, output }) => {
+            let prog = load_program(&engine, file).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            if let Err(err) = write_bundle(&prog, engine.tape_len(), engine.eof(), output) {
+                log::error!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Scaffold { file, output }) => {
+            // A generated program allocates its whole tape, so it does not take a mapped tape's length.
+            let length = args.length.unwrap_or(65536);
+            let result = load_tree(&engine, file)
+                .and_then(|prog| engine.optimize(prog))
+                .and_then(|prog| write_scaffold(&engine, &prog, file, length, output));
+            if let Err(err) = result {
+                log::error!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Link { files, output }) => {
+            let progs: Vec<Program> = files
+                .iter()
+                .map(|file| {
+                    let bytes = std::fs::read(file).expect("Failed to read the file.");
+                    Program::from_bytes(&bytes).unwrap_or_else(|err| {
+                        log::error!("{}: {}", file, err);
+                        std::process::exit(1);
+                    })
+                })
+                .collect();
+            let prog = Program::concat(&progs).unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            });
+            std::fs::write(output, prog.to_bytes()).expect("Failed to write the program.");
+            return;
+        }
+        Some(Command::Compare {
+            against,
+            runs,
+            bench_mode,
+            files,
+        }) => {
+            if !compare(&engine, against, *runs, *bench_mode, files) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Bench {
+            runs,
+            bench_mode,
+            names,
+        }) => {
+            match bench(&engine, *runs, *bench_mode, names) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    log::error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(
+            Command::GenText { .. } | Command::Asm { .. } | Command::Obfuscate { .. } | Command::Completions { .. },
+        )
+        | None => {}
+    }
+    let file = args.file.as_ref().unwrap();
+    if let Some(EmitArg::Json) = args.emit {
+        let json = if file.ends_with(".bop") {
+            load_program(&engine, file).map(|prog| serde_json::to_string(prog.insts()))
+        } else {
+            load_tree(&engine, file)
+                .and_then(|prog| engine.optimize(prog))
+                .map(|prog| serde_json::to_string(&prog))
+        };
+        match json {
+            Ok(json) => println!("{}", json.expect("Failed to serialize the IR.")),
+            Err(err) => {
+                log::error!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let start = Instant::now();
+    take_heap_peak();
+    let prog = load_program(&engine, file).unwrap_or_else(|err| {
+        log::error!("{}", err);
+        std::process::exit(1);
+    });
+    log::info!(
+        "Compiled {} bytes of source to {} instructions at -O{} in {:.2?}, fingerprint {:016x}",
+        prog.source_len(),
+        prog.insts().len(),
+        prog.opt_level(),
+        start.elapsed(),
+        prog.fingerprint()
+    );
+    match args.seed {
+        Some(_) if !prog.extensions().rand => log::warn!("--seed has no effect without --ext rand"),
+        None if prog.extensions().rand => log::info!("Seed {}", seed.value()),
+        _ => {}
+    }
+    let mut input = PrefixedInput {
+        prefix: input_prefix(&args).unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }),
+        pos: 0,
+        rest: Stdin,
+    };
+    install_report_handler();
+    if args.raw_input
+        && let Err(err) = enable_raw_input()
+    {
+        log::error!("{}", err);
+        std::process::exit(1);
+    }
+    let compile_peak = take_heap_peak();
+    let start = Instant::now();
+    let mut output = SplitOutput::default();
+    let result = match (args.json, args.echo_input) {
+        (true, true) => run_program(&engine, &prog, &args, &mut EchoInput(input), &mut output),
+        (true, false) => run_program(&engine, &prog, &args, &mut input, &mut output),
+        (false, true) => run_to_stdout(&engine, &prog, &args, &mut EchoInput(input)),
+        (false, false) => run_to_stdout(&engine, &prog, &args, &mut input),
+    };
+    let elapsed = start.elapsed();
+    let _ = std::io::stdout().flush();
+    if args.mem_stats {
+        let output = args.json.then_some(&output);
+        print_mem_stats(&engine, &prog, output, compile_peak, take_heap_peak());
+    }
+    match &result {
+        Ok(Ended { steps: Some(steps), .. }) => log::info!("Ran {} instructions in {:.2?}", steps, elapsed),
+        Ok(_) => log::info!("Ran in {:.2?}", elapsed),
+        Err(_) => {}
+    }
+    if args.json {
+        println!("{}", json_result(&result, &output, elapsed, args.tape_digest));
+    }
+    match result {
+        Ok(Ended { interrupted: true, .. }) => std::process::exit(130),
+        Ok(Ended { tape, dp, .. }) => {
+            if let Some(range) = &args.dump_tape {
+                let _ = std::io::stdout().flush();
+                dump_tape(&tape, dp, range.clone(), args.cell_format);
+            }
+            if let Some(cell) = args.exit_cell {
+                let cell = cell.unwrap_or(dp);
+                if cell >= tape.len() {
+                    log::error!("--exit-cell {} is outside the {}-cell tape", cell, tape.len());
+                    std::process::exit(1);
+                }
+                let _ = std::io::stdout().flush();
+                std::process::exit((tape.get(cell) & 0xff) as i32);
+            }
+        }
+        Err(err) => {
+            if !args.json {
+                log::error!("{}", err);
+            }
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! `bfasm`, a small language with named cells that compiles to the tree IR.
+//!
+//! A program is a sequence of statements; whitespace and newlines only separate tokens and
+//! `#` starts a comment running to the end of the line.
+//!
+//! ```text
+//! add x, N          x += N
+//! sub x, N          x -= N
+//! set x, N          x = N
+//! clear x           x = 0
+//! move x, y, ...    y += x for each destination, then x = 0
+//! copy x, y, ...    y += x for each destination, keeping x
+//! print x           write x
+//! read x            read into x
+//! while x { ... }   repeat the body while x is nonzero
+//! ```
+//!
+//! Constants are decimal numbers, optionally negative, or character literals such as `'A'`.
+//! Cells are laid out from position 0 in the order their names first appear, followed by one
+//! scratch cell used by `copy`.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::brainfuck::BaseInst;
+
+/// Largest constant magnitude accepted, so a statement expands to a bounded number of increments.
+const MAX_CONST: i32 = 65535;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Ident(String),
+    Number(i32),
+    Comma,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => name.clone(),
+            Token::Number(value) => value.to_string(),
+            Token::Comma => ",".to_string(),
+            Token::Open => "{".to_string(),
+            Token::Close => "}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Add(usize, i32),
+    Set(usize, i32),
+    Move(usize, Vec<usize>),
+    Copy(usize, Vec<usize>),
+    Print(usize),
+    Read(usize),
+    While(usize, Vec<Stmt>),
+}
+
+/// Compiles `source` to the tree IR. Errors name the line they were found on.
+pub fn assemble(source: &str) -> Result<Vec<BaseInst>, String> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        cells: BTreeMap::new(),
+    };
+    let stmts = parser.block(false)?;
+    let mut emitter = Emitter {
+        ptr: 0,
+        scratch: parser.cells.len(),
+    };
+    Ok(emitter.block(&stmts))
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line.split('#').next().unwrap();
+        let mut chars = line.chars().peekable();
+        while let Some(&ch) = chars.peek() {
+            let token = match ch {
+                _ if ch.is_whitespace() => {
+                    chars.next();
+                    continue;
+                }
+                ',' | '{' | '}' => {
+                    chars.next();
+                    match ch {
+                        ',' => Token::Comma,
+                        '{' => Token::Open,
+                        _ => Token::Close,
+                    }
+                }
+                '\'' => {
+                    chars.next();
+                    let (Some(value), Some('\'')) = (chars.next(), chars.next()) else {
+                        return Err(format!("line {line_no}: unterminated character literal"));
+                    };
+                    Token::Number(value as i32)
+                }
+                '-' | '0'..='9' => {
+                    let mut text = String::new();
+                    text.push(ch);
+                    chars.next();
+                    while let Some(&digit) = chars.peek().filter(|ch| ch.is_ascii_digit()) {
+                        text.push(digit);
+                        chars.next();
+                    }
+                    match text.parse::<i32>() {
+                        Ok(value) if value.abs() <= MAX_CONST => Token::Number(value),
+                        _ => return Err(format!("line {line_no}: invalid constant {text}")),
+                    }
+                }
+                _ if ch.is_alphabetic() || ch == '_' => {
+                    let mut name = String::new();
+                    while let Some(&ch) = chars.peek().filter(|ch| ch.is_alphanumeric() || **ch == '_') {
+                        name.push(ch);
+                        chars.next();
+                    }
+                    Token::Ident(name)
+                }
+                _ => return Err(format!("line {line_no}: unexpected character {ch:?}")),
+            };
+            tokens.push((token, line_no));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(Token, usize)],
+    pos: usize,
+    cells: BTreeMap<String, usize>,
+}
+
+impl Parser<'_> {
+    fn block(&mut self, nested: bool) -> Result<Vec<Stmt>, String> {
+        let mut stmts = Vec::new();
+        loop {
+            let (op, line_no) = match self.tokens.get(self.pos) {
+                None if nested => return Err(self.error("missing }")),
+                None => return Ok(stmts),
+                Some((Token::Close, _)) if nested => {
+                    self.pos += 1;
+                    return Ok(stmts);
+                }
+                Some((Token::Ident(op), line_no)) => (op.as_str(), *line_no),
+                Some((token, line_no)) => {
+                    return Err(format!(
+                        "line {line_no}: expected a statement, found {}",
+                        token.describe()
+                    ));
+                }
+            };
+            self.pos += 1;
+            let stmt = match op {
+                "add" | "sub" | "set" => {
+                    let cell = self.cell()?;
+                    self.expect(Token::Comma)?;
+                    let value = self.number()?;
+                    match op {
+                        "add" => Stmt::Add(cell, value),
+                        "sub" => Stmt::Add(cell, -value),
+                        _ => Stmt::Set(cell, value),
+                    }
+                }
+                "clear" => Stmt::Set(self.cell()?, 0),
+                "move" | "copy" => {
+                    let src = self.cell()?;
+                    let mut dsts = Vec::new();
+                    while self.tokens.get(self.pos).map(|(token, _)| token) == Some(&Token::Comma) {
+                        self.pos += 1;
+                        let dst = self.cell()?;
+                        if dst == src {
+                            return Err(format!("line {line_no}: cannot {op} a cell into itself"));
+                        }
+                        dsts.push(dst);
+                    }
+                    if dsts.is_empty() {
+                        return Err(format!("line {line_no}: {op} needs a destination"));
+                    }
+                    if op == "move" {
+                        Stmt::Move(src, dsts)
+                    } else {
+                        Stmt::Copy(src, dsts)
+                    }
+                }
+                "print" => Stmt::Print(self.cell()?),
+                "read" => Stmt::Read(self.cell()?),
+                "while" => {
+                    let cell = self.cell()?;
+                    self.expect(Token::Open)?;
+                    Stmt::While(cell, self.block(true)?)
+                }
+                _ => return Err(format!("line {line_no}: unknown statement {op}")),
+            };
+            stmts.push(stmt);
+        }
+    }
+
+    fn cell(&mut self) -> Result<usize, String> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Ident(name), _)) => {
+                self.pos += 1;
+                let next = self.cells.len();
+                Ok(*self.cells.entry(name.clone()).or_insert(next))
+            }
+            _ => Err(self.error("expected a cell name")),
+        }
+    }
+
+    fn number(&mut self) -> Result<i32, String> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Number(value), _)) => {
+                self.pos += 1;
+                Ok(*value)
+            }
+            _ => Err(self.error("expected a constant")),
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        if self.tokens.get(self.pos).map(|(next, _)| next) == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", token.describe())))
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some((_, line_no)) => format!("line {line_no}: {message}"),
+            None => message.to_string(),
+        }
+    }
+}
+
+struct Emitter {
+    ptr: usize,
+    scratch: usize,
+}
+
+impl Emitter {
+    fn block(&mut self, stmts: &[Stmt]) -> Vec<BaseInst> {
+        let mut prog = Vec::new();
+        for stmt in stmts {
+            match stmt {
+                Stmt::Add(cell, value) => {
+                    self.goto(&mut prog, *cell);
+                    push_add(&mut prog, *value);
+                }
+                Stmt::Set(cell, value) => {
+                    self.goto(&mut prog, *cell);
+                    prog.push(BaseInst::Reset);
+                    push_add(&mut prog, *value);
+                }
+                Stmt::Move(src, dsts) => self.transfer(&mut prog, *src, dsts),
+                Stmt::Copy(src, dsts) => {
+                    let mut targets = dsts.clone();
+                    targets.push(self.scratch);
+                    self.transfer(&mut prog, *src, &targets);
+                    self.transfer(&mut prog, self.scratch, &[*src]);
+                }
+                Stmt::Print(cell) => {
+                    self.goto(&mut prog, *cell);
+                    prog.push(BaseInst::Output);
+                }
+                Stmt::Read(cell) => {
+                    self.goto(&mut prog, *cell);
+                    prog.push(BaseInst::Input);
+                }
+                Stmt::While(cell, body) => {
+                    self.goto(&mut prog, *cell);
+                    let mut inner = self.block(body);
+                    self.goto(&mut inner, *cell);
+                    prog.push(BaseInst::Block(inner, true, None));
+                }
+            }
+        }
+        prog
+    }
+
+    /// Emits `[- >+ >+ ... <]` at `src`, adding it to every cell in `dsts`.
+    fn transfer(&mut self, prog: &mut Vec<BaseInst>, src: usize, dsts: &[usize]) {
+        self.goto(prog, src);
+        let mut body = vec![BaseInst::Inc(u8::MAX)];
+        for &dst in dsts {
+            self.goto(&mut body, dst);
+            body.push(BaseInst::Inc(1));
+        }
+        self.goto(&mut body, src);
+        prog.push(BaseInst::Block(body, true, None));
+    }
+
+    fn goto(&mut self, prog: &mut Vec<BaseInst>, cell: usize) {
+        if cell != self.ptr {
+            prog.push(BaseInst::Shift(cell as i32 - self.ptr as i32));
+            self.ptr = cell;
+        }
+    }
+}
+
+/// Increments are sign-extended bytes, so larger constants are split to stay exact on wide cells.
+fn push_add(prog: &mut Vec<BaseInst>, mut value: i32) {
+    while value != 0 {
+        let step = value.clamp(i8::MIN as i32, i8::MAX as i32);
+        prog.push(BaseInst::Inc(step as i8 as u8));
+        value -= step;
+    }
+}
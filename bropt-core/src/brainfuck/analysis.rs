@@ -0,0 +1,566 @@
+//! Abstract interpretation of the [`BaseInst`] tree, tracking what is known about each cell.
+//!
+//! A [`State`] maps cells, numbered relative to where the walk began, to values of a [`Domain`]:
+//! [`Const`], [`Interval`], [`Parity`], or [`Value`], which combines all three. Passes step a
+//! state through the code they rewrite instead of each tracking cells on their own.
+//!
+//! A stable loop is summarized by an invariant that holds at the start of every iteration: the
+//! state before the loop with every cell the body may write forgotten, joined with what one run
+//! of the body leaves from there. A scan, or a loop that does not return the pointer to where it
+//! started, moves the pointer by an unknown amount, so everything but the zero it stopped on is
+//! forgotten.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::fmt;
+
+use super::ir::{BaseInst, CellWidth};
+
+/// What an analysis knows about the value of one cell. `mask` is the largest value a cell holds,
+/// and values and increments passed in are already reduced to it.
+pub trait Domain: Clone + PartialEq + fmt::Debug {
+    /// Any value.
+    fn top(mask: u32) -> Self;
+
+    fn constant(value: u32) -> Self;
+
+    /// The most precise value covering both.
+    fn join(&self, other: &Self) -> Self;
+
+    /// The value after adding `inc`, wrapping at the cell width.
+    fn add(&self, inc: u32, mask: u32) -> Self;
+
+    /// The value after adding `weight` times `src`, as a `Mul` into this cell does.
+    fn mul_add(&self, src: &Self, weight: u32, mask: u32) -> Self;
+
+    /// The value given that it is nonzero, as inside a loop it controls.
+    fn assume_nonzero(&self) -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    fn is_nonzero(&self) -> bool;
+}
+
+/// A single known value, or none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Const(pub Option<u32>);
+
+impl Domain for Const {
+    fn top(_mask: u32) -> Self {
+        Const(None)
+    }
+
+    fn constant(value: u32) -> Self {
+        Const(Some(value))
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        if self == other { *self } else { Const(None) }
+    }
+
+    fn add(&self, inc: u32, mask: u32) -> Self {
+        Const(self.0.map(|value| value.wrapping_add(inc) & mask))
+    }
+
+    fn mul_add(&self, src: &Self, weight: u32, mask: u32) -> Self {
+        match (self.0, src.0) {
+            (_, Some(0)) => *self,
+            (Some(value), Some(src)) => Const(Some(value.wrapping_add(src.wrapping_mul(weight)) & mask)),
+            _ => Const(None),
+        }
+    }
+
+    fn assume_nonzero(&self) -> Self {
+        *self
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == Some(0)
+    }
+
+    fn is_nonzero(&self) -> bool {
+        self.0.is_some_and(|value| value != 0)
+    }
+}
+
+/// The unsigned values from `low` to `high`, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub low: u32,
+    pub high: u32,
+}
+
+impl Domain for Interval {
+    fn top(mask: u32) -> Self {
+        Interval { low: 0, high: mask }
+    }
+
+    fn constant(value: u32) -> Self {
+        Interval {
+            low: value,
+            high: value,
+        }
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Interval {
+            low: self.low.min(other.low),
+            high: self.high.max(other.high),
+        }
+    }
+
+    fn add(&self, inc: u32, mask: u32) -> Self {
+        let modulus = mask as u64 + 1;
+        let (low, high) = (self.low as u64 + inc as u64, self.high as u64 + inc as u64);
+        if high < modulus {
+            Interval {
+                low: low as u32,
+                high: high as u32,
+            }
+        } else if low >= modulus {
+            Interval {
+                low: (low - modulus) as u32,
+                high: (high - modulus) as u32,
+            }
+        } else {
+            Interval::top(mask)
+        }
+    }
+
+    fn mul_add(&self, src: &Self, weight: u32, mask: u32) -> Self {
+        if src.low == src.high {
+            self.add(src.low.wrapping_mul(weight) & mask, mask)
+        } else {
+            Interval::top(mask)
+        }
+    }
+
+    fn assume_nonzero(&self) -> Self {
+        if self.low == 0 && self.high > 0 {
+            Interval {
+                low: 1,
+                high: self.high,
+            }
+        } else {
+            *self
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.high == 0
+    }
+
+    fn is_nonzero(&self) -> bool {
+        self.low > 0
+    }
+}
+
+/// Whether the value is even or odd. Wrapping at a power of two keeps parity, so this holds on
+/// every cell width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    Even,
+    Odd,
+    Any,
+}
+
+impl Parity {
+    fn of(value: u32) -> Self {
+        if value & 1 == 0 { Parity::Even } else { Parity::Odd }
+    }
+
+    fn flip(self) -> Self {
+        match self {
+            Parity::Even => Parity::Odd,
+            Parity::Odd => Parity::Even,
+            Parity::Any => Parity::Any,
+        }
+    }
+}
+
+impl Domain for Parity {
+    fn top(_mask: u32) -> Self {
+        Parity::Any
+    }
+
+    fn constant(value: u32) -> Self {
+        Parity::of(value)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        if self == other { *self } else { Parity::Any }
+    }
+
+    fn add(&self, inc: u32, _mask: u32) -> Self {
+        if inc & 1 == 0 { *self } else { self.flip() }
+    }
+
+    fn mul_add(&self, src: &Self, weight: u32, _mask: u32) -> Self {
+        match (weight & 1, src) {
+            (0, _) | (_, Parity::Even) => *self,
+            (_, Parity::Odd) => self.flip(),
+            (_, Parity::Any) => Parity::Any,
+        }
+    }
+
+    fn assume_nonzero(&self) -> Self {
+        *self
+    }
+
+    fn is_zero(&self) -> bool {
+        false
+    }
+
+    fn is_nonzero(&self) -> bool {
+        *self == Parity::Odd
+    }
+}
+
+/// The constant, interval and parity of a cell together, each narrowing the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Value {
+    pub constant: Const,
+    pub interval: Interval,
+    pub parity: Parity,
+}
+
+impl Value {
+    /// Shares what each part knows with the others: a constant pins the interval and parity, a
+    /// single-value interval is a constant, and a known parity trims the interval's bounds.
+    fn reduce(mut self) -> Self {
+        if let Some(value) = self.constant.0 {
+            self.interval = Interval::constant(value);
+            self.parity = Parity::of(value);
+            return self;
+        }
+        let Interval { mut low, mut high } = self.interval;
+        if self.parity != Parity::Any && low < high {
+            if Parity::of(low) != self.parity {
+                low += 1;
+            }
+            if Parity::of(high) != self.parity {
+                high -= 1;
+            }
+            if low <= high {
+                self.interval = Interval { low, high };
+            }
+        }
+        if self.interval.low == self.interval.high {
+            self.constant = Const(Some(self.interval.low));
+            self.parity = Parity::of(self.interval.low);
+        }
+        self
+    }
+}
+
+impl Domain for Value {
+    fn top(mask: u32) -> Self {
+        Value {
+            constant: Const::top(mask),
+            interval: Interval::top(mask),
+            parity: Parity::top(mask),
+        }
+    }
+
+    fn constant(value: u32) -> Self {
+        Value {
+            constant: Const::constant(value),
+            interval: Interval::constant(value),
+            parity: Parity::constant(value),
+        }
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Value {
+            constant: self.constant.join(&other.constant),
+            interval: self.interval.join(&other.interval),
+            parity: self.parity.join(&other.parity),
+        }
+        .reduce()
+    }
+
+    fn add(&self, inc: u32, mask: u32) -> Self {
+        Value {
+            constant: self.constant.add(inc, mask),
+            interval: self.interval.add(inc, mask),
+            parity: self.parity.add(inc, mask),
+        }
+        .reduce()
+    }
+
+    fn mul_add(&self, src: &Self, weight: u32, mask: u32) -> Self {
+        Value {
+            constant: self.constant.mul_add(&src.constant, weight, mask),
+            interval: self.interval.mul_add(&src.interval, weight, mask),
+            parity: self.parity.mul_add(&src.parity, weight, mask),
+        }
+        .reduce()
+    }
+
+    fn assume_nonzero(&self) -> Self {
+        Value {
+            constant: self.constant.assume_nonzero(),
+            interval: self.interval.assume_nonzero(),
+            parity: self.parity.assume_nonzero(),
+        }
+        .reduce()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.constant.is_zero() || self.interval.is_zero()
+    }
+
+    fn is_nonzero(&self) -> bool {
+        self.constant.is_nonzero() || self.interval.is_nonzero() || self.parity.is_nonzero()
+    }
+}
+
+/// What is known about every cell at one point of a walk through the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct State<D> {
+    cells: BTreeMap<i32, D>,
+    /// Value of every cell missing from `cells`.
+    rest: D,
+    ptr: i32,
+    mask: u32,
+    /// Whether the pointer moved by an unknown amount since the state was created, so offsets
+    /// no longer line up with the ones before.
+    lost: bool,
+    /// Whether stable loops are summarized only by forgetting what they write, as inside the
+    /// run of a body that narrows an outer loop, so nested loops are not run once per level.
+    shallow: bool,
+}
+
+impl<D: Domain> State<D> {
+    /// The state at the start of a program, where every cell is zero when `zeroed` is set.
+    pub fn new(cell: CellWidth, zeroed: bool) -> Self {
+        let mask = cell.mask();
+        State {
+            cells: BTreeMap::new(),
+            rest: if zeroed { D::constant(0) } else { D::top(mask) },
+            ptr: 0,
+            mask,
+            lost: false,
+            shallow: false,
+        }
+    }
+
+    /// The pointer, relative to where the walk began or last lost track of it.
+    pub fn ptr(&self) -> i32 {
+        self.ptr
+    }
+
+    /// Largest value a cell holds.
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn get(&self, pos: i32) -> D {
+        self.cells.get(&pos).unwrap_or(&self.rest).clone()
+    }
+
+    pub fn current(&self) -> D {
+        self.get(self.ptr)
+    }
+
+    pub fn set(&mut self, pos: i32, value: D) {
+        if value == self.rest {
+            self.cells.remove(&pos);
+        } else {
+            self.cells.insert(pos, value);
+        }
+    }
+
+    /// Forgets what is known about the cell at `pos`.
+    pub fn forget(&mut self, pos: i32) {
+        self.set(pos, D::top(self.mask));
+    }
+
+    /// The fewest strides of `stride` from the pointer, one or more, to a cell known to be zero.
+    pub fn next_zero(&self, stride: i32) -> Option<u32> {
+        if stride == 0 {
+            return None;
+        }
+        let ptr = self.ptr;
+        if self.rest.is_zero() {
+            // Only the cells in `cells` can be nonzero, so one more stride than there are of them
+            // lands on a zero, unless the positions wrap around onto the same cells.
+            return (1..=self.cells.len() as i32 + 1)
+                .find(|&steps| self.get(ptr.wrapping_add(steps.wrapping_mul(stride))).is_zero())
+                .map(|steps| steps as u32);
+        }
+        let ahead = |&(&pos, value): &(&i32, &D)| {
+            let dist = pos as i64 - ptr as i64;
+            dist.signum() == stride.signum() as i64 && dist % stride as i64 == 0 && value.is_zero()
+        };
+        if stride > 0 {
+            self.cells.range(ptr.saturating_add(1)..).find(ahead)
+        } else {
+            self.cells.range(..ptr).rev().find(ahead)
+        }
+        .map(|(&pos, _)| ((pos as i64 - ptr as i64) / stride as i64) as u32)
+    }
+
+    /// Runs `inst` on the state. A loop whose cell is known to be zero is skipped.
+    pub fn step(&mut self, inst: &BaseInst) {
+        let mask = self.mask;
+        let sext = |value: u8| value as i8 as u32 & mask;
+        let ptr = self.ptr;
+        match inst {
+            BaseInst::Shift(delta) => self.ptr = ptr.wrapping_add(*delta),
+            BaseInst::Inc(inc) => self.set(ptr, self.current().add(sext(*inc), mask)),
+            BaseInst::Reset => self.set(ptr, D::constant(0)),
+            BaseInst::Input | BaseInst::Random => self.forget(ptr),
+            BaseInst::Output | BaseInst::ErrOutput => {}
+            BaseInst::Fill(start, len, value) => {
+                for pos in span(ptr.wrapping_add(*start), *len) {
+                    self.set(pos, D::constant(sext(*value)));
+                }
+            }
+            BaseInst::BlockMove(offset, len) => {
+                for pos in span(ptr, *len) {
+                    self.set(pos.wrapping_add(*offset), self.get(pos));
+                    self.set(pos, D::constant(0));
+                }
+            }
+            BaseInst::Mul(offset, weight) => {
+                let src = self.current();
+                let pos = ptr.wrapping_add(*offset);
+                let dst = self.get(pos).mul_add(&src, sext(*weight), mask);
+                self.set(pos, dst);
+            }
+            _ if self.current().is_zero() => {}
+            BaseInst::Seek(..) | BaseInst::BoundedSeek(..) | BaseInst::Skip(..) | BaseInst::Block(_, false, _) => {
+                self.lose_pointer()
+            }
+            BaseInst::Block(body, true, _) => {
+                *self = self.invariant(body);
+                self.set(ptr, D::constant(0));
+            }
+        }
+    }
+
+    pub fn run(&mut self, prog: &[BaseInst]) {
+        for inst in prog {
+            self.step(inst);
+        }
+    }
+
+    /// Steps over the loop over `body` like [`step`](Self::step) and returns the state at the
+    /// start of every iteration, for a pass that rewrites the body. Offsets inside keep lining
+    /// up with the ones outside only when the loop is `stable`.
+    pub fn step_loop(&mut self, body: &[BaseInst], stable: bool) -> Self {
+        let mut entry = if self.current().is_zero() {
+            self.clone()
+        } else if stable {
+            let invariant = self.invariant(body);
+            *self = invariant.clone();
+            self.set(self.ptr, D::constant(0));
+            invariant
+        } else {
+            self.lose_pointer();
+            let mut entry = self.clone();
+            entry.forget(entry.ptr);
+            entry
+        };
+        entry.set(entry.ptr, entry.current().assume_nonzero());
+        entry
+    }
+
+    /// Forgets everything but that the pointer stopped on a zero, after it moved by an unknown amount.
+    fn lose_pointer(&mut self) {
+        self.cells.clear();
+        self.rest = D::top(self.mask);
+        self.set(self.ptr, D::constant(0));
+        self.lost = true;
+    }
+
+    /// Joins two states at the same pointer, keeping what holds in both.
+    fn join(&self, other: &Self) -> Self {
+        let mut joined = State {
+            cells: BTreeMap::new(),
+            rest: self.rest.join(&other.rest),
+            ptr: self.ptr,
+            mask: self.mask,
+            lost: self.lost || other.lost,
+            shallow: self.shallow,
+        };
+        for &pos in self.cells.keys().chain(other.cells.keys()) {
+            joined.set(pos, self.get(pos).join(&other.get(pos)));
+        }
+        joined
+    }
+
+    /// Summarizes a stable loop over `body` entered from here. Forgetting the cells the body
+    /// may write gives a state that holds at the start of every iteration; unless `shallow`,
+    /// joining this state with one run of the body from there narrows it and still holds.
+    fn invariant(&self, body: &[BaseInst]) -> Self {
+        let mut havoc = self.clone();
+        match written(body) {
+            Some(cells) => {
+                for offset in cells {
+                    havoc.forget(self.ptr.wrapping_add(offset));
+                }
+            }
+            None => {
+                havoc.cells.clear();
+                havoc.rest = D::top(self.mask);
+            }
+        }
+        if self.shallow {
+            return havoc;
+        }
+        let mut iteration = havoc.clone();
+        iteration.set(self.ptr, iteration.current().assume_nonzero());
+        iteration.lost = false;
+        iteration.shallow = true;
+        iteration.run(body);
+        if iteration.lost || iteration.ptr != self.ptr {
+            return havoc;
+        }
+        self.join(&iteration)
+    }
+}
+
+/// The `len` cells from `start` on. Positions wrap around like the tape offsets they stand for,
+/// which no tape is long enough to tell apart.
+pub(crate) fn span(start: i32, len: i16) -> impl Iterator<Item = i32> {
+    (0..len as i32).map(move |idx| start.wrapping_add(idx))
+}
+
+/// Deepest nesting [`written`] looks into before giving up, which keeps the analysis of deeply
+/// nested loops from growing with the square of their depth.
+const MAX_DEPTH: u32 = 16;
+
+/// Offsets from the start of `body` of the cells it may write, or `None` when the pointer may
+/// move by an unknown amount or the loops nest too deep to tell.
+fn written(body: &[BaseInst]) -> Option<BTreeSet<i32>> {
+    fn collect(body: &[BaseInst], mut ptr: i32, depth: u32, cells: &mut BTreeSet<i32>) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        for inst in body {
+            match inst {
+                BaseInst::Shift(delta) => ptr = ptr.wrapping_add(*delta),
+                BaseInst::Inc(_) | BaseInst::Reset | BaseInst::Input | BaseInst::Random => {
+                    cells.insert(ptr);
+                }
+                BaseInst::Output | BaseInst::ErrOutput => {}
+                BaseInst::Mul(offset, _) => {
+                    cells.insert(ptr.wrapping_add(*offset));
+                }
+                BaseInst::Fill(start, len, _) => cells.extend(span(ptr.wrapping_add(*start), *len)),
+                BaseInst::BlockMove(offset, len) => {
+                    cells.extend(span(ptr, *len));
+                    cells.extend(span(ptr.wrapping_add(*offset), *len));
+                }
+                BaseInst::Block(inner, true, _) => collect(inner, ptr, depth + 1, cells)?,
+                BaseInst::Seek(..) | BaseInst::BoundedSeek(..) | BaseInst::Skip(..) | BaseInst::Block(_, false, _) => {
+                    return None;
+                }
+            }
+        }
+        Some(())
+    }
+    let mut cells = BTreeSet::new();
+    collect(body, 0, 0, &mut cells).map(|()| cells)
+}
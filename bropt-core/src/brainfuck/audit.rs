@@ -0,0 +1,325 @@
+//! A slow model of the flat stream run alongside a real one, to catch the fused and threaded loop
+//! instructions disagreeing with the loops they were compiled from.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::hooks::{ExecHooks, IoEvent};
+use super::interp::{Eof, skip_end};
+use super::ir::{CellWidth, Inst, InstType};
+use super::rng::{Rng, Seed};
+
+/// The first point where a run and [`ExecAudit`]'s model disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The instruction the model executed last before the two disagreed.
+    pub ip: usize,
+    /// What the run did against what the model expected.
+    pub message: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "after instruction {}: {}", self.ip, self.message)
+    }
+}
+
+impl core::error::Error for Divergence {}
+
+/// Checks every step of a run against a model of the same stream on a tape of its own.
+///
+/// The model runs each loop as written: an `Open` that finds a zero goes past its matching
+/// `Close`, and a `Close` that finds a nonzero goes back to the body and runs its leading
+/// increment and shift, which are the ones its `Open` carries. It ignores the jump targets, so a
+/// loop the run leaves or repeats differently, a `Close` fused with a different increment than its
+/// `Open`, or a threaded jump landing somewhere the model cannot reach through brackets alone, is
+/// reported. Instructions other than brackets run as the interpreters run them.
+///
+/// The run must start at the first instruction on a zeroed tape, and the model's `?` draws from
+/// its own generator, seeded as the run's.
+pub struct ExecAudit<'a> {
+    prog: &'a [Inst],
+    partner: Vec<usize>,
+    mask: u32,
+    eof: Eof,
+    rng: Rng,
+    cells: BTreeMap<isize, u32>,
+    ip: usize,
+    dp: isize,
+    last: usize,
+    pending: Option<usize>,
+    event: Option<IoEvent>,
+    divergence: Option<Divergence>,
+}
+
+impl<'a> ExecAudit<'a> {
+    pub fn new(prog: &'a [Inst], cell: CellWidth, eof: Eof, seed: Seed) -> Self {
+        let mut partner = alloc::vec![usize::MAX; prog.len()];
+        let mut stack = Vec::new();
+        for (ip, inst) in prog.iter().enumerate() {
+            match inst.cmd {
+                InstType::Open => stack.push(ip),
+                InstType::Close => {
+                    let open = stack.pop().expect("unbalanced stream");
+                    partner[open] = ip;
+                    partner[ip] = open;
+                }
+                _ => {}
+            }
+        }
+        ExecAudit {
+            prog,
+            partner,
+            mask: cell.mask(),
+            eof,
+            rng: seed.rng(),
+            cells: BTreeMap::new(),
+            ip: 0,
+            dp: 0,
+            last: 0,
+            pending: None,
+            event: None,
+            divergence: None,
+        }
+    }
+
+    /// The first disagreement found so far.
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Checks where a finished run ended and every cell the model wrote against `tape`, which
+    /// gives the run's final cells by position. A run stopped before the end of the stream is
+    /// only checked as far as it got.
+    pub fn finish(&mut self, ip: usize, dp: usize, tape: impl Fn(usize) -> u32) -> Option<&Divergence> {
+        if self.divergence.is_none() && ip >= self.prog.len() {
+            self.sync(self.prog.len(), dp);
+        }
+        if self.divergence.is_none() {
+            let wrong = self
+                .cells
+                .iter()
+                .find(|&(&pos, &value)| tape(pos as usize) != value)
+                .map(|(&pos, &value)| (pos, value));
+            if let Some((pos, value)) = wrong {
+                let message = format!(
+                    "cell {} ends as {} where the model has {}",
+                    pos,
+                    tape(pos as usize),
+                    value
+                );
+                self.diverge(message);
+            }
+        }
+        self.divergence.as_ref()
+    }
+
+    /// Catches the model up with a run about to execute instruction `ip` at `dp`.
+    fn sync(&mut self, ip: usize, dp: usize) {
+        if let Some(prev) = self.pending.take() {
+            self.last = prev;
+            match self.step(prev) {
+                Ok(next) => self.ip = next,
+                Err(message) => return self.diverge(message),
+            }
+        }
+        // Threaded jumps skip brackets whose outcome is already known, so the model walks them.
+        let mut walked = 0;
+        while self.ip != ip {
+            let bracket = self
+                .prog
+                .get(self.ip)
+                .is_some_and(|inst| matches!(inst.cmd, InstType::Open | InstType::Close));
+            if !bracket || walked > self.prog.len() {
+                let message = format!(
+                    "the run goes on at instruction {} where the model goes on at {}",
+                    ip, self.ip
+                );
+                return self.diverge(message);
+            }
+            self.last = self.ip;
+            self.ip = self.bracket(self.ip);
+            walked += 1;
+        }
+        if self.dp != dp as isize {
+            let message = format!("the pointer is at {} where the model has it at {}", dp, self.dp);
+            self.diverge(message);
+        }
+    }
+
+    fn diverge(&mut self, message: String) {
+        self.divergence = Some(Divergence { ip: self.last, message });
+    }
+
+    fn get(&self, pos: isize) -> u32 {
+        self.cells.get(&pos).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, pos: isize, value: u32) {
+        self.cells.insert(pos, value & self.mask);
+    }
+
+    fn add(&mut self, pos: isize, inc: u8) {
+        self.set(pos, self.get(pos).wrapping_add(inc as i8 as u32));
+    }
+
+    /// Runs the loop bracket at `ip` from its matching bracket alone and returns the next `ip`.
+    fn bracket(&mut self, ip: usize) -> usize {
+        let (open, close) = match self.prog[ip].cmd {
+            InstType::Open => (ip, self.partner[ip]),
+            _ => (self.partner[ip], ip),
+        };
+        if self.get(self.dp) == 0 {
+            return close + 1;
+        }
+        let Inst { inc, delta, .. } = self.prog[open];
+        self.add(self.dp, inc);
+        self.dp += delta as isize;
+        open + 1
+    }
+
+    /// Runs the instruction at `ip` with what the run read or wrote there, and returns the next
+    /// `ip`.
+    fn step(&mut self, ip: usize) -> Result<usize, String> {
+        let Inst { cmd, inc, delta, arg } = self.prog[ip];
+        let (arg, delta) = (arg as isize, delta as isize);
+        let event = self.event.take();
+        match cmd {
+            InstType::Open | InstType::Close => return Ok(self.bracket(ip)),
+            InstType::ShiftInc => {
+                self.add(self.dp + arg, inc);
+                self.dp += arg + delta;
+            }
+            InstType::Output | InstType::ErrOutput => {
+                let pos = self.dp + arg;
+                let byte = self.get(pos) as u8;
+                let expected = match cmd {
+                    InstType::Output => IoEvent::Write(byte),
+                    _ => IoEvent::WriteErr(byte),
+                };
+                if event != Some(expected) {
+                    return Err(format!(
+                        "the run {} where the model {}",
+                        describe(event),
+                        describe(Some(expected))
+                    ));
+                }
+                self.add(pos, inc);
+                self.dp = pos + delta;
+            }
+            InstType::Input => {
+                let Some(IoEvent::Read(byte)) = event else {
+                    return Err(format!("the run {} where the model reads", describe(event)));
+                };
+                let pos = self.dp + arg;
+                let value = match (byte, self.eof) {
+                    (Some(byte), _) => byte as u32,
+                    (None, Eof::Zero) => 0,
+                    (None, Eof::MinusOne) => self.mask,
+                    (None, Eof::Unchanged) => self.get(pos),
+                };
+                self.set(pos, value);
+                self.add(pos, inc);
+                self.dp = pos + delta;
+            }
+            InstType::Random => {
+                let pos = self.dp + arg;
+                let byte = self.rng.byte();
+                self.set(pos, byte as u32);
+                self.add(pos, inc);
+                self.dp = pos + delta;
+            }
+            InstType::Seek => {
+                while self.get(self.dp) != 0 {
+                    self.dp += arg;
+                }
+                self.add(self.dp + delta, inc);
+                self.dp += delta;
+            }
+            InstType::BoundedSeek => {
+                for _ in 0..delta {
+                    if self.get(self.dp) == 0 {
+                        break;
+                    }
+                    self.dp += arg;
+                }
+            }
+            InstType::Skip => {
+                while self.get(self.dp) != 0 {
+                    self.add(self.dp + delta, inc);
+                    self.dp += arg;
+                }
+            }
+            InstType::SkipInc => {
+                let end = skip_end(self.prog, ip);
+                while self.get(self.dp) != 0 {
+                    for step in &self.prog[ip..=end] {
+                        self.add(self.dp + step.delta as isize, step.inc);
+                    }
+                    self.dp += self.prog[end].arg as isize;
+                }
+                return Ok(end + 1);
+            }
+            InstType::Set => {
+                self.set(self.dp + arg, inc as i8 as u32);
+                self.dp += arg + delta;
+            }
+            InstType::Mul | InstType::Mulzero => {
+                let src = self.get(self.dp);
+                if src != 0 {
+                    let product = src.wrapping_mul(inc as i8 as u32);
+                    self.set(self.dp + arg, self.get(self.dp + arg).wrapping_add(product));
+                    if cmd == InstType::Mulzero {
+                        self.set(self.dp, 0);
+                    }
+                }
+                self.dp += delta;
+            }
+            InstType::Fill => {
+                for pos in self.dp + arg..self.dp + arg + delta {
+                    self.set(pos, inc as i8 as u32);
+                }
+            }
+            InstType::BlockMove => {
+                let values: Vec<u32> = (self.dp..self.dp + delta).map(|pos| self.get(pos)).collect();
+                for (pos, value) in (self.dp + arg..).zip(values) {
+                    self.set(pos, value);
+                }
+                for pos in self.dp..self.dp + delta {
+                    self.set(pos, 0);
+                }
+            }
+        }
+        Ok(ip + 1)
+    }
+}
+
+/// What a run did at an instruction, for [`Divergence::message`].
+fn describe(event: Option<IoEvent>) -> String {
+    match event {
+        Some(IoEvent::Read(_)) => "reads".into(),
+        Some(IoEvent::Write(byte)) => format!("writes {}", byte),
+        Some(IoEvent::WriteErr(byte)) => format!("writes {} to the error stream", byte),
+        None => "does no I/O".into(),
+    }
+}
+
+impl ExecHooks for ExecAudit<'_> {
+    fn on_instruction(&mut self, ip: usize, _inst: &Inst, dp: usize) {
+        if self.divergence.is_none() {
+            self.sync(ip, dp);
+            self.pending = Some(ip);
+        }
+    }
+
+    fn on_io(&mut self, _ip: usize, _dp: usize, event: IoEvent) {
+        self.event = Some(event);
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.divergence.is_some()
+    }
+}
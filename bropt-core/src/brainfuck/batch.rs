@@ -0,0 +1,273 @@
+//! Running one program over many tapes at once, for fuzzers and graders that feed the same
+//! program thousands of inputs.
+//!
+//! The tapes are interleaved cell by cell, so an instruction updates the same cell of every tape
+//! in one loop the compiler can vectorize. The tapes share the instruction and the pointer, so
+//! they only run together while every branch goes the same way on all of them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::interp::{Cell, CellOps, Eof, read_cell, skip_end};
+use super::io::{ByteRead, ByteWrite};
+use super::ir::{Inst, InstType};
+use super::rng::Rng;
+
+/// Tapes of the same length stored cell by cell: every lane's cell 0, then every lane's cell 1.
+#[derive(Debug, Clone)]
+pub struct BatchTape<C> {
+    cells: Vec<C>,
+    lanes: usize,
+    len: usize,
+    /// One past the highest cell touched so far. Every cell from here on is still zero.
+    touched: usize,
+}
+
+impl<C: Cell> BatchTape<C> {
+    /// `lanes` zeroed tapes of `len` cells.
+    pub fn new(len: usize, lanes: usize) -> Self {
+        BatchTape {
+            cells: vec![C::ZERO; len * lanes],
+            lanes,
+            len,
+            touched: 0,
+        }
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.lanes
+    }
+
+    /// Number of cells in each tape.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, lane: usize, pos: usize) -> C {
+        self.cells[pos * self.lanes + lane]
+    }
+
+    /// The tape of `lane` on its own.
+    pub fn lane(&self, lane: usize) -> Vec<C> {
+        let mut cells = vec![C::ZERO; self.len];
+        for (pos, cell) in cells[..self.touched].iter_mut().enumerate() {
+            *cell = self.get(lane, pos);
+        }
+        cells
+    }
+
+    /// Moves the lanes for which `split` is set to a new batch, keeping the others in order.
+    pub fn split_off(&mut self, split: &[bool]) -> BatchTape<C> {
+        let moved = split.iter().filter(|&&lane| lane).count();
+        let mut kept = BatchTape::new(self.len, self.lanes - moved);
+        let mut other = BatchTape::new(self.len, moved);
+        kept.touched = self.touched;
+        other.touched = self.touched;
+        for pos in 0..self.touched {
+            let (mut to_kept, mut to_other) = (pos * kept.lanes, pos * other.lanes);
+            for (lane, &split) in split.iter().enumerate() {
+                let cell = self.get(lane, pos);
+                if split {
+                    other.cells[to_other] = cell;
+                    to_other += 1;
+                } else {
+                    kept.cells[to_kept] = cell;
+                    to_kept += 1;
+                }
+            }
+        }
+        *self = kept;
+        other
+    }
+
+    fn row(&mut self, pos: usize) -> &mut [C] {
+        &mut self.cells[pos * self.lanes..(pos + 1) * self.lanes]
+    }
+
+    fn rows(&mut self, pos: usize, count: usize) -> &mut [C] {
+        &mut self.cells[pos * self.lanes..(pos + count) * self.lanes]
+    }
+
+    /// Whether the cell at `pos` is zero on every lane, on none, or `None` when the lanes disagree.
+    fn all_zero(&mut self, pos: usize) -> Option<bool> {
+        let zeros = self.row(pos).iter().filter(|&&cell| cell == C::ZERO).count();
+        match zeros {
+            0 => Some(false),
+            zeros if zeros == self.lanes => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// Runs `prog` on every lane of `tape` from the instruction and pointer in `start`, with lane
+/// `lane` reading from and writing to `inputs[ids[lane]]` and `outputs[ids[lane]]`. Every lane
+/// draws the same bytes for `?` from `rng`, as separate runs from the same seed would.
+///
+/// Returns the instruction and pointer it stopped at. The instruction is `prog.len()` once the
+/// program has finished. Otherwise the lanes disagree on whether the current cell is zero, or
+/// the instruction there touches a cell off the tape. Either way, the instruction has not run
+/// yet, and each lane can be resumed from there on its own.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_batch<C: Cell, O: CellOps, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    tape: &mut BatchTape<C>,
+    start: (usize, usize),
+    eof: Eof,
+    ids: &[usize],
+    inputs: &mut [R],
+    outputs: &mut [W],
+    rng: &mut Rng,
+) -> (usize, usize) {
+    let len = tape.len;
+    let (mut ip, mut dp) = start;
+    // The cells `offset` to `offset + count` away from `dp`, or a stop at the current
+    // instruction if any of them is off the tape.
+    macro_rules! at {
+        ($offset:expr) => {
+            at!($offset, 1)
+        };
+        ($offset:expr, $count:expr) => {{
+            let pos = dp.wrapping_add_signed($offset as isize);
+            match pos.checked_add($count) {
+                Some(end) if end <= len => {
+                    tape.touched = tape.touched.max(end);
+                    pos
+                }
+                _ => return (ip, dp),
+            }
+        }};
+    }
+    // Stops at the current instruction unless the cell at `pos` is zero on every lane or on none.
+    macro_rules! zero {
+        ($pos:expr) => {{
+            let pos = $pos;
+            match tape.all_zero(pos) {
+                Some(zero) => zero,
+                None => return (ip, dp),
+            }
+        }};
+    }
+    while ip < prog.len() {
+        let Inst { cmd, arg, inc, delta } = &prog[ip];
+        let inc = C::from_inc(*inc);
+        if *cmd == InstType::ShiftInc {
+            let pos = at!(*arg);
+            tape.row(pos).iter_mut().for_each(|cell| *cell = O::add(*cell, inc));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Output {
+            let pos = at!(*arg);
+            for (cell, &id) in tape.row(pos).iter_mut().zip(ids) {
+                outputs[id].write_byte(cell.to_byte());
+                *cell = O::add(*cell, inc);
+            }
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::ErrOutput {
+            let pos = at!(*arg);
+            for (cell, &id) in tape.row(pos).iter_mut().zip(ids) {
+                outputs[id].write_err(cell.to_byte());
+                *cell = O::add(*cell, inc);
+            }
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Input {
+            let pos = at!(*arg);
+            for (cell, &id) in tape.row(pos).iter_mut().zip(ids) {
+                outputs[id].flush();
+                *cell = O::add(read_cell(inputs[id].read_byte(), eof, *cell), inc);
+            }
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Seek {
+            while !zero!(at!(0)) {
+                dp = dp.wrapping_add_signed(*arg as isize);
+            }
+            let pos = at!(*delta);
+            tape.row(pos).iter_mut().for_each(|cell| *cell = O::add(*cell, inc));
+            dp = pos;
+        } else if *cmd == InstType::BoundedSeek {
+            // Looks ahead of `dp` instead of moving it, so a stop resumes the whole seek.
+            let mut steps = 0;
+            while steps < *delta as isize && !zero!(at!(steps * *arg as isize)) {
+                steps += 1;
+            }
+            dp = dp.wrapping_add_signed(steps * *arg as isize);
+        } else if *cmd == InstType::Skip {
+            while !zero!(at!(0)) {
+                let pos = at!(*delta);
+                tape.row(pos).iter_mut().for_each(|cell| *cell = O::add(*cell, inc));
+                dp = dp.wrapping_add_signed(*arg as isize);
+            }
+        } else if *cmd == InstType::Set {
+            let pos = at!(*arg);
+            tape.row(pos).fill(inc);
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Mul || *cmd == InstType::Mulzero {
+            let src = at!(0);
+            if !tape.row(src).iter().all(|&cell| cell == C::ZERO) {
+                let pos = at!(*arg);
+                let lanes = tape.lanes;
+                for lane in 0..lanes {
+                    let value = tape.cells[src * lanes + lane];
+                    let dst = &mut tape.cells[pos * lanes + lane];
+                    *dst = O::add(*dst, O::mul(value, inc));
+                }
+                if *cmd == InstType::Mulzero {
+                    tape.row(src).fill(C::ZERO);
+                }
+            }
+            dp = dp.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Fill {
+            let start = at!(*arg, *delta as usize);
+            tape.rows(start, *delta as usize).fill(inc);
+        } else if *cmd == InstType::BlockMove {
+            let count = *delta as usize;
+            let src = at!(0, count);
+            let dst = at!(*arg, count);
+            let lanes = tape.lanes;
+            tape.cells.copy_within(src * lanes..(src + count) * lanes, dst * lanes);
+            tape.rows(src, count).fill(C::ZERO);
+        } else if *cmd == InstType::Random {
+            let pos = at!(*arg);
+            let value = O::add(C::from_byte(rng.byte()), inc);
+            tape.row(pos).fill(value);
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::SkipInc {
+            let end = skip_end(prog, ip);
+            let steps = &prog[ip..=end];
+            let low = steps.iter().map(|step| step.delta).min().unwrap();
+            let high = steps.iter().map(|step| step.delta).max().unwrap();
+            while !zero!(at!(0)) {
+                // Every cell of a step is checked before any changes, so a stop never splits one.
+                let start = at!(low, (high as isize - low as isize) as usize + 1);
+                for step in steps {
+                    let pos = start + (step.delta as isize - low as isize) as usize;
+                    let inc = C::from_inc(step.inc);
+                    tape.row(pos).iter_mut().for_each(|cell| *cell = O::add(*cell, inc));
+                }
+                dp = dp.wrapping_add_signed(prog[end].arg as isize);
+            }
+            ip = end;
+        } else if *cmd == InstType::Open {
+            let pos = at!(0);
+            if zero!(pos) {
+                ip = *arg as usize;
+            } else {
+                tape.row(pos).iter_mut().for_each(|cell| *cell = O::add(*cell, inc));
+                dp = dp.wrapping_add_signed(*delta as isize);
+            }
+        } else
+        /* if *cmd == InstType::Close */
+        {
+            let pos = at!(0);
+            if !zero!(pos) {
+                ip = *arg as usize;
+                tape.row(pos).iter_mut().for_each(|cell| *cell = O::add(*cell, inc));
+                dp = dp.wrapping_add_signed(*delta as isize);
+            }
+        }
+        ip += 1;
+    }
+    (ip, dp)
+}
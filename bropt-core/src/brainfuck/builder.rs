@@ -0,0 +1,90 @@
+//! Building the [`BaseInst`] tree in code, for tests and front ends that do not start from source.
+
+use alloc::vec::Vec;
+
+use super::ir::BaseInst;
+
+/// Appends instructions to a tree, so nested loops need no hand-built `Block` vectors, as in
+/// `IrBuilder::new().inc(3).loop_(|body| { body.inc(-1).shift(1).inc(2).shift(-1); }).build()`.
+#[derive(Debug, Default, Clone)]
+pub struct IrBuilder {
+    prog: Vec<BaseInst>,
+}
+
+impl IrBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value` to the current cell, split into steps that stay exact on every cell width.
+    pub fn inc(&mut self, mut value: i32) -> &mut Self {
+        while value != 0 {
+            let step = value.clamp(i8::MIN as i32, i8::MAX as i32);
+            self.prog.push(BaseInst::Inc(step as i8 as u8));
+            value -= step;
+        }
+        self
+    }
+
+    pub fn shift(&mut self, delta: i32) -> &mut Self {
+        if delta != 0 {
+            self.prog.push(BaseInst::Shift(delta));
+        }
+        self
+    }
+
+    pub fn output(&mut self) -> &mut Self {
+        self.push(BaseInst::Output)
+    }
+
+    pub fn err_output(&mut self) -> &mut Self {
+        self.push(BaseInst::ErrOutput)
+    }
+
+    pub fn input(&mut self) -> &mut Self {
+        self.push(BaseInst::Input)
+    }
+
+    pub fn random(&mut self) -> &mut Self {
+        self.push(BaseInst::Random)
+    }
+
+    pub fn reset(&mut self) -> &mut Self {
+        self.push(BaseInst::Reset)
+    }
+
+    /// Adds any instruction, for the folded forms that have no shorthand.
+    pub fn push(&mut self, inst: BaseInst) -> &mut Self {
+        self.prog.push(inst);
+        self
+    }
+
+    /// Adds a loop whose body `body` builds. The loop is marked stable when the body provably
+    /// returns the pointer to where it started, as the parser marks it.
+    pub fn loop_(&mut self, body: impl FnOnce(&mut IrBuilder)) -> &mut Self {
+        let mut inner = IrBuilder::new();
+        body(&mut inner);
+        let stable = balanced(&inner.prog);
+        self.push(BaseInst::Block(inner.prog, stable, None))
+    }
+
+    /// Takes the instructions added so far, leaving the builder empty.
+    pub fn build(&mut self) -> Vec<BaseInst> {
+        core::mem::take(&mut self.prog)
+    }
+}
+
+/// Whether `body` provably returns the pointer to where it started.
+pub(super) fn balanced(body: &[BaseInst]) -> bool {
+    let mut ptr = 0i64;
+    for inst in body {
+        match inst {
+            BaseInst::Shift(delta) => ptr += *delta as i64,
+            BaseInst::Seek(..) | BaseInst::BoundedSeek(..) | BaseInst::Skip(..) | BaseInst::Block(_, false, _) => {
+                return false;
+            }
+            _ => {}
+        }
+    }
+    ptr == 0
+}
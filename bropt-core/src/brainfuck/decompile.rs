@@ -0,0 +1,68 @@
+//! [`BaseInst`] tree back to source text.
+
+use alloc::format;
+use alloc::string::String;
+
+use super::ir::BaseInst;
+
+/// Writes the tree IR back out as brainfuck.
+///
+/// Everything the parser produces round-trips, as do `Reset`, `Seek` and `Fill`. A `BoundedSeek`
+/// comes back as the plain scan it was proven equivalent to. `Mul`, `Skip` and `BlockMove` need
+/// scratch cells or a loop around them to be expressed and are rejected.
+pub fn decompile(prog: &[BaseInst]) -> Result<String, String> {
+    let mut code = String::new();
+    push_block(&mut code, prog)?;
+    Ok(code)
+}
+
+fn push_block(code: &mut String, prog: &[BaseInst]) -> Result<(), String> {
+    for inst in prog {
+        match inst {
+            BaseInst::Inc(inc) => push_inc(code, *inc),
+            BaseInst::Shift(delta) => push_shift(code, *delta),
+            BaseInst::Output => code.push('.'),
+            BaseInst::ErrOutput => code.push(':'),
+            BaseInst::Input => code.push(','),
+            BaseInst::Random => code.push('?'),
+            BaseInst::Reset => code.push_str("[-]"),
+            BaseInst::Seek(stride) | BaseInst::BoundedSeek(stride, _) => {
+                code.push('[');
+                push_shift(code, *stride);
+                code.push(']');
+            }
+            BaseInst::Fill(start, len, value) if *len > 0 => {
+                for idx in 0..*len as i32 {
+                    push_shift(code, if idx == 0 { *start } else { 1 });
+                    code.push_str("[-]");
+                    push_inc(code, *value);
+                }
+                push_shift(code, -(*start + *len as i32 - 1));
+            }
+            BaseInst::Fill(..) => {}
+            BaseInst::Block(body, ..) => {
+                code.push('[');
+                push_block(code, body)?;
+                code.push(']');
+            }
+            BaseInst::Mul(..) | BaseInst::Skip(..) | BaseInst::BlockMove(..) => {
+                return Err(format!("{inst:?} has no direct brainfuck form"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Increments are sign-extended, so the byte maps to the shorter of `+` and `-`.
+fn push_inc(code: &mut String, inc: u8) {
+    if inc < 128 {
+        code.extend(core::iter::repeat_n('+', inc as usize));
+    } else {
+        code.extend(core::iter::repeat_n('-', inc.wrapping_neg() as usize));
+    }
+}
+
+fn push_shift(code: &mut String, delta: i32) {
+    let ch = if delta < 0 { '<' } else { '>' };
+    code.extend(core::iter::repeat_n(ch, delta.unsigned_abs() as usize));
+}
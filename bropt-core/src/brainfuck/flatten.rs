@@ -0,0 +1,546 @@
+//! [`BaseInst`] tree to flat [`Inst`] stream.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
+use core::iter::Peekable;
+
+use super::ir::{BaseInst, CellWidth, Inst, InstType, SourceMap};
+use super::opt::combine_inc;
+
+/// Lowers the tree IR into a flat [`Inst`] stream, fusing neighbouring shifts and increments
+/// into operands and resolving bracket targets.
+///
+/// Every opcode that moves by `arg` takes a preceding shift, and every opcode with a free
+/// `inc`/`delta` takes a following increment and shift, so `[-]+++` becomes a single `Set(3)`.
+pub fn flatten(prog: Vec<BaseInst>) -> Vec<Inst> {
+    flatten_with_map(prog).0
+}
+
+/// [`flatten`] that also returns where each instruction's innermost loop starts in the source.
+pub fn flatten_with_map(prog: Vec<BaseInst>) -> (Vec<Inst>, SourceMap) {
+    fn pick_inc<I: Iterator<Item = BaseInst>>(iter: &mut Peekable<I>) -> u8 {
+        if let Some(BaseInst::Inc(value)) = iter.peek() {
+            let value = *value;
+            iter.next();
+            return value;
+        }
+        0
+    }
+    fn pick_shift<I: Iterator<Item = BaseInst>>(iter: &mut Peekable<I>) -> i16 {
+        if let Some(BaseInst::Shift(delta)) = iter.peek() {
+            let delta = *delta;
+            if i16::MIN as i32 <= delta && delta <= i16::MAX as i32 {
+                iter.next();
+                return delta as i16;
+            }
+        }
+        0
+    }
+    fn fuse<I: Iterator<Item = BaseInst>>(iter: &mut Peekable<I>, cmd: InstType, arg: i32) -> Inst {
+        let inc = pick_inc(iter);
+        let delta = pick_shift(iter);
+        Inst { cmd, inc, delta, arg }
+    }
+    fn flatten_block<I: Iterator<Item = BaseInst>>(
+        iter: &mut Peekable<I>,
+        flat: &mut Vec<Inst>,
+        map: &mut Vec<Option<u32>>,
+        enclosing: Option<u32>,
+    ) {
+        while let Some(inst) = iter.next() {
+            match inst {
+                BaseInst::Inc(inc) => {
+                    let delta = pick_shift(iter);
+                    flat.push(Inst {
+                        cmd: InstType::ShiftInc,
+                        arg: 0,
+                        inc,
+                        delta,
+                    });
+                }
+                BaseInst::Shift(arg) => {
+                    let cmd = match iter.peek() {
+                        Some(BaseInst::Reset) => InstType::Set,
+                        Some(BaseInst::Output) => InstType::Output,
+                        Some(BaseInst::ErrOutput) => InstType::ErrOutput,
+                        Some(BaseInst::Input) => InstType::Input,
+                        Some(BaseInst::Random) => InstType::Random,
+                        _ => InstType::ShiftInc,
+                    };
+                    if cmd != InstType::ShiftInc {
+                        iter.next();
+                    }
+                    flat.push(fuse(iter, cmd, arg));
+                }
+                BaseInst::Output => flat.push(fuse(iter, InstType::Output, 0)),
+                BaseInst::ErrOutput => flat.push(fuse(iter, InstType::ErrOutput, 0)),
+                BaseInst::Input => flat.push(fuse(iter, InstType::Input, 0)),
+                BaseInst::Random => flat.push(fuse(iter, InstType::Random, 0)),
+                BaseInst::Reset => flat.push(fuse(iter, InstType::Set, 0)),
+                BaseInst::Mul(offset, weight) => {
+                    let cmd = if let Some(BaseInst::Reset) = iter.peek() {
+                        iter.next();
+                        InstType::Mulzero
+                    } else {
+                        InstType::Mul
+                    };
+                    let delta = pick_shift(iter);
+                    flat.push(Inst {
+                        cmd,
+                        arg: offset,
+                        inc: weight,
+                        delta,
+                    });
+                }
+                BaseInst::Seek(offset) => {
+                    let delta = pick_shift(iter);
+                    let inc = pick_inc(iter);
+                    flat.push(Inst {
+                        cmd: InstType::Seek,
+                        arg: offset,
+                        inc,
+                        delta,
+                    });
+                }
+                BaseInst::BoundedSeek(stride, max) => {
+                    flat.push(Inst {
+                        cmd: InstType::BoundedSeek,
+                        arg: stride,
+                        inc: 0,
+                        delta: max,
+                    });
+                }
+                BaseInst::Skip(stride, mut incs) => {
+                    let (inc, delta) = incs.pop().unwrap();
+                    for (inc, delta) in incs {
+                        flat.push(Inst {
+                            cmd: InstType::SkipInc,
+                            arg: 0,
+                            inc,
+                            delta,
+                        });
+                    }
+                    flat.push(Inst {
+                        cmd: InstType::Skip,
+                        arg: stride,
+                        inc,
+                        delta,
+                    });
+                }
+                BaseInst::Fill(start, len, value) => {
+                    flat.push(Inst {
+                        cmd: InstType::Fill,
+                        arg: start,
+                        inc: value,
+                        delta: len,
+                    });
+                }
+                BaseInst::BlockMove(offset, len) => {
+                    flat.push(Inst {
+                        cmd: InstType::BlockMove,
+                        arg: offset,
+                        inc: 0,
+                        delta: len,
+                    });
+                }
+                BaseInst::Block(block, _, pos) => {
+                    let pos = pos.or(enclosing);
+                    let mut iter_block = block.into_iter().peekable();
+                    let inc = pick_inc(&mut iter_block);
+                    let delta = pick_shift(&mut iter_block);
+                    flat.push(Inst {
+                        cmd: InstType::Open,
+                        arg: 0,
+                        inc,
+                        delta,
+                    });
+                    map.push(pos);
+                    flatten_block(&mut iter_block, flat, map, pos);
+                    flat.push(Inst {
+                        cmd: InstType::Close,
+                        arg: 0,
+                        inc,
+                        delta,
+                    });
+                    map.push(pos);
+                }
+            }
+            map.resize(flat.len(), enclosing);
+        }
+    }
+    let mut flat = Vec::new();
+    let mut map = Vec::new();
+    flatten_block(&mut prog.into_iter().peekable(), &mut flat, &mut map, None);
+    link_brackets(&mut flat);
+    (flat, SourceMap::new(map))
+}
+
+/// Points every `Open` at its matching `Close` and vice versa.
+pub fn link_brackets(flat: &mut [Inst]) {
+    let mut stack = Vec::new();
+    for idx in 0..flat.len() {
+        match flat[idx].cmd {
+            InstType::Open => {
+                stack.push(idx);
+            }
+            InstType::Close => {
+                let open = stack.pop().unwrap();
+                flat[open].arg = idx as i32;
+                flat[idx].arg = open as i32;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Removes loops that can never be entered and retargets jumps that land on another branch.
+///
+/// A loop is dead when its cell is known to be zero: at the start of the program, right after
+/// another loop exits, or after an instruction that ends by zeroing the current cell.
+/// A failed `Open` leaves the pointer on a zero cell, so it can jump past any `Open`/`Close`
+/// that follows its `Close`. A `Close` of a loop without `inc`/`delta` can jump past the `Open`s
+/// without `inc`/`delta` that start its body. After this pass `arg` is a jump target and no longer
+/// necessarily the matching bracket.
+pub fn thread_jumps(prog: Vec<Inst>) -> Vec<Inst> {
+    thread_jumps_with_map(prog, &SourceMap::default(), true).0
+}
+
+/// [`thread_jumps`] that also drops the entries of removed instructions from `map`, and only
+/// treats the first cell as zero when `zeroed` says the tape starts out zeroed.
+pub fn thread_jumps_with_map(prog: Vec<Inst>, map: &SourceMap, zeroed: bool) -> (Vec<Inst>, SourceMap) {
+    let mut live = Vec::with_capacity(prog.len());
+    let mut live_map = Vec::with_capacity(prog.len());
+    let mut known_zero = zeroed;
+    let mut idx = 0;
+    while idx < prog.len() {
+        let inst = &prog[idx];
+        if inst.cmd == InstType::Open && known_zero {
+            idx = inst.arg as usize + 1;
+            continue;
+        }
+        known_zero = match inst.cmd {
+            InstType::Close | InstType::Skip | InstType::BoundedSeek => true,
+            InstType::Seek | InstType::Set => inst.inc == 0 && inst.delta == 0,
+            InstType::Mulzero => inst.delta == 0,
+            InstType::Mul => known_zero && inst.delta == 0,
+            InstType::Fill if (inst.arg..inst.arg.saturating_add(inst.delta as i32)).contains(&0) => inst.inc == 0,
+            InstType::Fill => known_zero,
+            InstType::BlockMove => true,
+            _ => false,
+        };
+        live.push(inst.clone());
+        live_map.push(map.loop_at(idx));
+        idx += 1;
+    }
+    link_brackets(&mut live);
+    for idx in 0..live.len() {
+        match live[idx].cmd {
+            InstType::Open => {
+                let mut target = live[idx].arg as usize;
+                while let Some(next) = live.get(target + 1) {
+                    match next.cmd {
+                        InstType::Open => target = next.arg as usize,
+                        InstType::Close => target += 1,
+                        _ => break,
+                    }
+                }
+                live[idx].arg = target as i32;
+            }
+            InstType::Close if live[idx].inc == 0 && live[idx].delta == 0 => {
+                let mut target = live[idx].arg as usize;
+                while let Some(next) = live.get(target + 1) {
+                    if next.cmd == InstType::Open && next.inc == 0 && next.delta == 0 {
+                        target += 1;
+                    } else {
+                        break;
+                    }
+                }
+                live[idx].arg = target as i32;
+            }
+            _ => {}
+        }
+    }
+    (live, SourceMap::new(live_map).with_labels(map.labels().clone()))
+}
+
+/// Fuses neighbouring instructions that only line up once the tree is flattened: a `ShiftInc`
+/// landing on the cell a `Set` or `ShiftInc` just wrote merges its increment into it, a pure
+/// shift merges into the move around it, and instructions that do nothing are dropped. `cell`
+/// is the width the increments are sign-extended to.
+pub fn peephole(prog: Vec<Inst>, cell: CellWidth) -> Vec<Inst> {
+    peephole_with_map(prog, &SourceMap::default(), cell).0
+}
+
+/// [`peephole`] that also drops the entries of fused and removed instructions from `map`.
+pub fn peephole_with_map(prog: Vec<Inst>, map: &SourceMap, cell: CellWidth) -> (Vec<Inst>, SourceMap) {
+    fn is_noop(inst: &Inst) -> bool {
+        match inst.cmd {
+            InstType::ShiftInc => inst.arg == 0 && inst.inc == 0 && inst.delta == 0,
+            InstType::Mul => inst.inc == 0 && inst.delta == 0,
+            InstType::Fill | InstType::BlockMove => inst.delta == 0,
+            _ => false,
+        }
+    }
+    // `next` is a `ShiftInc` run right after `prev`, whose `delta` is the last thing it does.
+    fn fuse(prev: &Inst, next: &Inst, cell: CellWidth) -> Option<Inst> {
+        let moves_last = matches!(
+            prev.cmd,
+            InstType::ShiftInc
+                | InstType::Set
+                | InstType::Output
+                | InstType::ErrOutput
+                | InstType::Input
+                | InstType::Random
+                | InstType::Mul
+                | InstType::Mulzero
+        );
+        if next.cmd != InstType::ShiftInc || !moves_last {
+            return None;
+        }
+        let between = next.arg.checked_add(prev.delta as i32)?;
+        if next.inc == 0 {
+            let delta = i16::try_from(between.checked_add(next.delta as i32)?).ok()?;
+            Some(Inst { delta, ..prev.clone() })
+        } else if between == 0 && matches!(prev.cmd, InstType::ShiftInc | InstType::Set) {
+            let inc = combine_inc(prev.inc, next.inc, cell)?;
+            Some(Inst {
+                inc,
+                delta: next.delta,
+                ..prev.clone()
+            })
+        } else if prev.cmd == InstType::ShiftInc && prev.inc == 0 {
+            Some(Inst {
+                arg: prev.arg.checked_add(between)?,
+                ..next.clone()
+            })
+        } else {
+            None
+        }
+    }
+    let mut live: Vec<Inst> = Vec::with_capacity(prog.len());
+    let mut live_map = Vec::with_capacity(prog.len());
+    for (idx, inst) in prog.into_iter().enumerate() {
+        if let Some(fused) = live.last().and_then(|prev| fuse(prev, &inst, cell)) {
+            *live.last_mut().unwrap() = fused;
+        } else {
+            live.push(inst);
+            live_map.push(map.loop_at(idx));
+        }
+        if live.last().is_some_and(is_noop) {
+            live.pop();
+            live_map.pop();
+        }
+    }
+    link_brackets(&mut live);
+    (live, SourceMap::new(live_map).with_labels(map.labels().clone()))
+}
+
+/// Appends the flat program `tail` to `prog`, moving its jump targets along with it.
+///
+/// `tail` runs from wherever `prog` leaves the pointer, so it should be compiled without assuming
+/// a zeroed tape. Nothing is fused or threaded across the seam.
+pub fn append(prog: &mut Vec<Inst>, tail: Vec<Inst>) {
+    let base = prog.len() as i32;
+    prog.extend(tail.into_iter().map(|mut inst| {
+        if matches!(inst.cmd, InstType::Open | InstType::Close) {
+            inst.arg += base;
+        }
+        inst
+    }));
+}
+
+/// Returns how many cells to the left of the start a program may touch, for `unsafe_run`.
+pub fn get_offset(prog: &Vec<Inst>) -> isize {
+    let mut offset = 0isize;
+    for inst in prog {
+        match inst.cmd {
+            InstType::Mul => {
+                offset = cmp::max(offset, -(inst.arg as isize));
+            }
+            InstType::Mulzero => {
+                offset = cmp::max(offset, -(inst.arg as isize));
+            }
+            InstType::Fill => {
+                offset = cmp::max(offset, -(inst.arg as isize));
+            }
+            InstType::BlockMove => {
+                offset = cmp::max(offset, -(inst.arg as isize));
+            }
+            _ => {}
+        }
+    }
+    offset
+}
+
+/// Returns the lowest and highest cells, relative to the start, that the pointer can reach, or
+/// `None` when that depends on the tape, as it does for scans and for loops that do not return
+/// the pointer to where they started.
+///
+/// A program whose range fits the tape can run without bounds checks.
+pub fn pointer_range(prog: &[Inst]) -> Option<(isize, isize)> {
+    analyze_ranges(prog).whole
+}
+
+/// Something that keeps [`pointer_range`] from bounding a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unbounded {
+    /// The scan at this index, which moves the pointer until it finds a zero.
+    Scan(usize),
+    /// The loop whose `Open` is at this index, which does not end its iterations where it starts
+    /// them.
+    Drift(usize),
+}
+
+/// Every scan and drifting loop that keeps [`pointer_range`] from bounding `prog`, in stream
+/// order. A loop is only listed when nothing inside it is.
+pub fn unbounded(prog: &[Inst]) -> Vec<Unbounded> {
+    analyze_ranges(prog).unbounded
+}
+
+/// A loop that keeps the pointer within `low..=high` cells of where its `Open` runs on every
+/// iteration, so it can run without bounds checks whenever those cells are on the tape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProvenLoop {
+    pub low: isize,
+    pub high: isize,
+    /// Index of the matching `Close`, which `arg` no longer is after [`thread_jumps`].
+    pub close: usize,
+}
+
+/// The [`pointer_range`] of every loop, indexed by the position of its `Open`. Loops that scan
+/// or drift, or contain one that does, are `None`, as is every other instruction.
+pub fn proven_loops(prog: &[Inst]) -> Vec<Option<ProvenLoop>> {
+    analyze_ranges(prog).loops
+}
+
+/// Indices of the `Open`s of the loops running at instruction `ip`, outermost first, including
+/// the loop of an `Open` or `Close` at `ip` itself. Read from the nesting of the brackets, since
+/// `arg` may no longer point at the matching one after [`thread_jumps`].
+pub fn loop_stack(prog: &[Inst], ip: usize) -> Vec<usize> {
+    let mut stack = Vec::new();
+    for (idx, inst) in prog.iter().enumerate().take(ip) {
+        match inst.cmd {
+            InstType::Open => stack.push(idx),
+            InstType::Close => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    if prog.get(ip).is_some_and(|inst| inst.cmd == InstType::Open) {
+        stack.push(ip);
+    }
+    stack
+}
+
+/// A loop being walked by [`analyze_ranges`], or the whole program at the bottom of the stack.
+struct Frame {
+    open: usize,
+    /// Lowest and highest cells the pointer may be on where the loop starts.
+    entry: (isize, isize),
+    low: isize,
+    high: isize,
+    bounded: bool,
+}
+
+impl Frame {
+    fn reach(&mut self, (from, to): (isize, isize)) {
+        self.low = self.low.min(from);
+        self.high = self.high.max(to);
+    }
+}
+
+/// What [`analyze_ranges`] finds: the [`pointer_range`] of the whole program, the
+/// [`proven_loops`] and what is [`unbounded`].
+struct Ranges {
+    whole: Option<(isize, isize)>,
+    loops: Vec<Option<ProvenLoop>>,
+    unbounded: Vec<Unbounded>,
+}
+
+fn analyze_ranges(prog: &[Inst]) -> Ranges {
+    let frame = |open, entry: (isize, isize)| Frame {
+        open,
+        entry,
+        low: entry.0,
+        high: entry.1,
+        bounded: true,
+    };
+    let mut loops = vec![None; prog.len()];
+    let mut causes = Vec::new();
+    let mut frames = vec![frame(prog.len(), (0, 0))];
+    // The pointer is only known to be within a range once a `BoundedSeek` has run. Bounds
+    // saturate, so one that overflows reaches past any tape rather than wrapping onto it.
+    let mut pos = (0isize, 0isize);
+    let at = |(low, high): (isize, isize), offset: isize| (low.saturating_add(offset), high.saturating_add(offset));
+    for (idx, inst) in prog.iter().enumerate() {
+        let (arg, delta) = (inst.arg as isize, inst.delta as isize);
+        let top = frames.last_mut().unwrap();
+        match inst.cmd {
+            InstType::ShiftInc
+            | InstType::Output
+            | InstType::ErrOutput
+            | InstType::Input
+            | InstType::Random
+            | InstType::Set => {
+                pos = at(pos, arg);
+                top.reach(pos);
+                pos = at(pos, delta);
+            }
+            InstType::Mul | InstType::Mulzero => {
+                top.reach(at(pos, arg));
+                pos = at(pos, delta);
+            }
+            InstType::Fill => top.reach(at((pos.0, pos.1.saturating_add(delta - 1)), arg)),
+            InstType::BlockMove => {
+                top.reach((pos.0, pos.1.saturating_add(delta - 1)));
+                top.reach(at((pos.0, pos.1.saturating_add(delta - 1)), arg));
+            }
+            InstType::BoundedSeek => {
+                let span = arg.saturating_mul(delta);
+                pos = (pos.0.saturating_add(span.min(0)), pos.1.saturating_add(span.max(0)));
+            }
+            InstType::Open => {
+                frames.push(frame(idx, pos));
+                pos = at(pos, delta);
+            }
+            InstType::Close => {
+                let inner = frames.pop().unwrap();
+                let bounded = inner.bounded && pos == inner.entry;
+                if inner.bounded && !bounded {
+                    causes.push(Unbounded::Drift(inner.open));
+                }
+                if bounded {
+                    loops[inner.open] = Some(ProvenLoop {
+                        low: inner.low.saturating_sub(inner.entry.0),
+                        high: inner.high.saturating_sub(inner.entry.1),
+                        close: idx,
+                    });
+                }
+                let outer = frames.last_mut().unwrap();
+                outer.reach((inner.low, inner.high));
+                outer.bounded &= bounded;
+                pos = inner.entry;
+            }
+            InstType::Seek | InstType::Skip | InstType::SkipInc => {
+                // A run of `SkipInc`s and its `Skip` are one scan.
+                if idx == 0 || prog[idx - 1].cmd != InstType::SkipInc {
+                    causes.push(Unbounded::Scan(idx));
+                }
+                top.bounded = false;
+            }
+        }
+        frames.last_mut().unwrap().reach(pos);
+    }
+    // Drifting loops are found at their `Close`, after the scans and loops they contain.
+    causes.sort_by_key(|cause| match *cause {
+        Unbounded::Scan(idx) | Unbounded::Drift(idx) => idx,
+    });
+    let whole = &frames[0];
+    Ranges {
+        whole: whole.bounded.then_some((whole.low, whole.high)),
+        loops,
+        unbounded: causes,
+    }
+}
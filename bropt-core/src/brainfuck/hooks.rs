@@ -0,0 +1,132 @@
+//! Callbacks an instrumented run reports to, for profilers, tracers, coverage and debuggers.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::ir::Inst;
+
+/// A byte crossing the program's I/O.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IoEvent {
+    /// `,` read a byte, or hit the end of input.
+    Read(Option<u8>),
+    /// `.` wrote a byte.
+    Write(u8),
+    /// `:` wrote a byte to the error stream.
+    WriteErr(u8),
+}
+
+/// Observes an instrumented run. Every method defaults to a no-op, so only the events
+/// an implementation overrides cost anything.
+///
+/// `ip` is the index of the instruction in the flat stream and `dp` the pointer.
+pub trait ExecHooks {
+    /// Called before each instruction executes.
+    #[inline(always)]
+    fn on_instruction(&mut self, _ip: usize, _inst: &Inst, _dp: usize) {}
+
+    /// Called when an `Open` finds a nonzero cell and runs the body.
+    #[inline(always)]
+    fn on_loop_enter(&mut self, _ip: usize, _dp: usize) {}
+
+    /// Called when a `Close` finds a zero cell and falls through.
+    #[inline(always)]
+    fn on_loop_exit(&mut self, _ip: usize, _dp: usize) {}
+
+    /// Called on every `,`, `.` and `:`, after the byte is read or before it is written.
+    #[inline(always)]
+    fn on_io(&mut self, _ip: usize, _dp: usize, _event: IoEvent) {}
+
+    /// Polled before each instruction; returning `true` stops the run there, leaving it resumable.
+    #[inline(always)]
+    fn should_stop(&mut self) -> bool {
+        false
+    }
+
+    /// Polled after [`should_stop`](Self::should_stop) with the instruction about to execute
+    /// and `cell`, which reads the tape and returns `None` past its end; returning `true` stops
+    /// the run there, leaving it resumable. For breakpoints, which need to know where they are.
+    #[inline(always)]
+    fn break_before(&mut self, _ip: usize, _dp: usize, _cell: impl Fn(usize) -> Option<u32>) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+}
+
+/// Hooks that observe nothing and compile away.
+pub struct NoHooks;
+
+impl ExecHooks for NoHooks {}
+
+/// Stops a run right after its `.` output so far ends with a byte pattern, so the state that
+/// printed it can be inspected. Matches overlapping an earlier one count, and
+/// [`clear`](Self::clear) lets a resumed run go on to the next.
+pub struct OutputMatch {
+    pattern: Vec<u8>,
+    /// For each prefix of the pattern, the length of its longest proper prefix that is also
+    /// its suffix, where matching falls back to on a mismatch.
+    fallback: Vec<usize>,
+    /// Bytes of the pattern the output currently ends with.
+    matched: usize,
+    found: bool,
+}
+
+impl OutputMatch {
+    /// Panics if `pattern` is empty, which would match before any output.
+    pub fn new(pattern: &[u8]) -> Self {
+        assert!(!pattern.is_empty(), "the output pattern is empty");
+        let mut fallback = vec![0; pattern.len()];
+        let mut len = 0;
+        for pos in 1..pattern.len() {
+            while len > 0 && pattern[pos] != pattern[len] {
+                len = fallback[len - 1];
+            }
+            if pattern[pos] == pattern[len] {
+                len += 1;
+            }
+            fallback[pos] = len;
+        }
+        OutputMatch {
+            pattern: pattern.to_vec(),
+            fallback,
+            matched: 0,
+            found: false,
+        }
+    }
+
+    /// Whether the output matched and stopped the run.
+    pub fn found(&self) -> bool {
+        self.found
+    }
+
+    /// Forgets the match, so a resumed run stops at the next one.
+    pub fn clear(&mut self) {
+        self.found = false;
+    }
+}
+
+impl ExecHooks for OutputMatch {
+    #[inline(always)]
+    fn on_io(&mut self, _ip: usize, _dp: usize, event: IoEvent) {
+        let IoEvent::Write(byte) = event else {
+            return;
+        };
+        while self.matched > 0 && self.pattern[self.matched] != byte {
+            self.matched = self.fallback[self.matched - 1];
+        }
+        if self.pattern[self.matched] == byte {
+            self.matched += 1;
+        }
+        if self.matched == self.pattern.len() {
+            self.found = true;
+            self.matched = self.fallback[self.matched - 1];
+        }
+    }
+
+    #[inline(always)]
+    fn should_stop(&mut self) -> bool {
+        self.found
+    }
+}
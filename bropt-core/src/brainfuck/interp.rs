@@ -0,0 +1,705 @@
+//! Interpreters for the flat [`Inst`] stream.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::{fmt, slice};
+
+use super::flatten::{ProvenLoop, loop_stack, proven_loops};
+use super::hooks::{ExecHooks, IoEvent, NoHooks};
+use super::io::{ByteRead, ByteWrite, SliceReader, SplitOutput};
+#[cfg(feature = "std")]
+use super::io::{FlushPolicy, Stdin, Stdout};
+use super::ir::{CellWidth, Inst, InstType, SourceMap};
+use super::limit::{OutputLimit, ResourceExhausted};
+use super::rng::Rng;
+use super::tape::Memory;
+
+/// What `,` stores once the input is exhausted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Eof {
+    #[default]
+    Zero,
+    MinusOne,
+    Unchanged,
+}
+
+/// A checked run touched a cell outside its tape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds {
+    /// The instruction that touched the cell.
+    pub ip: usize,
+    /// The cell, negative when it is left of the tape.
+    pub pos: isize,
+    /// Number of cells in the tape.
+    pub len: usize,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instruction {} touched cell {}, outside the {}-cell tape",
+            self.ip, self.pos, self.len
+        )
+    }
+}
+
+impl core::error::Error for OutOfBounds {}
+
+impl OutOfBounds {
+    /// The error followed by a line for every loop that was running, innermost first, named from
+    /// `map`, the source map of `prog`, as far as it knows them.
+    pub fn with_loops(&self, prog: &[Inst], map: &SourceMap) -> String {
+        let mut text = self.to_string();
+        for open in loop_stack(prog, self.ip).into_iter().rev() {
+            match map.describe_loop(open) {
+                Some(place) => text.push_str(&format!("\n    in {place}")),
+                None => text.push_str(&format!("\n    in the loop at instruction {open}")),
+            }
+        }
+        text
+    }
+}
+
+/// Tape cell type.
+pub trait Cell: Copy + PartialEq {
+    const WIDTH: CellWidth;
+    const ZERO: Self;
+    const MAX: Self;
+    fn from_byte(byte: u8) -> Self;
+    fn from_inc(inc: u8) -> Self;
+    fn to_byte(self) -> u8;
+    fn to_u32(self) -> u32;
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+}
+
+/// Arithmetic the interpreters apply to cells.
+pub trait CellOps {
+    fn add<C: Cell>(lhs: C, rhs: C) -> C;
+    fn mul<C: Cell>(lhs: C, rhs: C) -> C;
+}
+
+/// Cells wrap around at their width, which is the semantics every program is compiled for.
+pub struct Wrapping;
+
+impl CellOps for Wrapping {
+    #[inline(always)]
+    fn add<C: Cell>(lhs: C, rhs: C) -> C {
+        lhs.wrapping_add(rhs)
+    }
+
+    #[inline(always)]
+    fn mul<C: Cell>(lhs: C, rhs: C) -> C {
+        lhs.wrapping_mul(rhs)
+    }
+}
+
+macro_rules! impl_cell {
+    ($ty:ty, $width:ident) => {
+        impl Cell for $ty {
+            const WIDTH: CellWidth = CellWidth::$width;
+            const ZERO: Self = 0;
+            const MAX: Self = <$ty>::MAX;
+            #[inline(always)]
+            fn from_byte(byte: u8) -> Self {
+                byte as $ty
+            }
+            #[inline(always)]
+            fn from_inc(inc: u8) -> Self {
+                inc as i8 as $ty
+            }
+            #[inline(always)]
+            fn to_byte(self) -> u8 {
+                self as u8
+            }
+            #[inline(always)]
+            fn to_u32(self) -> u32 {
+                self as u32
+            }
+            #[inline(always)]
+            fn wrapping_add(self, rhs: Self) -> Self {
+                <$ty>::wrapping_add(self, rhs)
+            }
+            #[inline(always)]
+            fn wrapping_mul(self, rhs: Self) -> Self {
+                <$ty>::wrapping_mul(self, rhs)
+            }
+        }
+    };
+}
+
+impl_cell!(u8, U8);
+impl_cell!(u16, U16);
+impl_cell!(u32, U32);
+
+#[inline(always)]
+pub(crate) fn read_cell<C: Cell>(byte: Option<u8>, eof: Eof, cell: C) -> C {
+    match byte {
+        Some(byte) => C::from_byte(byte),
+        None => match eof {
+            Eof::Zero => C::ZERO,
+            Eof::MinusOne => C::MAX,
+            Eof::Unchanged => cell,
+        },
+    }
+}
+
+/// Runs on a zeroed tape of `length` 8-bit cells with stdin/stdout, with bounds checks.
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+#[inline]
+pub fn run(prog: Vec<Inst>, length: usize, flush: FlushPolicy) {
+    run_with_io(&prog, length, &mut Stdin, &mut Stdout::new(flush));
+}
+
+/// Runs with `input` as the whole input and returns the output, what `:` wrote to the error
+/// stream, the final tape and the pointer. Panics if the program touches a cell outside the tape,
+/// as do the other `run_*` functions.
+#[allow(dead_code)]
+#[inline]
+pub fn run_with_state(prog: &[Inst], length: usize, input: &[u8]) -> (Vec<u8>, Vec<u8>, Vec<u8>, usize) {
+    let mut data = vec![0u8; length];
+    let mut output = SplitOutput::default();
+    let dp = execute_proven::<_, Wrapping, _, _, _>(
+        prog,
+        &mut data[..],
+        Eof::Zero,
+        &mut SliceReader::new(input),
+        &mut output,
+        &mut Rng::default(),
+    )
+    .unwrap_or_else(|err| panic!("{err}"));
+    (output.out, output.err, data, dp)
+}
+
+/// [`run_with_state`] on a tape whose first cells start out as `tape`, for programs built by
+/// [`compile_preloaded`](super::compile_preloaded). Panics if `tape` is longer than `length`.
+/// Bytes written with `:` are dropped.
+#[allow(dead_code)]
+#[inline]
+pub fn run_with_state_preloaded(prog: &[Inst], length: usize, tape: &[u8], input: &[u8]) -> (Vec<u8>, Vec<u8>, usize) {
+    let mut data = vec![0u8; length];
+    data[..tape.len()].copy_from_slice(tape);
+    let mut output = Vec::new();
+    let dp = execute_proven::<_, Wrapping, _, _, _>(
+        prog,
+        &mut data[..],
+        Eof::Zero,
+        &mut SliceReader::new(input),
+        &mut output,
+        &mut Rng::default(),
+    )
+    .unwrap_or_else(|err| panic!("{err}"));
+    (output, data, dp)
+}
+
+/// [`run_with_state_preloaded`] allowing the tape and the output together at most `max_memory`
+/// bytes.
+///
+/// A tape longer than the limit is refused before it is allocated, and the run stops once the
+/// output outgrows what is left.
+#[allow(dead_code)]
+#[inline]
+pub fn run_with_state_limited(
+    prog: &[Inst],
+    length: usize,
+    tape: &[u8],
+    input: &[u8],
+    max_memory: usize,
+) -> Result<(Vec<u8>, Vec<u8>, usize), ResourceExhausted> {
+    if length > max_memory {
+        return Err(ResourceExhausted {
+            limit: max_memory,
+            requested: length,
+        });
+    }
+    let mut data = vec![0u8; length];
+    data[..tape.len()].copy_from_slice(tape);
+    let mut output = Vec::new();
+    let mut limit = OutputLimit::new(max_memory - length);
+    let dp = execute_with_hooks::<_, Wrapping, _, _, _, _>(
+        prog,
+        &mut data[..],
+        Eof::Zero,
+        &mut SliceReader::new(input),
+        &mut output,
+        &mut Rng::default(),
+        &mut limit,
+    )
+    .unwrap_or_else(|err| panic!("{err}"));
+    if limit.exceeded() {
+        return Err(ResourceExhausted {
+            limit: max_memory,
+            requested: length + output.len(),
+        });
+    }
+    Ok((output, data, dp))
+}
+
+/// Runs with the given I/O and returns the final tape and the pointer.
+#[allow(dead_code)]
+#[inline]
+pub fn run_with_io<R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    length: usize,
+    input: &mut R,
+    output: &mut W,
+) -> (Vec<u8>, usize) {
+    let mut data = vec![0u8; length];
+    let dp = execute_proven::<_, Wrapping, _, _, _>(prog, &mut data[..], Eof::Zero, input, output, &mut Rng::default())
+        .unwrap_or_else(|err| panic!("{err}"));
+    (data, dp)
+}
+
+/// Runs on a caller-provided tape, which is cleared first, without allocating.
+#[allow(dead_code)]
+#[inline]
+pub fn run_static<const N: usize, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    tape: &mut [u8; N],
+    input: &mut R,
+    output: &mut W,
+) -> usize {
+    tape.fill(0);
+    execute::<_, Wrapping, _, _, _>(prog, &mut tape[..], Eof::Zero, input, output, &mut Rng::default())
+        .unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Bounds-checked interpreter loop over an existing tape, dense or sparse, drawing the bytes `?`
+/// stores from `rng`. Returns the final pointer, or where the program left the tape.
+#[inline(always)]
+pub fn execute<C: Cell, O: CellOps, M: Memory<C> + ?Sized, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    data: &mut M,
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+) -> Result<usize, OutOfBounds> {
+    execute_with_hooks::<C, O, M, _, R, W>(prog, data, eof, input, output, rng, &mut NoHooks)
+}
+
+/// [`execute`] that runs each loop [`proven_loops`] bounds without checks whenever the pointer is
+/// far enough inside a contiguous tape, so only the code around those loops pays for them.
+#[inline(always)]
+pub fn execute_proven<C: Cell, O: CellOps, M: Memory<C> + ?Sized, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    data: &mut M,
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+) -> Result<usize, OutOfBounds> {
+    let loops = proven_loops(prog);
+    Ok(resume::<C, O, M, NoHooks, R, W>(prog, &loops, data, (0, 0), eof, input, output, rng, &mut NoHooks)?.1)
+}
+
+/// [`execute`] reporting every step to `hooks`. Returns the final pointer, or where the program
+/// left the tape.
+#[inline(always)]
+pub fn execute_with_hooks<C: Cell, O: CellOps, M: Memory<C> + ?Sized, H: ExecHooks, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    data: &mut M,
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+    hooks: &mut H,
+) -> Result<usize, OutOfBounds> {
+    Ok(resume_with_hooks::<C, O, M, H, R, W>(prog, data, (0, 0), eof, input, output, rng, hooks)?.1)
+}
+
+/// [`execute_with_hooks`] starting from the instruction and pointer in `start`, such as where an
+/// earlier run was stopped by [`ExecHooks::should_stop`].
+///
+/// Returns the instruction and pointer it stopped at; the instruction is `prog.len()` once the
+/// program has finished. The pointer may leave the tape between accesses, but touching a cell
+/// outside it ends the run with [`OutOfBounds`].
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+pub fn resume_with_hooks<C: Cell, O: CellOps, M: Memory<C> + ?Sized, H: ExecHooks, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    data: &mut M,
+    start: (usize, usize),
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+    hooks: &mut H,
+) -> Result<(usize, usize), OutOfBounds> {
+    resume::<C, O, M, H, R, W>(prog, &[], data, start, eof, input, output, rng, hooks)
+}
+
+/// The checked interpreter loop, handing the loops in `loops` to [`unchecked_loop`] when their
+/// cells are on the tape. Only hook-free runs pass any, since unchecked loops report nothing.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+fn resume<C: Cell, O: CellOps, M: Memory<C> + ?Sized, H: ExecHooks, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    loops: &[Option<ProvenLoop>],
+    data: &mut M,
+    start: (usize, usize),
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+    hooks: &mut H,
+) -> Result<(usize, usize), OutOfBounds> {
+    let len = data.len();
+    let (mut ip, mut dp) = start;
+    while ip < prog.len() {
+        if hooks.should_stop() || hooks.break_before(ip, dp, |pos| (pos < len).then(|| data.get(pos).to_u32())) {
+            break;
+        }
+        let Inst { cmd, arg, inc, delta } = &prog[ip];
+        hooks.on_instruction(ip, &prog[ip], dp);
+        if *cmd == InstType::ShiftInc {
+            let pos = cell_at(dp, *arg as isize, len, ip)?;
+            data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Output {
+            if output.would_block() {
+                break;
+            }
+            let pos = cell_at(dp, *arg as isize, len, ip)?;
+            let byte = data.get(pos).to_byte();
+            hooks.on_io(ip, pos, IoEvent::Write(byte));
+            output.write_byte(byte);
+            data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::ErrOutput {
+            let pos = cell_at(dp, *arg as isize, len, ip)?;
+            let byte = data.get(pos).to_byte();
+            hooks.on_io(ip, pos, IoEvent::WriteErr(byte));
+            output.write_err(byte);
+            data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Input {
+            if input.would_block() {
+                break;
+            }
+            let pos = cell_at(dp, *arg as isize, len, ip)?;
+            output.flush();
+            let byte = input.read_byte();
+            hooks.on_io(ip, pos, IoEvent::Read(byte));
+            data.set(pos, read_cell(byte, eof, data.get(pos)));
+            data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Seek {
+            while data.get(cell_at(dp, 0, len, ip)?) != C::ZERO {
+                dp = dp.wrapping_add_signed(*arg as isize);
+            }
+            let pos = cell_at(dp, *delta as isize, len, ip)?;
+            data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+            dp = pos;
+        } else if *cmd == InstType::BoundedSeek {
+            for _ in 0..*delta {
+                if data.get(cell_at(dp, 0, len, ip)?) == C::ZERO {
+                    break;
+                }
+                dp = dp.wrapping_add_signed(*arg as isize);
+            }
+        } else if *cmd == InstType::Skip {
+            while data.get(cell_at(dp, 0, len, ip)?) != C::ZERO {
+                let pos = cell_at(dp, *delta as isize, len, ip)?;
+                data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+                dp = dp.wrapping_add_signed(*arg as isize);
+            }
+        } else if *cmd == InstType::Set {
+            let pos = cell_at(dp, *arg as isize, len, ip)?;
+            data.set(pos, C::from_inc(*inc));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Mul {
+            let src = cell_at(dp, 0, len, ip)?;
+            if data.get(src) != C::ZERO {
+                let pos = cell_at(dp, *arg as isize, len, ip)?;
+                data.set(pos, O::add(data.get(pos), O::mul(data.get(src), C::from_inc(*inc))));
+            }
+            dp = dp.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Mulzero {
+            let src = cell_at(dp, 0, len, ip)?;
+            if data.get(src) != C::ZERO {
+                let pos = cell_at(dp, *arg as isize, len, ip)?;
+                data.set(pos, O::add(data.get(pos), O::mul(data.get(src), C::from_inc(*inc))));
+                data.set(src, C::ZERO);
+            }
+            dp = dp.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::Fill {
+            let start = cells_at(dp, *arg as isize, *delta as usize, len, ip)?;
+            data.fill(start, *delta as usize, C::from_inc(*inc));
+        } else if *cmd == InstType::BlockMove {
+            let src = cells_at(dp, 0, *delta as usize, len, ip)?;
+            let dst = cells_at(dp, *arg as isize, *delta as usize, len, ip)?;
+            data.copy_within(src, *delta as usize, dst);
+            data.fill(src, *delta as usize, C::ZERO);
+        } else if *cmd == InstType::Random {
+            let pos = cell_at(dp, *arg as isize, len, ip)?;
+            data.set(pos, O::add(C::from_byte(rng.byte()), C::from_inc(*inc)));
+            dp = pos.wrapping_add_signed(*delta as isize);
+        } else if *cmd == InstType::SkipInc {
+            let end = skip_end(prog, ip);
+            while data.get(cell_at(dp, 0, len, ip)?) != C::ZERO {
+                for step in &prog[ip..=end] {
+                    let pos = cell_at(dp, step.delta as isize, len, ip)?;
+                    data.set(pos, O::add(data.get(pos), C::from_inc(step.inc)));
+                }
+                dp = dp.wrapping_add_signed(prog[end].arg as isize);
+            }
+            ip = end;
+        } else if *cmd == InstType::Open {
+            if let Some(Some(proof)) = loops.get(ip)
+                && (dp as isize).saturating_add(proof.low) >= 0
+                && (dp as isize).saturating_add(proof.high) < len as isize
+                && let Some(cells) = data.as_mut_slice()
+            {
+                // Every iteration stays within `low..=high` of `dp`, and both ends are on the tape.
+                (ip, dp) = unsafe {
+                    unchecked_loop::<C, O, R, W>(
+                        prog,
+                        (ip, proof.close + 1),
+                        cells,
+                        dp as isize,
+                        eof,
+                        input,
+                        output,
+                        rng,
+                    )
+                };
+                // Stepping back rather than skipping the `ip += 1` below keeps this loop as fast as
+                // the one without proofs.
+                ip -= 1;
+            } else {
+                let pos = cell_at(dp, 0, len, ip)?;
+                if data.get(pos) == C::ZERO {
+                    ip = *arg as usize;
+                } else {
+                    hooks.on_loop_enter(ip, dp);
+                    data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+                    dp = dp.wrapping_add_signed(*delta as isize);
+                }
+            }
+        } else
+        /* if *cmd == InstType::Close */
+        {
+            let pos = cell_at(dp, 0, len, ip)?;
+            if data.get(pos) != C::ZERO {
+                ip = *arg as usize;
+                data.set(pos, O::add(data.get(pos), C::from_inc(*inc)));
+                dp = dp.wrapping_add_signed(*delta as isize);
+            } else {
+                hooks.on_loop_exit(ip, dp);
+            }
+        }
+        ip += 1;
+    }
+    Ok((ip, dp))
+}
+
+/// The cell `offset` away from `dp`, or the error for instruction `ip` if it is not on a tape of
+/// `len` cells.
+#[inline(always)]
+fn cell_at(dp: usize, offset: isize, len: usize, ip: usize) -> Result<usize, OutOfBounds> {
+    let pos = dp.wrapping_add_signed(offset);
+    if pos < len {
+        Ok(pos)
+    } else {
+        Err(OutOfBounds {
+            ip,
+            pos: pos as isize,
+            len,
+        })
+    }
+}
+
+/// Index of the `Skip` that ends the run of `SkipInc`s at `ip`.
+#[inline(always)]
+pub(crate) fn skip_end(prog: &[Inst], ip: usize) -> usize {
+    ip + prog[ip..].iter().position(|inst| inst.cmd == InstType::Skip).unwrap()
+}
+
+/// The first of the `count` cells starting `offset` away from `dp`, if they all fit the tape.
+#[inline(always)]
+fn cells_at(dp: usize, offset: isize, count: usize, len: usize, ip: usize) -> Result<usize, OutOfBounds> {
+    let start = cell_at(dp, offset, len, ip)?;
+    cell_at(start, count.saturating_sub(1) as isize, len, ip)?;
+    Ok(start)
+}
+
+/// Runs with stdin/stdout without bounds checks, starting `offset` cells into the tape.
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+#[inline]
+pub fn unsafe_run(prog: Vec<Inst>, length: usize, offset: isize, flush: FlushPolicy) {
+    unsafe_run_with_io(&prog, length, offset, &mut Stdin, &mut Stdout::new(flush));
+}
+
+/// Runs with the given I/O without bounds checks, starting `offset` cells into the tape.
+#[allow(dead_code)]
+#[inline]
+pub fn unsafe_run_with_io<R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    length: usize,
+    offset: isize,
+    input: &mut R,
+    output: &mut W,
+) {
+    let mut data = vec![0u8; length];
+    unsafe {
+        unsafe_execute::<_, Wrapping, _, _>(prog, &mut data, offset, Eof::Zero, input, output, &mut Rng::default())
+    };
+}
+
+/// Unchecked interpreter loop over an existing tape. Returns the final pointer.
+///
+/// # Safety
+/// The program must keep the pointer inside `data` when started at `offset`.
+#[inline(always)]
+pub unsafe fn unsafe_execute<C: Cell, O: CellOps, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    data: &mut [C],
+    offset: isize,
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+) -> usize {
+    unsafe { unchecked_range::<C, O, R, W>(prog, (0, prog.len()), data, offset, eof, input, output, rng).1 }
+}
+
+/// [`unchecked_range`] kept out of line, so the checked loop it is called from compiles as
+/// tightly as it would without it.
+///
+/// # Safety
+/// As for [`unchecked_range`].
+#[allow(clippy::too_many_arguments)]
+#[inline(never)]
+unsafe fn unchecked_loop<C: Cell, O: CellOps, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    range: (usize, usize),
+    data: &mut [C],
+    dp: isize,
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+) -> (usize, usize) {
+    unsafe { unchecked_range::<C, O, R, W>(prog, range, data, dp, eof, input, output, rng) }
+}
+
+/// Runs the instructions of `prog` from `ip` with the pointer at `dp` until the instruction
+/// reaches `end`, and returns where both stopped. Jumps out of the range may land past `end`.
+///
+/// # Safety
+/// Every cell the instructions touch before leaving the range must be inside `data`.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+unsafe fn unchecked_range<C: Cell, O: CellOps, R: ByteRead, W: ByteWrite>(
+    prog: &[Inst],
+    (mut ip, end): (usize, usize),
+    data: &mut [C],
+    dp: isize,
+    eof: Eof,
+    input: &mut R,
+    output: &mut W,
+    rng: &mut Rng,
+) -> (usize, usize) {
+    unsafe {
+        let base = data.as_mut_ptr();
+        let mut ptr = base.offset(dp);
+        while ip < end {
+            let Inst { cmd, arg, inc, delta } = &prog[ip];
+            if *cmd == InstType::Output {
+                ptr = ptr.offset(*arg as isize);
+                output.write_byte(ptr.read().to_byte());
+                ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::ErrOutput {
+                ptr = ptr.offset(*arg as isize);
+                output.write_err(ptr.read().to_byte());
+                ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::Input {
+                ptr = ptr.offset(*arg as isize);
+                output.flush();
+                ptr.write(read_cell(input.read_byte(), eof, ptr.read()));
+                ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::ShiftInc {
+                ptr = ptr.offset(*arg as isize);
+                ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::Seek {
+                while ptr.read() != C::ZERO {
+                    ptr = ptr.offset(*arg as isize);
+                }
+                ptr = ptr.offset(*delta as isize);
+                ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+            } else if *cmd == InstType::BoundedSeek {
+                for _ in 0..*delta {
+                    if ptr.read() == C::ZERO {
+                        break;
+                    }
+                    ptr = ptr.offset(*arg as isize);
+                }
+            } else if *cmd == InstType::Skip {
+                while ptr.read() != C::ZERO {
+                    let pos = ptr.offset(*delta as isize);
+                    pos.write(O::add(pos.read(), C::from_inc(*inc)));
+                    ptr = ptr.offset(*arg as isize);
+                }
+            } else if *cmd == InstType::Set {
+                ptr = ptr.offset(*arg as isize);
+                ptr.write(C::from_inc(*inc));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::Mulzero {
+                let pos = ptr.offset(*arg as isize);
+                pos.write(O::add(pos.read(), O::mul(ptr.read(), C::from_inc(*inc))));
+                ptr.write(C::ZERO);
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::Mul {
+                let pos = ptr.offset(*arg as isize);
+                pos.write(O::add(pos.read(), O::mul(ptr.read(), C::from_inc(*inc))));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::Fill {
+                slice::from_raw_parts_mut(ptr.offset(*arg as isize), *delta as usize).fill(C::from_inc(*inc));
+            } else if *cmd == InstType::BlockMove {
+                core::ptr::copy_nonoverlapping(ptr, ptr.offset(*arg as isize), *delta as usize);
+                slice::from_raw_parts_mut(ptr, *delta as usize).fill(C::ZERO);
+            } else if *cmd == InstType::Random {
+                ptr = ptr.offset(*arg as isize);
+                ptr.write(O::add(C::from_byte(rng.byte()), C::from_inc(*inc)));
+                ptr = ptr.offset(*delta as isize);
+            } else if *cmd == InstType::SkipInc {
+                let end = skip_end(prog, ip);
+                while ptr.read() != C::ZERO {
+                    for step in &prog[ip..=end] {
+                        let pos = ptr.offset(step.delta as isize);
+                        pos.write(O::add(pos.read(), C::from_inc(step.inc)));
+                    }
+                    ptr = ptr.offset(prog[end].arg as isize);
+                }
+                ip = end;
+            } else if *cmd == InstType::Open {
+                if ptr.read() == C::ZERO {
+                    ip = *arg as usize;
+                } else {
+                    ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+                    ptr = ptr.offset(*delta as isize);
+                }
+            } else
+            /* if *cmd == InstType::Close */
+            {
+                if ptr.read() != C::ZERO {
+                    ip = *arg as usize;
+                    ptr.write(O::add(ptr.read(), C::from_inc(*inc)));
+                    ptr = ptr.offset(*delta as isize);
+                }
+            }
+            ip += 1;
+        }
+        (ip, ptr.offset_from(base) as usize)
+    }
+}
@@ -0,0 +1,453 @@
+//! Byte I/O used by the interpreters.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+/// Source of `,` input. `None` means end of input.
+pub trait ByteRead {
+    fn read_byte(&mut self) -> Option<u8>;
+
+    /// Whether the next byte has not arrived yet, although the input has not ended. Resumable
+    /// runs stop before a `,` while this holds, so the caller can supply more and resume; other
+    /// runs read regardless.
+    #[inline(always)]
+    fn would_block(&mut self) -> bool {
+        false
+    }
+}
+
+/// Sink of `.` output.
+pub trait ByteWrite {
+    fn write_byte(&mut self, byte: u8);
+
+    /// Takes a byte `:` writes to the error stream. Sinks without one drop it.
+    #[inline(always)]
+    fn write_err(&mut self, _byte: u8) {}
+
+    /// Whether the sink cannot take another byte until the caller drains it. Resumable runs stop
+    /// before a `.` while this holds; other runs write regardless.
+    #[inline(always)]
+    fn would_block(&mut self) -> bool {
+        false
+    }
+
+    /// Passes on any output the sink holds back. Every interpreter calls it before each `,`
+    /// reads, so a prompt is out before the program waits for the answer; callers that report
+    /// on a stopped run or exit without dropping the sink call it too.
+    #[inline(always)]
+    fn flush(&mut self) {}
+}
+
+impl<F: FnMut() -> Option<u8>> ByteRead for F {
+    #[inline]
+    fn read_byte(&mut self) -> Option<u8> {
+        self()
+    }
+}
+
+impl<F: FnMut(u8)> ByteWrite for F {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        self(byte)
+    }
+}
+
+/// Reads input from a byte slice.
+pub struct SliceReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        SliceReader { input, pos: 0 }
+    }
+}
+
+impl ByteRead for SliceReader<'_> {
+    #[inline]
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.input.get(self.pos).copied();
+        self.pos += 1;
+        byte
+    }
+}
+
+/// Reads the input that has arrived so far, then asks the run to wait for more instead of
+/// reaching the end of input.
+pub struct PartialInput<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PartialInput<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        PartialInput { input, pos: 0 }
+    }
+
+    /// Bytes not read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.input[self.pos..]
+    }
+}
+
+impl ByteRead for PartialInput<'_> {
+    #[inline]
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.input.get(self.pos).copied();
+        self.pos += 1;
+        byte
+    }
+
+    #[inline]
+    fn would_block(&mut self) -> bool {
+        self.pos >= self.input.len()
+    }
+}
+
+impl ByteWrite for Vec<u8> {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        self.push(byte);
+    }
+}
+
+/// Collects the output and the error stream apart.
+#[derive(Debug, Default, Clone)]
+pub struct SplitOutput {
+    pub out: Vec<u8>,
+    pub err: Vec<u8>,
+}
+
+impl ByteWrite for SplitOutput {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        self.out.push(byte);
+    }
+
+    #[inline]
+    fn write_err(&mut self, byte: u8) {
+        self.err.push(byte);
+    }
+}
+
+/// Collects at most `capacity` bytes of output, then asks the run to wait until the caller has
+/// taken them.
+pub struct OutputChunk {
+    bytes: Vec<u8>,
+    capacity: usize,
+}
+
+impl OutputChunk {
+    pub fn new(capacity: usize) -> Self {
+        OutputChunk {
+            bytes: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Whether the chunk holds `capacity` bytes.
+    pub fn is_full(&self) -> bool {
+        self.bytes.len() >= self.capacity
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl ByteWrite for OutputChunk {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    #[inline]
+    fn would_block(&mut self) -> bool {
+        self.is_full()
+    }
+}
+
+/// Passes output through to `inner` as valid UTF-8, holding back bytes until their sequence is
+/// complete and writing U+FFFD for each invalid sequence, for programs that emit multi-byte text.
+pub struct Utf8Lossy<W> {
+    inner: W,
+    pending: [u8; 4],
+    len: usize,
+}
+
+impl<W: ByteWrite> Utf8Lossy<W> {
+    pub fn new(inner: W) -> Self {
+        Utf8Lossy {
+            inner,
+            pending: [0; 4],
+            len: 0,
+        }
+    }
+
+    /// Writes U+FFFD for a sequence the output ended in the middle of and returns `inner`.
+    pub fn finish(mut self) -> W {
+        if self.len > 0 {
+            self.write_replacement();
+        }
+        self.inner
+    }
+
+    fn write_replacement(&mut self) {
+        for &byte in "\u{FFFD}".as_bytes() {
+            self.inner.write_byte(byte);
+        }
+    }
+}
+
+/// Writes output as space-separated hex pairs, sixteen to a line.
+pub struct HexDump<W> {
+    inner: W,
+    column: usize,
+}
+
+impl<W: ByteWrite> HexDump<W> {
+    pub fn new(inner: W) -> Self {
+        HexDump { inner, column: 0 }
+    }
+
+    /// Ends the last line and returns `inner`.
+    pub fn finish(mut self) -> W {
+        if self.column > 0 {
+            self.inner.write_byte(b'\n');
+        }
+        self.inner
+    }
+}
+
+impl<W: ByteWrite> ByteWrite for HexDump<W> {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        if self.column > 0 {
+            self.inner.write_byte(b' ');
+        }
+        self.inner.write_byte(DIGITS[(byte >> 4) as usize]);
+        self.inner.write_byte(DIGITS[(byte & 15) as usize]);
+        self.column += 1;
+        if self.column == 16 {
+            self.inner.write_byte(b'\n');
+            self.column = 0;
+        }
+    }
+
+    #[inline]
+    fn write_err(&mut self, byte: u8) {
+        self.inner.write_err(byte);
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+}
+
+/// Writes output with Rust-style escapes for every byte that is not printable ASCII, breaking
+/// the line after each escaped newline so the result still diffs line by line.
+pub struct Escaped<W> {
+    inner: W,
+    line_open: bool,
+}
+
+impl<W: ByteWrite> Escaped<W> {
+    pub fn new(inner: W) -> Self {
+        Escaped {
+            inner,
+            line_open: false,
+        }
+    }
+
+    /// Ends the last line and returns `inner`.
+    pub fn finish(mut self) -> W {
+        if self.line_open {
+            self.inner.write_byte(b'\n');
+        }
+        self.inner
+    }
+}
+
+impl<W: ByteWrite> ByteWrite for Escaped<W> {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        for escaped in core::ascii::escape_default(byte) {
+            self.inner.write_byte(escaped);
+        }
+        self.line_open = byte != b'\n';
+        if !self.line_open {
+            self.inner.write_byte(b'\n');
+        }
+    }
+
+    #[inline]
+    fn write_err(&mut self, byte: u8) {
+        self.inner.write_err(byte);
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+}
+
+impl<W: ByteWrite> ByteWrite for Utf8Lossy<W> {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        self.pending[self.len] = byte;
+        self.len += 1;
+        while self.len > 0 {
+            match core::str::from_utf8(&self.pending[..self.len]) {
+                Ok(_) => {
+                    for idx in 0..self.len {
+                        self.inner.write_byte(self.pending[idx]);
+                    }
+                    self.len = 0;
+                }
+                // Only the start of a longer sequence so far.
+                Err(err) if err.error_len().is_none() => return,
+                // The pending bytes always start a sequence, so the invalid one is at the front;
+                // the bytes after it may still start a valid sequence.
+                Err(err) => {
+                    let invalid = err.error_len().unwrap();
+                    self.write_replacement();
+                    self.pending.copy_within(invalid..self.len, 0);
+                    self.len -= invalid;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn write_err(&mut self, byte: u8) {
+        self.inner.write_err(byte);
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+}
+
+/// Reads input from the process stdin.
+#[cfg(feature = "std")]
+pub struct Stdin;
+
+#[cfg(feature = "std")]
+impl ByteRead for Stdin {
+    #[inline]
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut buf = [0u8];
+        io::stdin().read_exact(&mut buf).ok().map(|_| buf[0])
+    }
+}
+
+/// When [`Stdout`] passes the output it holds on to the process stdout, besides before every
+/// `,`, which it always does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    /// When its buffer fills and when it is dropped, which is fastest for output going to a
+    /// file or a pipe.
+    #[default]
+    Never,
+    /// After every byte.
+    PerByte,
+    /// After every newline.
+    PerLine,
+    /// After every this many bytes.
+    EveryNBytes(usize),
+    /// Only before every `,` and when its buffer fills, the same as `Never`.
+    OnInput,
+}
+
+/// Bytes [`Stdout`] holds at most before passing them on, whatever its policy.
+#[cfg(feature = "std")]
+const STDOUT_BUFFER: usize = 8192;
+
+/// Writes output to the process stdout, buffered and flushed as its [`FlushPolicy`] says. What
+/// is left is flushed when it is dropped.
+///
+/// Bytes are written unchanged, so output is identical on every platform: std writes to pipes
+/// and files without newline translation, Windows included. A Windows console only accepts
+/// UTF-8, though, so there a byte that cannot continue a UTF-8 sequence is shown as U+FFFD.
+#[cfg(feature = "std")]
+pub struct Stdout {
+    policy: FlushPolicy,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl Stdout {
+    pub fn new(policy: FlushPolicy) -> Self {
+        Stdout {
+            policy,
+            buf: Vec::with_capacity(STDOUT_BUFFER),
+        }
+    }
+
+    /// Passes the buffered bytes on to the process stdout and flushes it.
+    fn write_out(&mut self) {
+        let mut stdout = io::stdout().lock();
+        let mut rest = &self.buf[..];
+        while !rest.is_empty() {
+            match stdout.write(rest) {
+                Ok(0) => panic!("failed printing to stdout: it no longer accepts bytes"),
+                Ok(written) => rest = &rest[written..],
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                // The console refuses the first byte of `rest`.
+                Err(err) if cfg!(windows) && err.kind() == io::ErrorKind::InvalidData => {
+                    stdout.write_all("\u{FFFD}".as_bytes()).unwrap();
+                    rest = &rest[1..];
+                }
+                Err(err) => panic!("failed printing to stdout: {err}"),
+            }
+        }
+        self.buf.clear();
+        stdout.flush().unwrap();
+    }
+}
+
+#[cfg(feature = "std")]
+impl ByteWrite for Stdout {
+    #[inline]
+    fn write_byte(&mut self, byte: u8) {
+        self.buf.push(byte);
+        let due = match self.policy {
+            FlushPolicy::PerByte => true,
+            FlushPolicy::PerLine => byte == b'\n',
+            FlushPolicy::EveryNBytes(count) => self.buf.len() >= count,
+            FlushPolicy::Never | FlushPolicy::OnInput => false,
+        };
+        if due || self.buf.len() >= STDOUT_BUFFER {
+            self.write_out();
+        }
+    }
+
+    /// Writes to the process stderr, flushing stdout first so the two streams interleave in the
+    /// order the program wrote them.
+    #[cold]
+    fn write_err(&mut self, byte: u8) {
+        self.write_out();
+        io::stderr().write_all(&[byte]).unwrap();
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            self.write_out();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for Stdout {
+    fn drop(&mut self) {
+        if !self.buf.is_empty() {
+            self.write_out();
+        }
+    }
+}
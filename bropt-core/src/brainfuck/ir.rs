@@ -0,0 +1,308 @@
+//! Intermediate representations shared by every stage.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::labels::Labels;
+
+/// Width of a tape cell. Increments in the IR are sign-extended to this width.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CellWidth {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+impl CellWidth {
+    /// Size of a cell in bytes.
+    pub fn bytes(self) -> usize {
+        match self {
+            CellWidth::U8 => 1,
+            CellWidth::U16 => 2,
+            CellWidth::U32 => 4,
+        }
+    }
+
+    /// Largest value a cell holds.
+    pub fn mask(self) -> u32 {
+        match self {
+            CellWidth::U8 => u8::MAX as u32,
+            CellWidth::U16 => u16::MAX as u32,
+            CellWidth::U32 => u32::MAX,
+        }
+    }
+}
+
+/// Opcode of a flattened [`Inst`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum InstType {
+    ShiftInc,
+    Output,
+    ErrOutput,
+    Input,
+    Random,
+    Seek,
+    BoundedSeek,
+    Skip,
+    SkipInc,
+    Set,
+    Mulzero,
+    Mul,
+    Fill,
+    BlockMove,
+    Open,
+    Close,
+}
+
+impl fmt::Display for InstType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InstType::ShiftInc => "shiftinc",
+            InstType::Output => "output",
+            InstType::ErrOutput => "erroutput",
+            InstType::Input => "input",
+            InstType::Random => "random",
+            InstType::Seek => "seek",
+            InstType::BoundedSeek => "boundedseek",
+            InstType::Skip => "skip",
+            InstType::SkipInc => "skipinc",
+            InstType::Set => "set",
+            InstType::Mulzero => "mulzero",
+            InstType::Mul => "mul",
+            InstType::Fill => "fill",
+            InstType::BlockMove => "blockmove",
+            InstType::Open => "open",
+            InstType::Close => "close",
+        })
+    }
+}
+
+/// A flattened instruction executed by the interpreters.
+///
+/// Most opcodes move by `arg`, apply their effect, add `inc` to the current cell and move by `delta`;
+/// `Set` stores `inc` instead of adding it. `Seek`, `BoundedSeek`, `Skip`, `Mul` and `Mulzero` use
+/// `arg` as their stride or target offset, and `Mul`/`Mulzero` use `inc` as the weight.
+/// `BoundedSeek` moves at most `delta` strides and adds nothing.
+/// `SkipInc`s come right before a `Skip` and run with it as one instruction, adding their `inc`
+/// at their `delta` on each step as well.
+/// `Fill` stores `inc` into the `delta` cells starting at offset `arg` without moving.
+/// `BlockMove` moves the `delta` cells starting at the pointer to offset `arg`, zeroing the source.
+/// `Open`/`Close` store the index of the matching bracket in `arg`.
+///
+/// The fields are read-only outside the crate, since the interpreters that skip bounds checks
+/// trust the streams the compiler produces. For the same reason the `serde` feature only
+/// serializes instructions; a tree deserialized into [`BaseInst`] is checked and compiled instead.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Inst {
+    pub(crate) cmd: InstType,
+    pub(crate) inc: u8,
+    pub(crate) delta: i16,
+    pub(crate) arg: i32,
+}
+
+impl Inst {
+    /// Builds an instruction from its fields, for streams the compiler produced earlier and
+    /// embedded in source, as `include_bf!` does.
+    ///
+    /// # Safety
+    ///
+    /// The interpreters that skip bounds checks trust every stream they run, so the instruction
+    /// must be one the compiler produced, at the index it produced it.
+    pub const unsafe fn from_raw_parts(cmd: InstType, inc: u8, delta: i16, arg: i32) -> Inst {
+        Inst { cmd, inc, delta, arg }
+    }
+
+    pub fn cmd(&self) -> InstType {
+        self.cmd
+    }
+
+    /// The increment or stored value, sign-extended to the cell width.
+    pub fn inc(&self) -> u8 {
+        self.inc
+    }
+
+    pub fn delta(&self) -> i16 {
+        self.delta
+    }
+
+    pub fn arg(&self) -> i32 {
+        self.arg
+    }
+}
+
+/// Shows the opcode and its operands in the order they apply, as in `set arg 2, inc 5, delta -1`.
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} arg {}, inc {}, delta {}",
+            self.cmd, self.arg, self.inc as i8, self.delta
+        )
+    }
+}
+
+/// Where each instruction of a flat stream came from: the byte offset of the `[` of the innermost
+/// loop around it in the source, or of its own `[` for `Open` and `Close`.
+///
+/// Instructions outside every loop, and loops that were not parsed from source, map to `None`.
+/// The map also carries the names the source gave its loops and cells, if any.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    loops: Vec<Option<u32>>,
+    labels: Labels,
+}
+
+impl SourceMap {
+    pub fn new(loops: Vec<Option<u32>>) -> Self {
+        SourceMap {
+            loops,
+            labels: Labels::default(),
+        }
+    }
+
+    /// This map with the names read from the source it was compiled from.
+    pub fn with_labels(self, labels: Labels) -> Self {
+        SourceMap { labels, ..self }
+    }
+
+    /// Names the source gave its loops and cells.
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    /// Source offset of the `[` of the loop instruction `ip` belongs to.
+    pub fn loop_at(&self, ip: usize) -> Option<u32> {
+        self.loops.get(ip).copied().flatten()
+    }
+
+    /// Describes the loop instruction `ip` belongs to by its name, if the source gives it one,
+    /// and source position, or `None` when its position is unknown.
+    pub fn describe_loop(&self, ip: usize) -> Option<String> {
+        let pos = self.loop_at(ip)?;
+        Some(match self.labels.loop_name(pos) {
+            Some(name) => format!("the loop {name} at byte {pos}"),
+            None => format!("the loop at byte {pos}"),
+        })
+    }
+
+    /// Appends `tail`, the map of code that followed `shift` bytes of earlier source, after the
+    /// `len` instructions this map covers.
+    pub fn append(&mut self, len: usize, tail: SourceMap, shift: usize) {
+        self.loops.resize(len, None);
+        self.loops.extend(
+            tail.loops
+                .into_iter()
+                .map(|pos| pos.and_then(|pos| u32::try_from(pos as usize + shift).ok())),
+        );
+        self.labels.append(tail.labels, shift);
+    }
+}
+
+/// Tree-shaped IR produced by the parser and rewritten by the optimization passes.
+///
+/// `Block` is a loop; its flag is set when the body provably returns the pointer to where it started,
+/// and it carries the byte offset of its `[` in the source it was parsed from, if any.
+/// Increments and weights are sign-extended to the cell width.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BaseInst {
+    Inc(u8),
+    Shift(i32),
+    Output,
+    /// Writes the current cell to the error stream instead of the output.
+    ErrOutput,
+    Input,
+    Random,
+    Reset,
+    /// Adds the current cell times the weight to the cell at the offset: `(offset, weight)`.
+    Mul(i32, u8),
+    /// Moves by the stride until the current cell is zero.
+    Seek(i32),
+    /// A `Seek` proven to reach a zero within the given number of strides, which it never moves
+    /// past: `(stride, max)`.
+    BoundedSeek(i32, i16),
+    /// While the current cell is nonzero, adds each increment to the cell at its offset and moves
+    /// by the stride: `(stride, [(inc, offset)])`.
+    Skip(i32, Vec<(u8, i16)>),
+    /// Stores the value into the cells from the offset on without moving: `(offset, len, value)`.
+    Fill(i32, i16, u8),
+    /// Moves the cells starting at the pointer to the offset, zeroing them: `(offset, len)`.
+    BlockMove(i32, i16),
+    Block(Vec<BaseInst>, bool, Option<u32>),
+}
+
+/// Shows the instruction on one line, with a block's body in braces, as in
+/// `block { inc -1, shift 1, inc 1, shift -1 }`.
+impl fmt::Display for BaseInst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BaseInst::Inc(inc) => write!(f, "inc {}", *inc as i8),
+            BaseInst::Shift(delta) => write!(f, "shift {delta}"),
+            BaseInst::Output => f.write_str("output"),
+            BaseInst::ErrOutput => f.write_str("erroutput"),
+            BaseInst::Input => f.write_str("input"),
+            BaseInst::Random => f.write_str("random"),
+            BaseInst::Reset => f.write_str("reset"),
+            BaseInst::Mul(offset, weight) => write!(f, "mul {offset}, {}", *weight as i8),
+            BaseInst::Seek(stride) => write!(f, "seek {stride}"),
+            BaseInst::BoundedSeek(stride, max) => write!(f, "boundedseek {stride}, {max}"),
+            BaseInst::Skip(stride, incs) => {
+                write!(f, "skip {stride}")?;
+                for (inc, offset) in incs {
+                    write!(f, ", {}, {offset}", *inc as i8)?;
+                }
+                Ok(())
+            }
+            BaseInst::Fill(offset, len, value) => write!(f, "fill {offset}, {len}, {}", *value as i8),
+            BaseInst::BlockMove(offset, len) => write!(f, "blockmove {offset}, {len}"),
+            BaseInst::Block(body, ..) => {
+                f.write_str("block {")?;
+                for (idx, inst) in body.iter().enumerate() {
+                    f.write_str(if idx == 0 { " " } else { ", " })?;
+                    write!(f, "{inst}")?;
+                }
+                f.write_str(" }")
+            }
+        }
+    }
+}
+
+/// Checks that `prog` could have come out of the optimizer, as the interpreters that skip bounds
+/// checks assume: `Fill` and `BlockMove` lengths are not negative, a `BlockMove` does not
+/// overlap its destination, a `Skip` adds to at least one cell and a `BoundedSeek` does not move
+/// a negative number of strides.
+pub fn validate(prog: &[BaseInst]) -> Result<(), String> {
+    let mut result = Ok(());
+    walk(prog, &mut |inst, _| {
+        let valid = match *inst {
+            BaseInst::Fill(_, len, _) => len >= 0,
+            BaseInst::BlockMove(offset, len) => len >= 0 && (len == 0 || offset.unsigned_abs() >= len as u32),
+            BaseInst::Skip(_, ref incs) => !incs.is_empty(),
+            BaseInst::BoundedSeek(_, max) => max >= 0,
+            _ => true,
+        };
+        if !valid && result.is_ok() {
+            result = Err(format!("invalid instruction {inst}"));
+        }
+    });
+    result
+}
+
+/// Calls `visit` on every instruction of `prog` in order with its loop nesting depth, visiting
+/// each block before its body.
+pub fn walk(prog: &[BaseInst], visit: &mut impl FnMut(&BaseInst, usize)) {
+    fn walk_at(prog: &[BaseInst], depth: usize, visit: &mut impl FnMut(&BaseInst, usize)) {
+        for inst in prog {
+            visit(inst, depth);
+            if let BaseInst::Block(body, ..) = inst {
+                walk_at(body, depth + 1, visit);
+            }
+        }
+    }
+    walk_at(prog, 0, visit);
+}
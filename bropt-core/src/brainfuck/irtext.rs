@@ -0,0 +1,190 @@
+//! A text form of the [`BaseInst`] tree, for golden tests of single passes and for reading what
+//! a pass left behind.
+//!
+//! Instructions use the words [`BaseInst`]'s `Display` does, such as `inc -1`, `mul 2, 3` or
+//! `fill 0, 4, 0`, separated by commas or newlines, and `#` starts a comment running to the end of
+//! the line. `skip` takes its stride and then an increment and offset for each cell it changes.
+//! A loop is `block { ... }`. Its stability flag defaults to whether the body returns
+//! the pointer to where it started, as [`IrBuilder`](super::IrBuilder) sets it, and is written
+//! out as `block stable { ... }` or `block unstable { ... }` only when it differs.
+//!
+//! Source positions are not part of the text, so parsed loops have none.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::builder::balanced;
+use super::ir::BaseInst;
+
+/// Parses the text form of a tree, as [`print_ir`] writes it or the optimizer's log shows it.
+pub fn parse_ir(text: &str) -> Result<Vec<BaseInst>, String> {
+    let mut tokens = Tokens::new(text);
+    let prog = tokens.block()?;
+    match tokens.next() {
+        None => Ok(prog),
+        Some((line, token)) => Err(format!("line {line}: unmatched `{token}`")),
+    }
+}
+
+/// Writes `prog` one instruction per line, indenting loop bodies by four spaces.
+pub fn print_ir(prog: &[BaseInst]) -> String {
+    let mut text = String::new();
+    for (line, _) in ir_lines(prog) {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+/// The lines [`print_ir`] writes, each with the source position of the innermost loop it belongs
+/// to: a loop's own for its `block {` and `}` lines.
+pub(crate) fn ir_lines(prog: &[BaseInst]) -> Vec<(String, Option<u32>)> {
+    fn push_block(prog: &[BaseInst], depth: usize, pos: Option<u32>, lines: &mut Vec<(String, Option<u32>)>) {
+        let indent = "    ".repeat(depth);
+        for inst in prog {
+            match inst {
+                BaseInst::Block(body, flag, inner) => {
+                    let inner = inner.or(pos);
+                    let header = match (*flag, balanced(body)) {
+                        (true, false) => "block stable {",
+                        (false, true) => "block unstable {",
+                        _ => "block {",
+                    };
+                    lines.push((format!("{indent}{header}"), inner));
+                    push_block(body, depth + 1, inner, lines);
+                    lines.push((format!("{indent}}}"), inner));
+                }
+                inst => lines.push((format!("{indent}{inst}"), pos)),
+            }
+        }
+    }
+    let mut lines = Vec::new();
+    push_block(prog, 0, None, &mut lines);
+    lines
+}
+
+struct Tokens<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut tokens = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            for word in code.split(|c: char| c.is_whitespace() || c == ',') {
+                // Braces may touch the words around them, as in `block {inc -1}`.
+                let mut rest = word;
+                while let Some(at) = rest.find(['{', '}']) {
+                    if at > 0 {
+                        tokens.push((idx + 1, &rest[..at]));
+                    }
+                    tokens.push((idx + 1, &rest[at..at + 1]));
+                    rest = &rest[at + 1..];
+                }
+                if !rest.is_empty() {
+                    tokens.push((idx + 1, rest));
+                }
+            }
+        }
+        Tokens { tokens, pos: 0 }
+    }
+
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|&(_, token)| token)
+    }
+
+    /// Parses instructions up to a `}` or the end of the text, leaving the `}` unread.
+    fn block(&mut self) -> Result<Vec<BaseInst>, String> {
+        let mut prog = Vec::new();
+        while let Some(&(line, word)) = self.tokens.get(self.pos)
+            && word != "}"
+        {
+            self.pos += 1;
+            prog.push(self.inst(line, word)?);
+        }
+        Ok(prog)
+    }
+
+    fn inst(&mut self, line: usize, word: &str) -> Result<BaseInst, String> {
+        let inst = match word {
+            "inc" => BaseInst::Inc(self.byte(line)?),
+            "shift" => BaseInst::Shift(self.int(line)?),
+            "output" => BaseInst::Output,
+            "erroutput" => BaseInst::ErrOutput,
+            "input" => BaseInst::Input,
+            "random" => BaseInst::Random,
+            "reset" => BaseInst::Reset,
+            "mul" => BaseInst::Mul(self.int(line)?, self.byte(line)?),
+            "seek" => BaseInst::Seek(self.int(line)?),
+            "boundedseek" => BaseInst::BoundedSeek(self.int(line)?, self.narrow(line)?),
+            "skip" => {
+                let stride = self.int(line)?;
+                let mut incs = Vec::new();
+                // Pairs run on until the next word, which is never a number.
+                loop {
+                    incs.push((self.byte(line)?, self.narrow(line)?));
+                    if self.peek().is_none_or(|token| token.parse::<i32>().is_err()) {
+                        break;
+                    }
+                }
+                BaseInst::Skip(stride, incs)
+            }
+            "fill" => BaseInst::Fill(self.int(line)?, self.narrow(line)?, self.byte(line)?),
+            "blockmove" => BaseInst::BlockMove(self.int(line)?, self.narrow(line)?),
+            "block" => {
+                let flag = match self.peek() {
+                    Some("stable") => Some(true),
+                    Some("unstable") => Some(false),
+                    _ => None,
+                };
+                if flag.is_some() {
+                    self.pos += 1;
+                }
+                if self.next().map(|(_, token)| token) != Some("{") {
+                    return Err(format!("line {line}: expected `{{` after `block`"));
+                }
+                let body = self.block()?;
+                if self.next().is_none() {
+                    return Err(format!("line {line}: unclosed `block`"));
+                }
+                let flag = flag.unwrap_or_else(|| balanced(&body));
+                BaseInst::Block(body, flag, None)
+            }
+            word => return Err(format!("line {line}: unknown instruction `{word}`")),
+        };
+        Ok(inst)
+    }
+
+    fn int(&mut self, line: usize) -> Result<i32, String> {
+        match self.next() {
+            Some((_, token)) => token
+                .parse()
+                .map_err(|_| format!("line {line}: expected a number, found `{token}`")),
+            None => Err(format!("line {line}: expected a number")),
+        }
+    }
+
+    fn narrow(&mut self, line: usize) -> Result<i16, String> {
+        let value = self.int(line)?;
+        i16::try_from(value).map_err(|_| format!("line {line}: {value} does not fit in 16 bits"))
+    }
+
+    /// An increment, weight or value, written signed as `Display` does but also taken unsigned.
+    fn byte(&mut self, line: usize) -> Result<u8, String> {
+        let value = self.int(line)?;
+        match value {
+            -128..=-1 => Ok(value as i8 as u8),
+            0..=255 => Ok(value as u8),
+            _ => Err(format!("line {line}: {value} does not fit in a byte")),
+        }
+    }
+}
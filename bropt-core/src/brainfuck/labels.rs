@@ -0,0 +1,135 @@
+//! Names given to loops and cells in comments, so diagnostics can say `loop mul_digits` instead
+//! of a byte offset.
+//!
+//! `{label: name}` names the next loop after it, and `{cell: name}` the cell the pointer is on
+//! where it appears. Names are letters, digits and `_`, so an annotation holds no commands and
+//! stays a comment for every other brainfuck tool. Cells can only be named outside loops while
+//! the pointer is still known, that is before any loop that does not return it to where it
+//! started.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Loop and cell names read from a program's comments.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Labels {
+    /// Names of loops by the byte offset of their `[`.
+    loops: BTreeMap<u32, String>,
+    /// Names of cells by their position on the tape.
+    cells: BTreeMap<usize, String>,
+}
+
+/// What an annotation names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Loop,
+    Cell,
+}
+
+impl Labels {
+    /// Reads the annotations in `code`, whose pointer starts on cell 0.
+    pub fn scan(code: &str) -> Labels {
+        let mut labels = Labels::default();
+        let bytes = code.as_bytes();
+        let mut pending = None;
+        let mut ptr = Some(0i64);
+        let mut entries = Vec::new();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            if let Some((kind, name, len)) = annotation(bytes, idx) {
+                match kind {
+                    Kind::Loop => pending = Some(name),
+                    Kind::Cell => {
+                        if entries.is_empty()
+                            && let Some(cell) = ptr.and_then(|ptr| usize::try_from(ptr).ok())
+                        {
+                            labels.cells.insert(cell, String::from(name));
+                        }
+                    }
+                }
+                idx += len;
+                continue;
+            }
+            match bytes[idx] {
+                b'>' => ptr = ptr.map(|ptr| ptr + 1),
+                b'<' => ptr = ptr.map(|ptr| ptr - 1),
+                b'[' => {
+                    if let Some(name) = pending.take()
+                        && let Ok(pos) = u32::try_from(idx)
+                    {
+                        labels.loops.insert(pos, String::from(name));
+                    }
+                    entries.push(ptr);
+                }
+                b']' if entries.pop().is_some_and(|entry| entry != ptr) => ptr = None,
+                _ => {}
+            }
+            idx += 1;
+        }
+        labels
+    }
+
+    /// Name of the loop whose `[` is at byte `pos` of the source.
+    pub fn loop_name(&self, pos: u32) -> Option<&str> {
+        self.loops.get(&pos).map(String::as_str)
+    }
+
+    /// Name of cell `cell` of the tape.
+    pub fn cell_name(&self, cell: usize) -> Option<&str> {
+        self.cells.get(&cell).map(String::as_str)
+    }
+
+    /// Named cells in tape order.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.cells.iter().map(|(&cell, name)| (cell, name.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loops.is_empty() && self.cells.is_empty()
+    }
+
+    /// Adds the loop names of code that followed `shift` bytes of earlier source. Its cell names
+    /// are dropped, since that code starts wherever the earlier code left the pointer.
+    pub(crate) fn append(&mut self, tail: Labels, shift: usize) {
+        for (pos, name) in tail.loops {
+            if let Ok(pos) = u32::try_from(pos as usize + shift) {
+                self.loops.insert(pos, name);
+            }
+        }
+    }
+}
+
+/// Matches an annotation starting at byte `idx`, returning what it names, the name and its
+/// length in bytes.
+pub(crate) fn annotation(bytes: &[u8], idx: usize) -> Option<(Kind, &str, usize)> {
+    if bytes.get(idx) != Some(&b'{') {
+        return None;
+    }
+    let rest = &bytes[idx + 1..];
+    let skip_spaces = |at: usize| at + rest[at..].iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+    let start = skip_spaces(0);
+    let (kind, word_len) = if rest[start..].starts_with(b"label") {
+        (Kind::Loop, 5)
+    } else if rest[start..].starts_with(b"cell") {
+        (Kind::Cell, 4)
+    } else {
+        return None;
+    };
+    let colon = skip_spaces(start + word_len);
+    if rest.get(colon) != Some(&b':') {
+        return None;
+    }
+    let name_start = skip_spaces(colon + 1);
+    let name_len = rest[name_start..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_alphanumeric() || **byte == b'_')
+        .count();
+    let close = skip_spaces(name_start + name_len);
+    if name_len == 0 || rest.get(close) != Some(&b'}') {
+        return None;
+    }
+    // The name is ASCII, so it is valid UTF-8 on its own.
+    let name = core::str::from_utf8(&rest[name_start..name_start + name_len]).ok()?;
+    Some((kind, name, close + 2))
+}
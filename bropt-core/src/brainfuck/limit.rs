@@ -0,0 +1,60 @@
+//! Memory limits for running untrusted programs.
+
+use core::fmt;
+
+use super::hooks::{ExecHooks, IoEvent};
+
+/// A run needed more memory than its limit allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceExhausted {
+    /// The limit in bytes.
+    pub limit: usize,
+    /// Bytes the run needed when it was refused or stopped: its tape plus the output so far.
+    pub requested: usize,
+}
+
+impl fmt::Display for ResourceExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "run needs {} bytes of memory but is limited to {}",
+            self.requested, self.limit
+        )
+    }
+}
+
+impl core::error::Error for ResourceExhausted {}
+
+/// Stops a run once it has written more than `limit` bytes to its output and error stream
+/// together, for output collected in memory.
+///
+/// The byte that crosses the limit is still written, so the output ends one byte past it.
+pub struct OutputLimit {
+    limit: usize,
+    written: usize,
+}
+
+impl OutputLimit {
+    pub fn new(limit: usize) -> Self {
+        OutputLimit { limit, written: 0 }
+    }
+
+    /// Whether the run wrote more than the limit and was stopped.
+    pub fn exceeded(&self) -> bool {
+        self.written > self.limit
+    }
+}
+
+impl ExecHooks for OutputLimit {
+    #[inline(always)]
+    fn on_io(&mut self, _ip: usize, _dp: usize, event: IoEvent) {
+        if let IoEvent::Write(_) | IoEvent::WriteErr(_) = event {
+            self.written += 1;
+        }
+    }
+
+    #[inline(always)]
+    fn should_stop(&mut self) -> bool {
+        self.exceeded()
+    }
+}
@@ -0,0 +1,165 @@
+//! Dense tape backed by an anonymous memory mapping.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::{fmt, mem, ptr, slice};
+
+use super::interp::Cell;
+
+/// Bytes of inaccessible address space kept on each side of a [`MappedTape`].
+pub const GUARD_BYTES: usize = 1 << 24;
+
+/// Size of the huge pages [`MappedTape::with_huge_pages`] aligns its cells to, the 2 MiB of
+/// x86-64 and of arm64 with 4 KiB pages.
+const HUGE_PAGE: usize = 1 << 21;
+
+/// Tape whose pages cost memory only once touched, so tapes of many gigabytes are practical.
+///
+/// The cells are surrounded by [`GUARD_BYTES`] of inaccessible pages, so the unchecked
+/// interpreter faults instead of corrupting memory when it runs off either end.
+pub struct MappedTape<C> {
+    map: *mut libc::c_void,
+    map_len: usize,
+    cells: *mut C,
+    len: usize,
+    huge: bool,
+    _cells: PhantomData<C>,
+}
+
+impl<C: Cell> MappedTape<C> {
+    /// Maps a zeroed tape of `len` cells.
+    pub fn new(len: usize) -> Result<Self, String> {
+        Self::map(len, false)
+    }
+
+    /// Maps a zeroed tape of `len` cells aligned to huge pages and asks the kernel to back it with
+    /// them, so a program sweeping a tape of gigabytes misses the TLB less often. Pages are still
+    /// committed as they are touched, but 2 MiB at a time. Where transparent huge pages are not
+    /// available, as on systems other than Linux, this is the tape [`MappedTape::new`] maps.
+    pub fn with_huge_pages(len: usize) -> Result<Self, String> {
+        Self::map(len, true)
+    }
+
+    fn map(len: usize, huge: bool) -> Result<Self, String> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let guard = GUARD_BYTES.next_multiple_of(page);
+        // Room to move the cells up to the next huge page boundary, taken from the guard below.
+        let slack = if huge { HUGE_PAGE } else { 0 };
+        let (bytes, map_len) = len
+            .checked_mul(mem::size_of::<C>())
+            .and_then(|bytes| bytes.checked_next_multiple_of(page))
+            .and_then(|bytes| Some((bytes, bytes.checked_add(2 * guard + slack)?)))
+            .ok_or_else(|| "tape is too long".to_string())?;
+        unsafe {
+            let map = libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            );
+            if map == libc::MAP_FAILED {
+                return Err("failed to map the tape".to_string());
+            }
+            let start = (map as usize + guard).next_multiple_of(slack.max(page));
+            let cells = map.byte_add(start - map as usize);
+            if bytes > 0 && libc::mprotect(cells, bytes, libc::PROT_READ | libc::PROT_WRITE) != 0 {
+                libc::munmap(map, map_len);
+                return Err("failed to map the tape".to_string());
+            }
+            // Only advice, which kernels built without huge pages refuse, so failing is fine.
+            #[cfg(target_os = "linux")]
+            if huge && bytes > 0 {
+                libc::madvise(cells, bytes, libc::MADV_HUGEPAGE);
+            }
+            Ok(MappedTape {
+                map,
+                map_len,
+                cells: cells as *mut C,
+                len,
+                huge,
+                _cells: PhantomData,
+            })
+        }
+    }
+
+    /// Iterates over the stretches of pages that have been touched, as their first position and
+    /// their cells, so a mostly untouched tape of gigabytes can be read without faulting in every
+    /// page. Every cell outside them is zero.
+    pub fn iter_pages(&self) -> impl Iterator<Item = (usize, &[C])> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let page_len = page / mem::size_of::<C>();
+        let bytes = (self.len * mem::size_of::<C>()).next_multiple_of(page);
+        let mut resident = vec![0u8; bytes / page];
+        let found = bytes > 0 && unsafe { libc::mincore(self.cells.cast(), bytes, resident.as_mut_ptr().cast()) } == 0;
+        if !found {
+            // Without residency information, every page has to be read.
+            resident.fill(1);
+        }
+        let mut stretches = Vec::new();
+        let mut idx = 0;
+        while idx < resident.len() {
+            if resident[idx] & 1 == 0 {
+                idx += 1;
+                continue;
+            }
+            let start = idx;
+            while idx < resident.len() && resident[idx] & 1 != 0 {
+                idx += 1;
+            }
+            let end = (idx * page_len).min(self.len);
+            stretches.push((start * page_len, &self[start * page_len..end]));
+        }
+        stretches.into_iter()
+    }
+}
+
+impl<C> Drop for MappedTape<C> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map, self.map_len) };
+    }
+}
+
+impl<C> Deref for MappedTape<C> {
+    type Target = [C];
+
+    fn deref(&self) -> &[C] {
+        unsafe { slice::from_raw_parts(self.cells, self.len) }
+    }
+}
+
+impl<C> DerefMut for MappedTape<C> {
+    fn deref_mut(&mut self) -> &mut [C] {
+        unsafe { slice::from_raw_parts_mut(self.cells, self.len) }
+    }
+}
+
+impl<C> fmt::Debug for MappedTape<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedTape")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Cell> Clone for MappedTape<C> {
+    fn clone(&self) -> Self {
+        let mut tape = MappedTape::map(self.len, self.huge).unwrap();
+        tape.copy_from_slice(self);
+        tape
+    }
+}
+
+impl<C: Cell> PartialEq for MappedTape<C> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+unsafe impl<C: Send> Send for MappedTape<C> {}
+
+unsafe impl<C: Sync> Sync for MappedTape<C> {}
@@ -0,0 +1,113 @@
+//! The compiler and interpreters, split by stage:
+//!
+//! - [`parser`]: source text to the [`BaseInst`] tree, or [`builder`] to build one in code
+//! - [`opt`]: passes rewriting the tree, with what they know about cells from [`analysis`], and
+//!   [`irtext`] to read and write trees as text for testing them one at a time
+//! - [`labels`]: names for loops and cells given in the source's comments
+//! - [`decompile`](mod@decompile): tree back to source text
+//! - [`flatten`](mod@flatten): tree to the flat [`Inst`] stream, and the passes fusing and
+//!   threading it
+//! - [`interp`]: interpreters for the flat stream, with I/O from [`io`] and storage from [`tape`]
+//!   or, on unix with the `mmap` feature, `mapped`
+//! - [`batch`]: an interpreter running the flat stream over many tapes at once
+//! - [`hooks`]: callbacks an instrumented run reports to, and [`audit`] to check a run against a
+//!   model of its loops
+//! - [`limit`]: memory limits for running untrusted programs
+//! - [`rng`]: the generator behind the `?` extension and the seed runs draw it from
+//!
+//! [`compile`] runs the whole pipeline.
+
+pub mod analysis;
+pub mod audit;
+pub mod batch;
+pub mod builder;
+pub mod decompile;
+pub mod flatten;
+pub mod hooks;
+pub mod interp;
+pub mod io;
+pub mod ir;
+pub mod irtext;
+pub mod labels;
+pub mod limit;
+#[cfg(all(unix, feature = "mmap"))]
+pub mod mapped;
+pub mod opt;
+pub mod parser;
+pub mod rng;
+pub mod tape;
+pub(crate) mod trace;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub use analysis::{Const, Domain, Interval, Parity, State, Value};
+pub use audit::{Divergence, ExecAudit};
+pub use batch::{BatchTape, execute_batch};
+pub use builder::IrBuilder;
+pub use decompile::decompile;
+pub use flatten::{
+    ProvenLoop, Unbounded, append, flatten, flatten_with_map, get_offset, link_brackets, loop_stack, peephole,
+    peephole_with_map, pointer_range, proven_loops, thread_jumps, thread_jumps_with_map, unbounded,
+};
+pub use hooks::{ExecHooks, IoEvent, NoHooks, OutputMatch};
+pub use interp::{
+    Cell, CellOps, Eof, OutOfBounds, Wrapping, execute, execute_proven, execute_with_hooks, resume_with_hooks,
+    run_static, run_with_io, run_with_state, run_with_state_limited, run_with_state_preloaded, unsafe_execute,
+    unsafe_run_with_io,
+};
+#[cfg(feature = "std")]
+pub use interp::{run, unsafe_run};
+pub use io::{
+    ByteRead, ByteWrite, Escaped, FlushPolicy, HexDump, OutputChunk, PartialInput, SliceReader, SplitOutput, Utf8Lossy,
+};
+#[cfg(feature = "std")]
+pub use io::{Stdin, Stdout};
+pub use ir::{BaseInst, CellWidth, Inst, InstType, SourceMap, validate, walk};
+pub use irtext::{parse_ir, print_ir};
+pub use labels::Labels;
+pub use limit::{OutputLimit, ResourceExhausted};
+#[cfg(all(unix, feature = "mmap"))]
+pub use mapped::{GUARD_BYTES, MappedTape};
+pub use opt::{
+    PASSES, bound_seeks, compress, evaluate_constant_loops, fold_block_moves, fold_fills, fold_mul_loops,
+    fold_simple_loops, fold_skip_loops, infer_stability, move_repeating_resets, normalize, optimize, optimize_with,
+    optimize_with_passes, propagate_mul_chains, remove_dead_loops, remove_dead_writes, remove_redundant_resets,
+    run_pass,
+};
+pub use parser::{Extensions, parse, try_parse, try_parse_with};
+pub use rng::{Rng, Seed};
+pub use tape::{Memory, PAGE_LEN, SparseTape};
+
+/// Parses, optimizes and flattens `code`, panicking on unbalanced brackets.
+pub fn compile(code: &str) -> Vec<Inst> {
+    try_compile(code).unwrap()
+}
+
+/// Parses, optimizes and flattens `code` with the full pipeline for 8-bit cells.
+pub fn try_compile(code: &str) -> Result<Vec<Inst>, String> {
+    compile_with(code, true)
+}
+
+/// [`compile`] for a tape that may start with nonzero cells, as [`run_with_state_preloaded`] takes.
+pub fn compile_preloaded(code: &str) -> Vec<Inst> {
+    try_compile_preloaded(code).unwrap()
+}
+
+/// [`try_compile`] for a tape that may start with nonzero cells.
+pub fn try_compile_preloaded(code: &str) -> Result<Vec<Inst>, String> {
+    compile_with(code, false)
+}
+
+fn compile_with(code: &str, zeroed: bool) -> Result<Vec<Inst>, String> {
+    trace::pass("compile", code, |code| {
+        let prog = trace::pass("parse", code, try_parse)?;
+        let optimized = optimize_with(prog, 2, CellWidth::U8, zeroed, Eof::Zero);
+        let flat = trace::pass("flatten", optimized, flatten);
+        let flat = trace::pass("peephole", flat, |flat| peephole(flat, CellWidth::U8));
+        let threaded = trace::pass("thread_jumps", flat, |flat| {
+            thread_jumps_with_map(flat, &SourceMap::default(), zeroed)
+        });
+        Ok(threaded.0)
+    })
+}
@@ -0,0 +1,1395 @@
+//! Optimization passes over the [`BaseInst`] tree.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+use core::ops::Range;
+
+use super::analysis::{Domain, State, Value, span};
+use super::interp::Eof;
+use super::ir::{BaseInst, CellWidth};
+use super::trace::{self, decision};
+
+/// Runs the pass pipeline for `level`: 0 leaves the IR untouched, 1 folds loops once, 2 is the full pipeline.
+/// `,` is taken to store zero once the input is exhausted, as with the default [`Eof`].
+pub fn optimize(prog: Vec<BaseInst>, level: u8, cell: CellWidth) -> Vec<BaseInst> {
+    optimize_with(prog, level, cell, true, Eof::Zero)
+}
+
+/// [`optimize`] that only assumes the tape starts out zeroed when `zeroed` is set, so programs
+/// run over a preloaded tape keep the loops and resets a zeroed tape would make redundant, for
+/// programs whose `,` stores what `eof` says at the end of input.
+pub fn optimize_with(prog: Vec<BaseInst>, level: u8, cell: CellWidth, zeroed: bool, eof: Eof) -> Vec<BaseInst> {
+    optimize_with_passes(prog, level, cell, zeroed, eof, &mut |_, prog| prog)
+}
+
+/// [`optimize_with`] that hands the tree to `extra` after every pass, with the pass's name from
+/// [`PASSES`], and goes on with the tree it returns, so passes written outside bropt can run at
+/// any point of the pipeline. Level 0 runs no passes, so it never calls `extra`.
+pub fn optimize_with_passes(
+    prog: Vec<BaseInst>,
+    level: u8,
+    cell: CellWidth,
+    zeroed: bool,
+    eof: Eof,
+    extra: &mut dyn FnMut(&str, Vec<BaseInst>) -> Vec<BaseInst>,
+) -> Vec<BaseInst> {
+    if level == 0 {
+        return prog;
+    }
+    let mut run = |name: &'static str, prog: Vec<BaseInst>, pass: &dyn Fn(Vec<BaseInst>) -> Vec<BaseInst>| {
+        extra(name, trace::pass(name, prog, pass))
+    };
+    trace::pass("optimize", prog, |mut prog| {
+        if level >= 2 {
+            for _ in 0..2 {
+                prog = run("compress", prog, &|prog| compress(prog, cell));
+                prog = run("evaluate_constant_loops", prog, &|prog| {
+                    evaluate_constant_loops(prog, cell, zeroed)
+                });
+                prog = run("fold_simple_loops", prog, &fold_simple_loops);
+                prog = run("fold_fills", prog, &fold_fills);
+                prog = run("infer_stability", prog, &|prog| infer_stability(prog, cell));
+                prog = run("fold_mul_loops", prog, &|prog| fold_mul_loops(prog, cell));
+                prog = run("propagate_mul_chains", prog, &|prog| {
+                    propagate_mul_chains(prog, cell, zeroed)
+                });
+                prog = run("fold_block_moves", prog, &|prog| fold_block_moves(prog, cell, zeroed));
+                prog = run("remove_dead_writes", prog, &|prog| remove_dead_writes(prog, eof));
+                prog = run("remove_redundant_resets", prog, &|prog| {
+                    remove_redundant_resets(prog, cell, zeroed)
+                });
+                prog = run("remove_dead_loops", prog, &|prog| remove_dead_loops(prog, cell, zeroed));
+                prog = run("move_repeating_resets", prog, &move_repeating_resets);
+            }
+        }
+        prog = run("compress", prog, &|prog| compress(prog, cell));
+        prog = run("fold_simple_loops", prog, &fold_simple_loops);
+        prog = run("fold_mul_loops", prog, &|prog| fold_mul_loops(prog, cell));
+        prog = run("fold_skip_loops", prog, &fold_skip_loops);
+        prog = run("normalize", prog, &|prog| normalize(prog, cell));
+        run("bound_seeks", prog, &|prog| bound_seeks(prog, cell, zeroed))
+    })
+}
+
+/// Names of the passes [`run_pass`] runs one at a time, in the order the pipeline first runs them.
+pub const PASSES: [&str; 15] = [
+    "compress",
+    "evaluate_constant_loops",
+    "fold_simple_loops",
+    "fold_fills",
+    "infer_stability",
+    "fold_mul_loops",
+    "propagate_mul_chains",
+    "fold_block_moves",
+    "remove_dead_writes",
+    "remove_redundant_resets",
+    "remove_dead_loops",
+    "move_repeating_resets",
+    "fold_skip_loops",
+    "normalize",
+    "bound_seeks",
+];
+
+/// Runs the single pass `name` from [`PASSES`] over `prog` as the pipeline would, or `None` for an unknown name.
+pub fn run_pass(name: &str, prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool, eof: Eof) -> Option<Vec<BaseInst>> {
+    let prog = match name {
+        "compress" => compress(prog, cell),
+        "evaluate_constant_loops" => evaluate_constant_loops(prog, cell, zeroed),
+        "fold_simple_loops" => fold_simple_loops(prog),
+        "fold_fills" => fold_fills(prog),
+        "infer_stability" => infer_stability(prog, cell),
+        "fold_mul_loops" => fold_mul_loops(prog, cell),
+        "propagate_mul_chains" => propagate_mul_chains(prog, cell, zeroed),
+        "fold_block_moves" => fold_block_moves(prog, cell, zeroed),
+        "remove_dead_writes" => remove_dead_writes(prog, eof),
+        "remove_redundant_resets" => remove_redundant_resets(prog, cell, zeroed),
+        "remove_dead_loops" => remove_dead_loops(prog, cell, zeroed),
+        "move_repeating_resets" => move_repeating_resets(prog),
+        "fold_skip_loops" => fold_skip_loops(prog),
+        "normalize" => normalize(prog, cell),
+        "bound_seeks" => bound_seeks(prog, cell, zeroed),
+        _ => return None,
+    };
+    Some(prog)
+}
+
+/// Merges runs of `Inc` and `Shift` and drops the ones that cancel out.
+pub fn compress(prog: Vec<BaseInst>, cell: CellWidth) -> Vec<BaseInst> {
+    fn compress_block(block: Vec<BaseInst>, cell: CellWidth) -> Vec<BaseInst> {
+        let mut iter = block.into_iter().peekable();
+        let mut compressed = Vec::with_capacity(iter.size_hint().0);
+        while let Some(inst) = iter.next() {
+            match inst {
+                BaseInst::Inc(mut val) => {
+                    while let Some(BaseInst::Inc(next)) = iter.peek() {
+                        match combine_inc(val, *next, cell) {
+                            Some(sum) => val = sum,
+                            None => break,
+                        }
+                        iter.next();
+                    }
+                    if val != 0 {
+                        compressed.push(BaseInst::Inc(val));
+                    }
+                }
+                BaseInst::Shift(mut off) => {
+                    while let Some(BaseInst::Shift(next)) = iter.peek() {
+                        match off.checked_add(*next) {
+                            Some(sum) => off = sum,
+                            None => break,
+                        }
+                        iter.next();
+                    }
+                    if off != 0 {
+                        compressed.push(BaseInst::Shift(off));
+                    }
+                }
+                BaseInst::Block(inner, stability, pos) => {
+                    compressed.push(BaseInst::Block(compress_block(inner, cell), stability, pos));
+                }
+                other => compressed.push(other),
+            }
+        }
+        compressed
+    }
+    compress_block(prog, cell)
+}
+
+/// Folds `[-]`-style loops into `Reset` and `[>]`-style loops into `Seek`.
+pub fn fold_simple_loops(prog: Vec<BaseInst>) -> Vec<BaseInst> {
+    fn gcd(mut a: u32, mut b: u32) -> u32 {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    }
+    fn fold_block(block: Vec<BaseInst>) -> Vec<BaseInst> {
+        block
+            .into_iter()
+            .map(|inst| match inst {
+                BaseInst::Block(inner, stability, pos) => {
+                    let inner = fold_block(inner);
+                    if inner.len() == 1 {
+                        match inner[0] {
+                            BaseInst::Inc(x)
+                                if gcd(x as u32, 256) == 1 && decision!("folded loop {inner:?} into Reset") =>
+                            {
+                                BaseInst::Reset
+                            }
+                            BaseInst::Shift(n) if decision!("folded loop {inner:?} into Seek({n})") => {
+                                BaseInst::Seek(n)
+                            }
+                            _ => BaseInst::Block(inner, stability, pos),
+                        }
+                    } else {
+                        BaseInst::Block(inner, stability, pos)
+                    }
+                }
+                other => other,
+            })
+            .collect()
+    }
+    fold_block(prog)
+}
+
+/// Recomputes the stability flag of every block by tracking the pointer and known cell values
+/// through the body, so blocks whose `Seek`s provably return to the start count as stable.
+///
+/// A `Seek` over cells known to be nonzero lands on the first known zero. When it runs into
+/// unknown cells the pointer is only known up to the scan; shifting back one stride and seeking
+/// the other way then lands on a known zero before the scanned run.
+pub fn infer_stability(prog: Vec<BaseInst>, cell: CellWidth) -> Vec<BaseInst> {
+    #[derive(Clone, Copy)]
+    enum Value {
+        Known(u32),
+        NonZero,
+    }
+    #[derive(Clone, Copy)]
+    enum Ptr {
+        At(i32),
+        // Somewhere at `origin + k * stride + rel` with every scanned cell before it nonzero.
+        Scan { origin: i32, stride: i32, rel: i32 },
+        Lost,
+    }
+    fn seek(known: &mut BTreeMap<i32, Value>, mut pos: i32, stride: i32) -> Ptr {
+        loop {
+            match known.get(&pos) {
+                Some(Value::Known(0)) => return Ptr::At(pos),
+                Some(_) => match pos.checked_add(stride) {
+                    Some(next) => pos = next,
+                    None => return Ptr::Lost,
+                },
+                None => {
+                    return Ptr::Scan {
+                        origin: pos,
+                        stride,
+                        rel: 0,
+                    };
+                }
+            }
+        }
+    }
+    // Records a write at `rel` past the scan, giving up if it may hit a scanned cell.
+    fn write_past_scan(known: &mut BTreeMap<i32, Value>, origin: i32, stride: i32, rel: i32) -> bool {
+        let Some(end) = origin.checked_add(rel) else {
+            return false;
+        };
+        if stride > 0 && rel >= 0 {
+            known.retain(|&pos, _| pos < end);
+            true
+        } else if stride < 0 && rel <= 0 {
+            known.retain(|&pos, _| pos > end);
+            true
+        } else {
+            false
+        }
+    }
+    // The `len` cells `offset` past `pos`, or `None` when they run past the ends of `i32`.
+    fn range_at(pos: i32, offset: i32, len: i16) -> Option<Range<i32>> {
+        let start = pos.checked_add(offset)?;
+        Some(start..start.checked_add(len as i32)?)
+    }
+    // The scans compare positions, so a position past the ends of `i32` gives up rather than wraps.
+    fn stable(block: &[BaseInst], mask: u32) -> bool {
+        let mut known = BTreeMap::from([(0, Value::NonZero)]);
+        let mut ptr = Ptr::At(0);
+        for inst in block {
+            ptr = match (ptr, inst) {
+                (Ptr::Lost, _) => return false,
+                (Ptr::At(pos), BaseInst::Shift(offset)) => pos.checked_add(*offset).map_or(Ptr::Lost, Ptr::At),
+                (Ptr::At(pos), BaseInst::Inc(val)) => {
+                    match known.get(&pos) {
+                        Some(Value::Known(v)) => {
+                            known.insert(pos, Value::Known(v.wrapping_add(*val as i8 as u32) & mask));
+                        }
+                        _ => {
+                            known.remove(&pos);
+                        }
+                    }
+                    ptr
+                }
+                (Ptr::At(pos), BaseInst::Reset) => {
+                    known.insert(pos, Value::Known(0));
+                    ptr
+                }
+                (Ptr::At(pos), BaseInst::Input | BaseInst::Random) => {
+                    known.remove(&pos);
+                    ptr
+                }
+                (Ptr::At(pos), BaseInst::Fill(start, len, value)) => {
+                    let Some(cells) = range_at(pos, *start, *len) else {
+                        return false;
+                    };
+                    for cell in cells {
+                        known.insert(cell, Value::Known(*value as i8 as u32 & mask));
+                    }
+                    ptr
+                }
+                (Ptr::At(pos), BaseInst::BlockMove(offset, len)) => {
+                    let (Some(src), Some(dst)) = (range_at(pos, 0, *len), range_at(pos, *offset, *len)) else {
+                        return false;
+                    };
+                    for (cell, target) in src.zip(dst) {
+                        match known.insert(cell, Value::Known(0)) {
+                            Some(value) => known.insert(target, value),
+                            None => known.remove(&target),
+                        };
+                    }
+                    ptr
+                }
+                (Ptr::At(pos), BaseInst::Mul(offset, _)) => {
+                    let Some(target) = pos.checked_add(*offset) else {
+                        return false;
+                    };
+                    if !matches!(known.get(&pos), Some(Value::Known(0))) {
+                        known.remove(&target);
+                    }
+                    ptr
+                }
+                (Ptr::At(pos), BaseInst::Seek(stride) | BaseInst::BoundedSeek(stride, _)) => {
+                    seek(&mut known, pos, *stride)
+                }
+                (Ptr::At(pos), BaseInst::Skip(..) | BaseInst::Block(_, false, _)) => {
+                    if matches!(known.get(&pos), Some(Value::Known(0))) {
+                        ptr
+                    } else {
+                        Ptr::Lost
+                    }
+                }
+                (Ptr::At(pos), BaseInst::Block(_, true, _)) => {
+                    if !matches!(known.get(&pos), Some(Value::Known(0))) {
+                        known.clear();
+                        known.insert(pos, Value::Known(0));
+                    }
+                    ptr
+                }
+                (_, BaseInst::Output | BaseInst::ErrOutput) => ptr,
+                (Ptr::Scan { origin, stride, rel }, BaseInst::Shift(offset)) => match rel.checked_add(*offset) {
+                    Some(rel) => Ptr::Scan { origin, stride, rel },
+                    None => Ptr::Lost,
+                },
+                (
+                    Ptr::Scan { origin, stride, rel },
+                    BaseInst::Inc(_) | BaseInst::Reset | BaseInst::Input | BaseInst::Random,
+                ) => {
+                    if write_past_scan(&mut known, origin, stride, rel) {
+                        ptr
+                    } else {
+                        Ptr::Lost
+                    }
+                }
+                (Ptr::Scan { origin, stride, rel }, BaseInst::Mul(offset, _)) => {
+                    if rel
+                        .checked_add(*offset)
+                        .is_some_and(|rel| write_past_scan(&mut known, origin, stride, rel))
+                    {
+                        ptr
+                    } else {
+                        Ptr::Lost
+                    }
+                }
+                (Ptr::Scan { origin, stride, rel }, BaseInst::Seek(back) | BaseInst::BoundedSeek(back, _))
+                    if stride.checked_neg() == Some(*back) && rel == *back =>
+                {
+                    origin
+                        .checked_add(*back)
+                        .map_or(Ptr::Lost, |pos| seek(&mut known, pos, *back))
+                }
+                (Ptr::Scan { .. }, _) => Ptr::Lost,
+            };
+        }
+        matches!(ptr, Ptr::At(0))
+    }
+    fn infer_block(block: Vec<BaseInst>, mask: u32) -> Vec<BaseInst> {
+        block
+            .into_iter()
+            .map(|inst| match inst {
+                BaseInst::Block(inner, old, pos) => {
+                    let inner = infer_block(inner, mask);
+                    let mut flag = stable(&inner, mask);
+                    if flag != old && !decision!("marked loop {inner:?} {}", if flag { "stable" } else { "unstable" }) {
+                        flag = old;
+                    }
+                    BaseInst::Block(inner, flag, pos)
+                }
+                other => other,
+            })
+            .collect()
+    }
+    infer_block(prog, cell.mask())
+}
+
+/// Removes `Reset`s of cells that are provably already zero, tracking known cell values forward
+/// through straight-line code. Every loop or scan exits on a zero cell, and `zeroed` says whether
+/// the tape starts out zeroed.
+pub fn remove_redundant_resets(prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool) -> Vec<BaseInst> {
+    fn remove_block(prog: Vec<BaseInst>, state: &mut State<Value>) -> Vec<BaseInst> {
+        let mut removed = Vec::with_capacity(prog.len());
+        for inst in prog {
+            match inst {
+                BaseInst::Reset
+                    if state.current().is_zero()
+                        && decision!("removed Reset of a cell known to be zero at offset {}", state.ptr()) => {}
+                BaseInst::Block(inner, flag, pos) => {
+                    let mut entry = state.step_loop(&inner, flag);
+                    let inner = remove_block(inner, &mut entry);
+                    removed.push(BaseInst::Block(inner, flag, pos));
+                }
+                other => {
+                    state.step(&other);
+                    removed.push(other);
+                }
+            }
+        }
+        removed
+    }
+    remove_block(prog, &mut State::new(cell, zeroed))
+}
+
+/// Removes loops, scans and `Mul`s on cells that are provably zero when they are reached, such
+/// as a copy loop over a cell that was just cleared. `zeroed` says whether the tape starts out zeroed.
+pub fn remove_dead_loops(prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool) -> Vec<BaseInst> {
+    fn remove_block(prog: Vec<BaseInst>, state: &mut State<Value>) -> Vec<BaseInst> {
+        let mut removed = Vec::with_capacity(prog.len());
+        for inst in prog {
+            match inst {
+                BaseInst::Block(..)
+                | BaseInst::Seek(_)
+                | BaseInst::BoundedSeek(..)
+                | BaseInst::Skip(..)
+                | BaseInst::Mul(..)
+                    if state.current().is_zero()
+                        && decision!("removed {inst} over a cell known to be zero at offset {}", state.ptr()) => {}
+                BaseInst::Block(inner, flag, pos) => {
+                    let mut entry = state.step_loop(&inner, flag);
+                    let inner = remove_block(inner, &mut entry);
+                    removed.push(BaseInst::Block(inner, flag, pos));
+                }
+                other => {
+                    state.step(&other);
+                    removed.push(other);
+                }
+            }
+        }
+        removed
+    }
+    remove_block(prog, &mut State::new(cell, zeroed))
+}
+
+/// Most instructions a loop may take to run at compile time, which bounds its trip count.
+const MAX_EVAL_STEPS: usize = 1 << 14;
+
+/// Most `Inc`s a cell set by an evaluated loop may take, so wide cells holding large values
+/// keep their loop.
+const MAX_SET_INCS: usize = 4;
+
+/// Most bytes an evaluated loop may print, so a loop printing a long text keeps its loop.
+const MAX_EVAL_OUTPUT: usize = 256;
+
+/// Runs loops whose cells are all known constants at compile time, as in the nested counters
+/// of `++++[>++++[>++++<-]<-]`, and replaces them with the values they leave. A loop that prints,
+/// such as `[>.+<-]` over a known counter, becomes the table of bytes it prints, written from its
+/// counter cell one `Output` each. Loops that read input, read a cell that is not known, print
+/// more than 256 bytes or take more than 16384 instructions are kept.
+/// `zeroed` says whether the tape starts out zeroed.
+pub fn evaluate_constant_loops(prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool) -> Vec<BaseInst> {
+    fn evaluate_block(prog: Vec<BaseInst>, state: &mut State<Value>) -> Vec<BaseInst> {
+        let mut evaluated = Vec::with_capacity(prog.len());
+        for inst in prog {
+            match inst {
+                BaseInst::Block(inner, flag, pos) => {
+                    let block = BaseInst::Block(inner, flag, pos);
+                    if let Some(sets) = evaluate(&block, state)
+                        && decision!("evaluated loop {block} into {} instructions", sets.len())
+                    {
+                        for set in sets {
+                            state.step(&set);
+                            evaluated.push(set);
+                        }
+                        continue;
+                    }
+                    let BaseInst::Block(inner, flag, pos) = block else {
+                        unreachable!()
+                    };
+                    let mut entry = state.step_loop(&inner, flag);
+                    let inner = evaluate_block(inner, &mut entry);
+                    evaluated.push(BaseInst::Block(inner, flag, pos));
+                }
+                other => {
+                    state.step(&other);
+                    evaluated.push(other);
+                }
+            }
+        }
+        evaluated
+    }
+    evaluate_block(prog, &mut State::new(cell, zeroed))
+}
+
+/// Runs `loop_` on the constants `state` knows and returns the instructions printing what it
+/// prints and setting the cells it changes, or `None` when it cannot be run at compile time.
+fn evaluate(loop_: &BaseInst, state: &State<Value>) -> Option<Vec<BaseInst>> {
+    let mask = state.mask();
+    let mut eval = Eval {
+        state,
+        cells: BTreeMap::new(),
+        ptr: state.ptr(),
+        steps: 0,
+        mask,
+        output: Vec::new(),
+    };
+    eval.run(core::slice::from_ref(loop_))?;
+    let mut sets = Vec::new();
+    // The printing runs through the loop's own cell, which then holds the last value printed
+    // and is set back below even where the loop left it as it was, as a loop that moves the
+    // pointer may.
+    let printed = !eval.output.is_empty();
+    let mut last = state.current().constant.0?;
+    for (inst, value) in eval.output {
+        push_incs(&mut sets, value.wrapping_sub(last) & mask, mask)?;
+        sets.push(inst);
+        last = value;
+    }
+    let mut ptr = state.ptr();
+    for (&pos, &value) in &eval.cells {
+        if state.get(pos).constant.0 == Some(value) && !(printed && pos == state.ptr()) {
+            continue;
+        }
+        if pos != ptr {
+            sets.push(BaseInst::Shift(pos.checked_sub(ptr)?));
+            ptr = pos;
+        }
+        sets.push(BaseInst::Reset);
+        push_incs(&mut sets, value, mask)?;
+    }
+    if eval.ptr != ptr {
+        sets.push(BaseInst::Shift(eval.ptr.checked_sub(ptr)?));
+    }
+    Some(sets)
+}
+
+/// Pushes the `Inc`s adding `value`, as the shortest signed distance from zero split into
+/// sign-extended steps, or `None` when that takes more than [`MAX_SET_INCS`].
+fn push_incs(sets: &mut Vec<BaseInst>, value: u32, mask: u32) -> Option<()> {
+    let mut value = if value > mask / 2 {
+        value as i64 - mask as i64 - 1
+    } else {
+        value as i64
+    };
+    let start = sets.len();
+    while value != 0 {
+        let step = value.clamp(i8::MIN as i64, i8::MAX as i64);
+        sets.push(BaseInst::Inc(step as i8 as u8));
+        value -= step;
+        if sets.len() - start > MAX_SET_INCS {
+            return None;
+        }
+    }
+    Some(())
+}
+
+// Concrete cells of a loop run at compile time, read from the analysis the first time they are used.
+// A run moving past the ends of `i32` is given up on, like one running too long.
+struct Eval<'a> {
+    state: &'a State<Value>,
+    cells: BTreeMap<i32, u32>,
+    ptr: i32,
+    steps: usize,
+    mask: u32,
+    /// Each `Output` or `ErrOutput` run, with the value of the cell it printed.
+    output: Vec<(BaseInst, u32)>,
+}
+
+impl Eval<'_> {
+    fn read(&mut self, pos: i32) -> Option<u32> {
+        if let Some(&value) = self.cells.get(&pos) {
+            return Some(value);
+        }
+        let value = self.state.get(pos).constant.0?;
+        self.cells.insert(pos, value);
+        Some(value)
+    }
+
+    fn add(&mut self, pos: i32, inc: u32) -> Option<()> {
+        let value = self.read(pos)?.wrapping_add(inc) & self.mask;
+        self.cells.insert(pos, value);
+        Some(())
+    }
+
+    fn tick(&mut self) -> Option<()> {
+        self.steps += 1;
+        (self.steps <= MAX_EVAL_STEPS).then_some(())
+    }
+
+    /// The cells from `offset` past the pointer on, `len` of them.
+    fn cells_at(&self, offset: i32, len: i16) -> Option<Range<i32>> {
+        let start = self.ptr.checked_add(offset)?;
+        Some(start..start.checked_add(len as i32)?)
+    }
+
+    fn run(&mut self, prog: &[BaseInst]) -> Option<()> {
+        let sext = |value: u8| value as i8 as u32;
+        for inst in prog {
+            self.tick()?;
+            match inst {
+                BaseInst::Shift(delta) => self.ptr = self.ptr.checked_add(*delta)?,
+                BaseInst::Inc(inc) => self.add(self.ptr, sext(*inc))?,
+                BaseInst::Reset => {
+                    self.cells.insert(self.ptr, 0);
+                }
+                BaseInst::Output | BaseInst::ErrOutput if self.output.len() < MAX_EVAL_OUTPUT => {
+                    let value = self.read(self.ptr)?;
+                    self.output.push((inst.clone(), value));
+                }
+                BaseInst::Output | BaseInst::ErrOutput | BaseInst::Input | BaseInst::Random => return None,
+                BaseInst::Mul(offset, weight) => {
+                    let src = self.read(self.ptr)?;
+                    if src != 0 {
+                        self.add(self.ptr.checked_add(*offset)?, src.wrapping_mul(sext(*weight)))?;
+                    }
+                }
+                BaseInst::Fill(start, len, value) => {
+                    for pos in self.cells_at(*start, *len)? {
+                        self.cells.insert(pos, sext(*value) & self.mask);
+                    }
+                }
+                BaseInst::BlockMove(offset, len) => {
+                    for (pos, dst) in self.cells_at(0, *len)?.zip(self.cells_at(*offset, *len)?) {
+                        let value = self.read(pos)?;
+                        self.cells.insert(dst, value);
+                        self.cells.insert(pos, 0);
+                    }
+                }
+                BaseInst::Seek(stride) => {
+                    while self.read(self.ptr)? != 0 {
+                        self.tick()?;
+                        self.ptr = self.ptr.checked_add(*stride)?;
+                    }
+                }
+                BaseInst::BoundedSeek(stride, max) => {
+                    for _ in 0..*max {
+                        if self.read(self.ptr)? == 0 {
+                            break;
+                        }
+                        self.tick()?;
+                        self.ptr = self.ptr.checked_add(*stride)?;
+                    }
+                }
+                BaseInst::Skip(stride, incs) => {
+                    while self.read(self.ptr)? != 0 {
+                        self.tick()?;
+                        for &(inc, offset) in incs {
+                            self.add(self.ptr.checked_add(offset as i32)?, sext(inc))?;
+                        }
+                        self.ptr = self.ptr.checked_add(*stride)?;
+                    }
+                }
+                BaseInst::Block(body, ..) => {
+                    while self.read(self.ptr)? != 0 {
+                        self.tick()?;
+                        self.run(body)?;
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// Collapses copy chains such as `[->+<]>[->+<]`, where a cell is moved into a temporary that
+/// is known to be zero and the temporary is moved on right away, into `Mul`s that write the
+/// final destinations directly. `zeroed` says whether the tape starts out zeroed.
+pub fn propagate_mul_chains(prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool) -> Vec<BaseInst> {
+    // The last `Mul`s-then-`Reset` group emitted, followed by nothing but `Shift`s.
+    struct Group {
+        start: usize,
+        src: i32,
+        // Absolute target positions and their weights.
+        targets: BTreeMap<i32, u8>,
+        // Targets that were zero before the group ran.
+        zero: BTreeSet<i32>,
+    }
+    fn merge(group: &mut Group, targets: &[(i32, u8)], state: &State<Value>, cell: CellWidth) -> bool {
+        let temp = state.ptr();
+        let Some(&temp_weight) = group.targets.get(&temp) else {
+            return false;
+        };
+        if !group.zero.contains(&temp) {
+            return false;
+        }
+        let mut merged = group.targets.clone();
+        merged.remove(&temp);
+        for &(pos, weight) in targets {
+            if pos == group.src {
+                return false;
+            }
+            let Some(weight) = combine_mul(temp_weight, weight, cell) else {
+                return false;
+            };
+            let entry = merged.entry(pos).or_insert(0);
+            match combine_inc(*entry, weight, cell) {
+                Some(sum) => *entry = sum,
+                None => return false,
+            }
+        }
+        for &(pos, _) in targets {
+            if !group.targets.contains_key(&pos) && state.get(pos).is_zero() {
+                group.zero.insert(pos);
+            }
+        }
+        group.zero.remove(&temp);
+        group.targets = merged;
+        true
+    }
+    fn propagate_block(prog: Vec<BaseInst>, state: &mut State<Value>, cell: CellWidth) -> Vec<BaseInst> {
+        let mut propagated = Vec::with_capacity(prog.len());
+        let mut last: Option<Group> = None;
+        let mut iter = prog.into_iter().peekable();
+        while let Some(inst) = iter.next() {
+            match inst {
+                BaseInst::Mul(offset, weight) => {
+                    let mut targets = vec![(state.ptr().wrapping_add(offset), weight)];
+                    while let Some(&BaseInst::Mul(offset, weight)) = iter.peek() {
+                        targets.push((state.ptr().wrapping_add(offset), weight));
+                        iter.next();
+                    }
+                    if iter.peek() != Some(&BaseInst::Reset) {
+                        for &(pos, weight) in &targets {
+                            propagated.push(BaseInst::Mul(pos.wrapping_sub(state.ptr()), weight));
+                            state.step(&BaseInst::Mul(pos.wrapping_sub(state.ptr()), weight));
+                        }
+                        last = None;
+                        continue;
+                    }
+                    iter.next();
+                    if let Some(group) = &mut last
+                        && merge(group, &targets, state, cell)
+                    {
+                        let end = propagated[group.start..]
+                            .iter()
+                            .position(|ins| matches!(ins, BaseInst::Reset))
+                            .unwrap()
+                            + group.start;
+                        let muls = group
+                            .targets
+                            .iter()
+                            .filter(|&(_, &weight)| weight != 0)
+                            .map(|(&pos, &weight)| BaseInst::Mul(pos.wrapping_sub(group.src), weight));
+                        // A refused merge falls through to start a new group, so the one merged
+                        // into is dropped either way.
+                        if decision!(
+                            "collapsed Mul chain through the temporary at offset {} into {:?}",
+                            state.ptr().wrapping_sub(group.src),
+                            muls.clone().collect::<Vec<_>>()
+                        ) {
+                            propagated.splice(group.start..end, muls);
+                            for &(pos, _) in &targets {
+                                state.forget(pos);
+                            }
+                            state.set(state.ptr(), Value::constant(0));
+                            continue;
+                        }
+                    }
+                    let zero = targets
+                        .iter()
+                        .filter(|&&(pos, _)| state.get(pos).is_zero())
+                        .map(|&(pos, _)| pos)
+                        .collect();
+                    let map: BTreeMap<i32, u8> = targets.iter().copied().collect();
+                    last = (map.len() == targets.len()).then_some(Group {
+                        start: propagated.len(),
+                        src: state.ptr(),
+                        targets: map,
+                        zero,
+                    });
+                    for (pos, weight) in targets {
+                        let mul = BaseInst::Mul(pos.wrapping_sub(state.ptr()), weight);
+                        state.step(&mul);
+                        propagated.push(mul);
+                    }
+                    state.step(&BaseInst::Reset);
+                    propagated.push(BaseInst::Reset);
+                }
+                BaseInst::Shift(offset) => {
+                    state.step(&inst);
+                    propagated.push(BaseInst::Shift(offset));
+                }
+                BaseInst::Block(inner, flag, pos) => {
+                    let mut entry = state.step_loop(&inner, flag);
+                    let inner = propagate_block(inner, &mut entry, cell);
+                    propagated.push(BaseInst::Block(inner, flag, pos));
+                    last = None;
+                }
+                other => {
+                    state.step(&other);
+                    propagated.push(other);
+                    last = None;
+                }
+            }
+        }
+        propagated
+    }
+    propagate_block(prog, &mut State::new(cell, zeroed), cell)
+}
+
+/// Fewest cells a straight-line run must set before it is worth a `Fill`.
+const MIN_FILL: i32 = 4;
+
+/// Folds runs of cells set to the same value one after another, such as `[-]>[-]>[-]>[-]`,
+/// into a single `Fill` followed by the shift to the last cell.
+pub fn fold_fills(mut prog: Vec<BaseInst>) -> Vec<BaseInst> {
+    // Matches a `Reset` and an optional `Inc` at `idx`, returning the value set and the instructions spanned.
+    fn set_at(prog: &[BaseInst], idx: usize) -> Option<(u8, usize)> {
+        match (prog.get(idx), prog.get(idx + 1)) {
+            (Some(BaseInst::Reset), Some(BaseInst::Inc(value))) => Some((*value, 2)),
+            (Some(BaseInst::Reset), _) => Some((0, 1)),
+            _ => None,
+        }
+    }
+    let mut folded = Vec::with_capacity(prog.len());
+    let mut idx = 0;
+    while idx < prog.len() {
+        if let Some((value, width)) = set_at(&prog, idx)
+            && let Some(&BaseInst::Shift(step @ (1 | -1))) = prog.get(idx + width)
+        {
+            let mut len: i32 = 1;
+            let mut end = idx + width;
+            while let Some(BaseInst::Shift(next)) = prog.get(end)
+                && *next == step
+                && len < i16::MAX as i32
+                && let Some((next_value, next_width)) = set_at(&prog, end + 1)
+                && next_value == value
+            {
+                len += 1;
+                end += 1 + next_width;
+            }
+            let start = if step > 0 { 0 } else { 1 - len };
+            if len >= MIN_FILL && decision!("folded {len} sets to {value} into Fill({start}, {len}, {value})") {
+                folded.push(BaseInst::Fill(start, len as i16, value));
+                folded.push(BaseInst::Shift((len - 1) * step));
+                idx = end;
+                continue;
+            }
+        }
+        folded.push(match mem::replace(&mut prog[idx], BaseInst::Reset) {
+            BaseInst::Block(inner, flag, pos) => BaseInst::Block(fold_fills(inner), flag, pos),
+            other => other,
+        });
+        idx += 1;
+    }
+    folded
+}
+
+/// Fewest cells a run of moves must shift before it is worth a `BlockMove`.
+const MIN_BLOCK_MOVE: i32 = 4;
+
+/// Folds runs of neighbouring cells each moved by the same offset, as left by `[->>>>+<<<<]>`
+/// repeated over an array, into a `BlockMove` when the destination is known to be zero and
+/// does not overlap the source. `zeroed` says whether the tape starts out zeroed.
+pub fn fold_block_moves(prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool) -> Vec<BaseInst> {
+    // Matches `Mul(offset, 1)`, `Reset` at `idx` and returns the offset.
+    fn move_at(prog: &[BaseInst], idx: usize) -> Option<i32> {
+        match (prog.get(idx), prog.get(idx + 1)) {
+            (Some(BaseInst::Mul(offset, 1)), Some(BaseInst::Reset)) => Some(*offset),
+            _ => None,
+        }
+    }
+    fn fold_block(mut prog: Vec<BaseInst>, state: &mut State<Value>) -> Vec<BaseInst> {
+        let mut folded = Vec::with_capacity(prog.len());
+        let mut idx = 0;
+        while idx < prog.len() {
+            if let Some(offset) = move_at(&prog, idx)
+                && let Some(&BaseInst::Shift(step @ (1 | -1))) = prog.get(idx + 2)
+            {
+                let mut len: i32 = 1;
+                let mut end = idx + 2;
+                while let Some(BaseInst::Shift(next)) = prog.get(end)
+                    && *next == step
+                    && len < i16::MAX as i32
+                    && move_at(&prog, end + 1) == Some(offset)
+                {
+                    len += 1;
+                    end += 3;
+                }
+                let start = if step > 0 { 0 } else { 1 - len };
+                let dst = state.ptr().wrapping_add(start).wrapping_add(offset);
+                let block_move = BaseInst::BlockMove(offset, len as i16);
+                if len >= MIN_BLOCK_MOVE
+                    && offset.unsigned_abs() >= len as u32
+                    && span(dst, len as i16).all(|pos| state.get(pos).is_zero())
+                    && decision!("folded {len} moves by {offset} into {block_move:?}")
+                {
+                    let shift = BaseInst::Shift(if step > 0 { len - 1 } else { start });
+                    let seq = if step > 0 {
+                        [block_move, shift]
+                    } else {
+                        [shift, block_move]
+                    };
+                    for inst in seq {
+                        state.step(&inst);
+                        folded.push(inst);
+                    }
+                    idx = end;
+                    continue;
+                }
+            }
+            match mem::replace(&mut prog[idx], BaseInst::Reset) {
+                BaseInst::Block(inner, flag, pos) => {
+                    let mut entry = state.step_loop(&inner, flag);
+                    let inner = fold_block(inner, &mut entry);
+                    folded.push(BaseInst::Block(inner, flag, pos));
+                }
+                other => {
+                    state.step(&other);
+                    folded.push(other);
+                }
+            }
+            idx += 1;
+        }
+        folded
+    }
+    fold_block(prog, &mut State::new(cell, zeroed))
+}
+
+/// Most cells a `Skip` adds to on each step. A loop changing more spends long enough on its body
+/// that running it as a block costs little more.
+pub const MAX_SKIP_INCS: usize = 4;
+
+/// Folds scan loops that add to up to [`MAX_SKIP_INCS`] cells per step into `Skip`.
+pub fn fold_skip_loops(prog: Vec<BaseInst>) -> Vec<BaseInst> {
+    let mut folded = Vec::with_capacity(prog.len());
+    for inst in prog {
+        match inst {
+            BaseInst::Block(inner, flag, pos) => {
+                let folded_inner = fold_skip_loops(inner);
+                let mut ptr: i32 = 0;
+                let mut changes: BTreeMap<i32, i8> = BTreeMap::new();
+                let mut valid = true;
+                for ins in &folded_inner {
+                    match ins {
+                        BaseInst::Shift(offset) => match ptr.checked_add(*offset) {
+                            Some(sum) => ptr = sum,
+                            None => {
+                                valid = false;
+                                break;
+                            }
+                        },
+                        BaseInst::Inc(n) => {
+                            // Increments are sign-extended, so a sum past `i8` would change on wider cells.
+                            let change = changes.entry(ptr).or_insert(0);
+                            match change.checked_add(*n as i8) {
+                                Some(sum) => *change = sum,
+                                None => {
+                                    valid = false;
+                                    break;
+                                }
+                            }
+                        }
+                        _ => {
+                            valid = false;
+                            break;
+                        }
+                    }
+                }
+                changes.retain(|_, inc| *inc != 0);
+                let incs: Vec<(u8, i16)> = changes
+                    .iter()
+                    .filter_map(|(&offset, &inc)| Some((inc as u8, i16::try_from(offset).ok()?)))
+                    .collect();
+                // A loop that changes more than its own cell without moving is a counter loop, which
+                // runs faster as a block whose cells are proven to be on the tape.
+                if valid
+                    && !incs.is_empty()
+                    && incs.len() == changes.len()
+                    && incs.len() <= MAX_SKIP_INCS
+                    && (ptr != 0 || incs.len() == 1)
+                    && decision!("folded loop {folded_inner:?} into Skip({ptr}, {incs:?})")
+                {
+                    folded.push(BaseInst::Skip(ptr, incs));
+                } else {
+                    folded.push(BaseInst::Block(folded_inner, flag, pos));
+                }
+            }
+            other => folded.push(other),
+        }
+    }
+    folded
+}
+
+/// Folds balanced loops that decrement their counter by one into `Mul`s followed by `Reset`.
+pub fn fold_mul_loops(prog: Vec<BaseInst>, cell: CellWidth) -> Vec<BaseInst> {
+    let mut folded = Vec::with_capacity(prog.len());
+    for inst in prog {
+        match inst {
+            BaseInst::Block(inner, stable, pos) => {
+                let folded_inner = fold_mul_loops(inner, cell);
+                if stable
+                    && folded_inner
+                        .iter()
+                        .all(|ins| matches!(ins, BaseInst::Inc(..) | BaseInst::Shift(..)))
+                {
+                    let mut ptr: i32 = 0;
+                    let mut changes: BTreeMap<i32, u8> = BTreeMap::new();
+                    let mut representable = true;
+                    changes.insert(0, 0);
+                    for inst in &folded_inner {
+                        match inst {
+                            BaseInst::Inc(val) => {
+                                let entry = changes.entry(ptr).or_insert(0);
+                                match combine_inc(*entry, *val, cell) {
+                                    Some(sum) => *entry = sum,
+                                    None => representable = false,
+                                }
+                            }
+                            BaseInst::Shift(offset) => match ptr.checked_add(*offset) {
+                                Some(sum) => ptr = sum,
+                                None => representable = false,
+                            },
+                            _ => unreachable!(),
+                        }
+                    }
+                    let muls: Vec<BaseInst> = changes
+                        .iter()
+                        .filter(|&(&offset, &weight)| offset != 0 && weight != 0)
+                        .map(|(&offset, &weight)| BaseInst::Mul(offset, weight))
+                        .collect();
+                    if representable
+                        && let Some(&u8::MAX) = changes.get(&0)
+                        && decision!("folded loop {folded_inner:?} into {muls:?} + Reset")
+                    {
+                        folded.extend(muls);
+                        folded.push(BaseInst::Reset);
+                        continue;
+                    }
+                }
+                folded.push(BaseInst::Block(folded_inner, stable, pos));
+            }
+            other => folded.push(other),
+        }
+    }
+    folded
+}
+
+/// Removes writes that are overwritten before being read inside stable blocks. With
+/// [`Eof::Unchanged`] a `,` at the end of input keeps the cell's value, so it overwrites nothing.
+pub fn remove_dead_writes(prog: Vec<BaseInst>, eof: Eof) -> Vec<BaseInst> {
+    fn remove_block(prog: Vec<BaseInst>, stable: bool, eof: Eof) -> Vec<BaseInst> {
+        if !stable {
+            prog.into_iter()
+                .map(|inst| match inst {
+                    BaseInst::Block(inner, flag, pos) => BaseInst::Block(remove_block(inner, flag, eof), flag, pos),
+                    other => other,
+                })
+                .collect()
+        } else {
+            let mut targets = BTreeSet::<i32>::new();
+            let mut ptr: i32 = 0;
+            // Once a `Seek` or `Skip` is crossed the offsets before it are unknown, so nothing more is removed.
+            let mut lost = false;
+            let mut removed = Vec::with_capacity(prog.len());
+            for inst in prog.into_iter().rev() {
+                if lost {
+                    removed.push(match inst {
+                        BaseInst::Block(inner, flag, pos) => BaseInst::Block(remove_block(inner, flag, eof), flag, pos),
+                        other => other,
+                    });
+                    continue;
+                }
+                match inst {
+                    BaseInst::Shift(offset) => {
+                        ptr = ptr.wrapping_sub(offset);
+                        removed.push(BaseInst::Shift(offset));
+                    }
+                    BaseInst::Reset => {
+                        if targets.insert(ptr)
+                            || !decision!("removed dead Reset at offset {ptr} from the end of the loop body")
+                        {
+                            removed.push(BaseInst::Reset);
+                        }
+                    }
+                    BaseInst::Input if eof == Eof::Unchanged => {
+                        targets.remove(&ptr);
+                        removed.push(inst)
+                    }
+                    BaseInst::Input | BaseInst::Random => {
+                        targets.insert(ptr);
+                        removed.push(inst)
+                    }
+                    BaseInst::Fill(start, len, value) => {
+                        let first = ptr.wrapping_add(start);
+                        if !span(first, len).all(|pos| targets.contains(&pos))
+                            || !decision!(
+                                "removed dead Fill of {len} cells at offset {first} from the end of the loop body"
+                            )
+                        {
+                            targets.extend(span(first, len));
+                            removed.push(BaseInst::Fill(start, len, value));
+                        }
+                    }
+                    BaseInst::BlockMove(offset, len) => {
+                        for pos in span(ptr, len) {
+                            targets.remove(&pos);
+                            targets.insert(pos.wrapping_add(offset));
+                        }
+                        removed.push(BaseInst::BlockMove(offset, len));
+                    }
+                    BaseInst::Output | BaseInst::ErrOutput => {
+                        targets.remove(&ptr);
+                        removed.push(inst);
+                    }
+                    BaseInst::Mul(offset, weight) => {
+                        let target = ptr.wrapping_add(offset);
+                        targets.remove(&ptr);
+                        if !targets.contains(&target)
+                            || !decision!(
+                                "removed dead Mul({offset}, {weight}) at offset {ptr} from the end of the loop body"
+                            )
+                        {
+                            removed.push(BaseInst::Mul(offset, weight));
+                        }
+                    }
+                    BaseInst::Inc(n) => {
+                        if !targets.contains(&ptr)
+                            || !decision!("removed dead Inc({n}) at offset {ptr} from the end of the loop body")
+                        {
+                            removed.push(BaseInst::Inc(n));
+                        }
+                    }
+                    BaseInst::Seek(..) | BaseInst::BoundedSeek(..) => {
+                        lost = true;
+                        removed.push(inst);
+                    }
+                    BaseInst::Skip(stride, incs) => {
+                        lost = true;
+                        removed.push(BaseInst::Skip(stride, incs));
+                    }
+                    BaseInst::Block(inner, flag, pos) => {
+                        targets.clear();
+                        let removed_inner = remove_block(inner, flag, eof);
+                        removed.push(BaseInst::Block(removed_inner, flag, pos));
+                    }
+                }
+            }
+            removed.reverse();
+            removed
+        }
+    }
+    remove_block(prog, false, eof)
+}
+
+/// Moves `Reset`s that repeat every iteration of a stable loop to after the loop.
+pub fn move_repeating_resets(prog: Vec<BaseInst>) -> Vec<BaseInst> {
+    let mut moved = Vec::with_capacity(prog.len());
+    for inst in prog {
+        match inst {
+            BaseInst::Block(block, flag, pos) => {
+                let moved_block = move_repeating_resets(block);
+                if flag
+                    && moved_block.iter().all(|ins| {
+                        !matches!(
+                            ins,
+                            BaseInst::Block(..) | BaseInst::Seek(..) | BaseInst::BoundedSeek(..) | BaseInst::Skip(..)
+                        )
+                    })
+                {
+                    // Offsets are summed as `i64`, so the shifts to the moved Resets are exact.
+                    let mut unremovable = BTreeSet::<i64>::new();
+                    unremovable.insert(0);
+                    let mut ptr: i64 = 0;
+                    for ins in &moved_block {
+                        match ins {
+                            BaseInst::Shift(offset) => {
+                                ptr += *offset as i64;
+                            }
+                            BaseInst::Output | BaseInst::ErrOutput => {
+                                unremovable.insert(ptr);
+                            }
+                            BaseInst::Mul(..) => {
+                                unremovable.insert(ptr);
+                            }
+                            BaseInst::BlockMove(_, len) => {
+                                unremovable.extend(ptr..ptr + *len as i64);
+                            }
+                            _ => {}
+                        }
+                    }
+                    let mut seq = Vec::with_capacity(moved_block.len());
+                    let mut removed = Vec::new();
+                    ptr = 0;
+                    for ins in moved_block.iter().rev() {
+                        match ins {
+                            BaseInst::Shift(offset) => {
+                                ptr -= *offset as i64;
+                                seq.push(BaseInst::Shift(*offset));
+                            }
+                            BaseInst::Reset => {
+                                if !unremovable.contains(&ptr) {
+                                    removed.push(ptr);
+                                } else {
+                                    seq.push(BaseInst::Reset);
+                                }
+                            }
+                            BaseInst::Inc(val) => {
+                                unremovable.insert(ptr);
+                                seq.push(BaseInst::Inc(*val));
+                            }
+                            BaseInst::Fill(start, len, value) => {
+                                let first = ptr + *start as i64;
+                                unremovable.extend(first..first + *len as i64);
+                                seq.push(BaseInst::Fill(*start, *len, *value));
+                            }
+                            BaseInst::BlockMove(offset, len) => {
+                                let first = ptr + *offset as i64;
+                                unremovable.extend(first..first + *len as i64);
+                                seq.push(BaseInst::BlockMove(*offset, *len));
+                            }
+                            BaseInst::Mul(offset, weight) => {
+                                unremovable.insert(ptr + *offset as i64);
+                                seq.push(BaseInst::Mul(*offset, *weight));
+                            }
+                            BaseInst::Output => seq.push(BaseInst::Output),
+                            BaseInst::ErrOutput => seq.push(BaseInst::ErrOutput),
+                            // A `,` at the end of input may keep the cell, so a Reset before
+                            // it stays where it is.
+                            BaseInst::Input => {
+                                unremovable.insert(ptr);
+                                seq.push(BaseInst::Input);
+                            }
+                            BaseInst::Random => {
+                                unremovable.insert(ptr);
+                                seq.push(BaseInst::Random);
+                            }
+                            BaseInst::Seek(..)
+                            | BaseInst::BoundedSeek(..)
+                            | BaseInst::Skip(..)
+                            | BaseInst::Block(..) => {
+                                unreachable!()
+                            }
+                        }
+                    }
+                    seq.reverse();
+                    // The Resets are moved only when each shift there and back fits in a `Shift`.
+                    let shifts: Option<Vec<i32>> = removed
+                        .iter()
+                        .map(|&offset| i32::try_from(offset).ok().filter(|&offset| offset != i32::MIN))
+                        .collect();
+                    if let Some(shifts) = shifts
+                        && !shifts.is_empty()
+                        && decision!("moved Resets at offsets {shifts:?} out of loop {seq:?}")
+                    {
+                        let mut moved_sets: Vec<BaseInst> = Vec::new();
+                        for offset in shifts {
+                            moved_sets.push(BaseInst::Shift(offset));
+                            moved_sets.push(BaseInst::Reset);
+                            moved_sets.push(BaseInst::Shift(-offset));
+                        }
+                        seq = vec![BaseInst::Block(seq, flag, pos)];
+                        seq.extend(moved_sets);
+                        moved.push(BaseInst::Block(seq, true, pos));
+                        continue;
+                    }
+                }
+                moved.push(BaseInst::Block(moved_block, flag, pos));
+            }
+            other => moved.push(other),
+        }
+    }
+    moved
+}
+
+/// Puts the tree in a canonical form, so trees that do the same thing in the same way print the
+/// same and diffs between versions show real changes. Runs of `Mul`s are sorted by offset with
+/// the weights on one target merged, instructions that do nothing are dropped, a `Fill` of one
+/// cell becomes a `Reset` and repeated `Reset`s one, and `Shift`s move past the `Fill`s after them
+/// so that they merge. The pipeline runs it last, just before flattening.
+pub fn normalize(prog: Vec<BaseInst>, cell: CellWidth) -> Vec<BaseInst> {
+    fn push_shift(normalized: &mut Vec<BaseInst>, delta: i32) {
+        if let Some(BaseInst::Shift(last)) = normalized.last_mut()
+            && let Some(sum) = last.checked_add(delta)
+        {
+            *last = sum;
+            if *last == 0 {
+                normalized.pop();
+            }
+        } else if delta != 0 {
+            normalized.push(BaseInst::Shift(delta));
+        }
+    }
+    let mut normalized = Vec::with_capacity(prog.len());
+    let mut iter = prog.into_iter().peekable();
+    while let Some(inst) = iter.next() {
+        match inst {
+            BaseInst::Inc(0) | BaseInst::Mul(_, 0) | BaseInst::Fill(_, 0, _) | BaseInst::BlockMove(_, 0)
+                if decision!("dropped {inst}, which does nothing") => {}
+            BaseInst::Shift(delta) => push_shift(&mut normalized, delta),
+            BaseInst::Reset if normalized.last() == Some(&BaseInst::Reset) && decision!("merged repeated Resets") => {}
+            BaseInst::Fill(0, 1, value) if decision!("turned Fill(0, 1, {value}) into Reset") => {
+                if normalized.last() != Some(&BaseInst::Reset) {
+                    normalized.push(BaseInst::Reset);
+                }
+                if value != 0 {
+                    normalized.push(BaseInst::Inc(value));
+                }
+            }
+            BaseInst::Fill(offset, len, value) => match normalized.last() {
+                Some(&BaseInst::Shift(delta))
+                    if offset.checked_add(delta).is_some()
+                        && decision!("moved Shift({delta}) past Fill({offset}, {len}, {value})") =>
+                {
+                    normalized.pop();
+                    normalized.push(BaseInst::Fill(offset + delta, len, value));
+                    normalized.push(BaseInst::Shift(delta));
+                }
+                _ => normalized.push(BaseInst::Fill(offset, len, value)),
+            },
+            // A `Mul` with offset 0 changes the cell the others read, so runs stop there.
+            BaseInst::Mul(offset, weight) if offset != 0 => {
+                let mut run = vec![(offset, weight)];
+                while let Some(&BaseInst::Mul(offset, weight)) = iter.peek()
+                    && offset != 0
+                {
+                    run.push((offset, weight));
+                    iter.next();
+                }
+                let mut sorted = run.clone();
+                sorted.sort_by_key(|&(offset, _)| offset);
+                let mut merged: Vec<(i32, u8)> = Vec::with_capacity(sorted.len());
+                for (offset, weight) in sorted {
+                    if let Some(last) = merged.last_mut()
+                        && last.0 == offset
+                        && let Some(sum) = combine_inc(last.1, weight, cell)
+                    {
+                        last.1 = sum;
+                    } else {
+                        merged.push((offset, weight));
+                    }
+                }
+                merged.retain(|&(_, weight)| weight != 0);
+                if merged != run && decision!("sorted Muls {run:?} into {merged:?}") {
+                    run = merged;
+                }
+                normalized.extend(run.into_iter().map(|(offset, weight)| BaseInst::Mul(offset, weight)));
+            }
+            BaseInst::Block(inner, flag, pos) => normalized.push(BaseInst::Block(normalize(inner, cell), flag, pos)),
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Turns `Seek`s that provably reach a cell known to be zero into `BoundedSeek`s, so the pointer
+/// range of the code around them can still be proven. Runs last, since a pass rewriting the code
+/// before a `BoundedSeek` could clear the zero it stops on. `zeroed` says whether the tape starts
+/// out zeroed.
+pub fn bound_seeks(prog: Vec<BaseInst>, cell: CellWidth, zeroed: bool) -> Vec<BaseInst> {
+    fn bound_block(prog: Vec<BaseInst>, state: &mut State<Value>) -> Vec<BaseInst> {
+        let mut bounded = Vec::with_capacity(prog.len());
+        for inst in prog {
+            let inst = match inst {
+                BaseInst::Block(inner, flag, pos) => {
+                    let mut entry = state.step_loop(&inner, flag);
+                    let inner = bound_block(inner, &mut entry);
+                    bounded.push(BaseInst::Block(inner, flag, pos));
+                    continue;
+                }
+                BaseInst::Seek(stride) => match state.next_zero(stride).and_then(|max| i16::try_from(max).ok()) {
+                    Some(max) if decision!("bounded Seek({stride}) to {max} strides") => {
+                        BaseInst::BoundedSeek(stride, max)
+                    }
+                    _ => inst,
+                },
+                other => other,
+            };
+            state.step(&inst);
+            bounded.push(inst);
+        }
+        bounded
+    }
+    bound_block(prog, &mut State::new(cell, zeroed))
+}
+
+fn combine_mul(lhs: u8, rhs: u8, cell: CellWidth) -> Option<u8> {
+    if cell == CellWidth::U8 {
+        return Some(lhs.wrapping_mul(rhs));
+    }
+    let product = lhs as i8 as i32 * rhs as i8 as i32;
+    if (i8::MIN as i32..=i8::MAX as i32).contains(&product) {
+        Some(product as u8)
+    } else {
+        None
+    }
+}
+
+pub(super) fn combine_inc(lhs: u8, rhs: u8, cell: CellWidth) -> Option<u8> {
+    if cell == CellWidth::U8 {
+        return Some(lhs.wrapping_add(rhs));
+    }
+    let sum = lhs as i8 as i32 + rhs as i8 as i32;
+    if (i8::MIN as i32..=i8::MAX as i32).contains(&sum) {
+        Some(sum as u8)
+    } else {
+        None
+    }
+}
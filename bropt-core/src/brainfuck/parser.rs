@@ -0,0 +1,177 @@
+//! Source text to [`BaseInst`] tree.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::ir::BaseInst;
+use super::labels::annotation;
+
+/// Commands beyond the standard eight that the parser accepts. All are off by default, so their
+/// characters are comments as usual.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Extensions {
+    /// `?` stores a random byte in the current cell.
+    pub rand: bool,
+    /// `:` writes the current cell to the error stream, as `.` does to the output. The `:` of a
+    /// [label annotation](super::labels) stays a comment.
+    pub stderr: bool,
+}
+
+/// Parses brainfuck source into the tree IR, panicking on unbalanced brackets.
+pub fn parse(code: &str) -> Vec<BaseInst> {
+    try_parse(code).unwrap()
+}
+
+/// Parses brainfuck source into the tree IR. Characters other than the eight commands are ignored.
+///
+/// Runs of `+`/`-` and of `<`/`>` are merged as far as the result stays exact on every cell width.
+pub fn try_parse(code: &str) -> Result<Vec<BaseInst>, String> {
+    try_parse_with(code, Extensions::default())
+}
+
+/// A command of the flat buffer the source is scanned into before the tree is built.
+#[derive(Clone, Copy)]
+enum Token {
+    Inc(i8),
+    Shift(i32),
+    Output,
+    ErrOutput,
+    Input,
+    Random,
+    /// A loop with `len` children, the flag [`BaseInst::Block`] carries and its `[` offset.
+    Open {
+        len: usize,
+        stable: bool,
+        pos: Option<u32>,
+    },
+    Close,
+}
+
+/// A loop whose `]` the scan has not reached yet.
+struct OpenBlock {
+    token: usize,
+    len: usize,
+    delta: i32,
+    stable: bool,
+}
+
+/// [`try_parse`] that also accepts the commands enabled in `ext`.
+///
+/// The source is scanned into one flat buffer first, so every block of the tree is allocated
+/// once at its final size instead of growing as the source is read.
+pub fn try_parse_with(code: &str, ext: Extensions) -> Result<Vec<BaseInst>, String> {
+    let mut tokens = Vec::new();
+    let mut open = vec![OpenBlock {
+        token: 0,
+        len: 0,
+        delta: 0,
+        stable: true,
+    }];
+    // End of the label annotation being skipped, whose `:` is not a command.
+    let mut skip_to = 0;
+    for (idx, byte) in code.bytes().enumerate() {
+        if idx < skip_to {
+            continue;
+        }
+        let block = open.last_mut().unwrap();
+        let token = match byte {
+            b'+' | b'-' => {
+                let step = if byte == b'+' { 1 } else { -1 };
+                if let Some(Token::Inc(inc)) = tokens.last_mut()
+                    && let Some(sum) = inc.checked_add(step)
+                {
+                    *inc = sum;
+                    if sum == 0 {
+                        tokens.pop();
+                        block.len -= 1;
+                    }
+                    continue;
+                }
+                Token::Inc(step)
+            }
+            b'>' | b'<' => {
+                let step = if byte == b'>' { 1 } else { -1 };
+                block.delta += step;
+                if let Some(Token::Shift(delta)) = tokens.last_mut() {
+                    *delta += step;
+                    if *delta == 0 {
+                        tokens.pop();
+                        block.len -= 1;
+                    }
+                    continue;
+                }
+                Token::Shift(step)
+            }
+            b'.' => Token::Output,
+            b',' => Token::Input,
+            b'?' if ext.rand => Token::Random,
+            b'{' if ext.stderr => {
+                if let Some((_, _, len)) = annotation(code.as_bytes(), idx) {
+                    skip_to = idx + len;
+                }
+                continue;
+            }
+            b':' if ext.stderr => Token::ErrOutput,
+            b'[' => {
+                block.len += 1;
+                open.push(OpenBlock {
+                    token: tokens.len(),
+                    len: 0,
+                    delta: 0,
+                    stable: true,
+                });
+                tokens.push(Token::Open {
+                    len: 0,
+                    stable: true,
+                    pos: u32::try_from(idx).ok(),
+                });
+                continue;
+            }
+            b']' => {
+                if open.len() == 1 {
+                    return Err("Unmatched ]".to_string());
+                }
+                let inner = open.pop().unwrap();
+                let stable = inner.stable && inner.delta == 0;
+                open.last_mut().unwrap().stable &= stable;
+                if let Token::Open { len, stable: flag, .. } = &mut tokens[inner.token] {
+                    *len = inner.len;
+                    *flag = stable;
+                }
+                Token::Close
+            }
+            _ => continue,
+        };
+        if !matches!(token, Token::Close) {
+            open.last_mut().unwrap().len += 1;
+        }
+        tokens.push(token);
+    }
+    if open.len() > 1 {
+        return Err("Unmatched [".to_string());
+    }
+    Ok(build(&mut tokens.into_iter(), open[0].len))
+}
+
+/// Builds the next `len` siblings of the tree from the scanned tokens.
+fn build<I: Iterator<Item = Token>>(tokens: &mut I, len: usize) -> Vec<BaseInst> {
+    let mut block = Vec::with_capacity(len);
+    while block.len() < len {
+        block.push(match tokens.next().unwrap() {
+            Token::Inc(inc) => BaseInst::Inc(inc as u8),
+            Token::Shift(delta) => BaseInst::Shift(delta),
+            Token::Output => BaseInst::Output,
+            Token::ErrOutput => BaseInst::ErrOutput,
+            Token::Input => BaseInst::Input,
+            Token::Random => BaseInst::Random,
+            Token::Open { len, stable, pos } => {
+                let body = build(tokens, len);
+                tokens.next();
+                BaseInst::Block(body, stable, pos)
+            }
+            Token::Close => unreachable!("every Close is consumed with its Open"),
+        });
+    }
+    block
+}
@@ -0,0 +1,62 @@
+//! Seeded pseudo-random numbers for the `?` extension and the obfuscator, and the seed every
+//! one of them starts from.
+
+/// SplitMix64, which is enough to make a run reproducible from its seed. The default seed is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// The generator's state, which [`Rng::new`] restores.
+    pub fn state(&self) -> u64 {
+        self.0
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+/// The number all of a run's chance comes from, whether the bytes `?` stores or the rewrites
+/// the obfuscator picks, so anything bropt does at random happens the same way again given the
+/// same seed. Tools take their seed from here rather than from the clock themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Seed(u64);
+
+impl Seed {
+    pub fn new(value: u64) -> Self {
+        Seed(value)
+    }
+
+    /// A seed that differs on every call, for runs not given one. Print its
+    /// [`value`](Self::value) to be able to repeat the run.
+    #[cfg(feature = "std")]
+    pub fn from_clock() -> Self {
+        use std::hash::BuildHasher;
+        Seed(std::collections::hash_map::RandomState::new().hash_one(std::time::Instant::now()))
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    /// A generator starting from this seed.
+    pub fn rng(&self) -> Rng {
+        Rng::new(self.0)
+    }
+}
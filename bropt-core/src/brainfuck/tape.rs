@@ -0,0 +1,154 @@
+//! Tape storage the bounds-checked interpreter reads and writes cells through.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+
+use super::interp::Cell;
+
+/// Cells addressed by position. Out-of-range accesses panic.
+pub trait Memory<C: Cell> {
+    /// Number of cells, which the checked interpreter keeps every access below.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, pos: usize) -> C;
+    fn set(&mut self, pos: usize, value: C);
+    fn fill(&mut self, start: usize, len: usize, value: C);
+    /// Copies `len` cells from `src` to `dst`. The ranges may overlap.
+    fn copy_within(&mut self, src: usize, len: usize, dst: usize);
+
+    /// The cells as one slice when they are stored contiguously, which proven loops need to run
+    /// unchecked.
+    fn as_mut_slice(&mut self) -> Option<&mut [C]> {
+        None
+    }
+}
+
+impl<C: Cell> Memory<C> for [C] {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        <[C]>::len(self)
+    }
+
+    #[inline(always)]
+    fn get(&self, pos: usize) -> C {
+        self[pos]
+    }
+
+    #[inline(always)]
+    fn set(&mut self, pos: usize, value: C) {
+        self[pos] = value;
+    }
+
+    #[inline(always)]
+    fn fill(&mut self, start: usize, len: usize, value: C) {
+        self[start..start + len].fill(value);
+    }
+
+    #[inline(always)]
+    fn copy_within(&mut self, src: usize, len: usize, dst: usize) {
+        <[C]>::copy_within(self, src..src + len, dst);
+    }
+
+    #[inline(always)]
+    fn as_mut_slice(&mut self) -> Option<&mut [C]> {
+        Some(self)
+    }
+}
+
+/// Cells per page of a [`SparseTape`].
+pub const PAGE_LEN: usize = 4096;
+
+/// Tape of `len` cells that allocates a page only once a nonzero value is written to it,
+/// for programs that roam over huge ranges but touch few cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseTape<C> {
+    len: usize,
+    pages: BTreeMap<usize, Box<[C]>>,
+}
+
+impl<C: Cell> SparseTape<C> {
+    pub fn new(len: usize) -> Self {
+        SparseTape {
+            len,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of pages allocated so far.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Iterates over the allocated pages as their first position and their cells.
+    pub fn iter_pages(&self) -> impl Iterator<Item = (usize, &[C])> {
+        self.pages.iter().map(|(&page, cells)| (page * PAGE_LEN, &cells[..]))
+    }
+
+    #[inline(always)]
+    fn check(&self, pos: usize) {
+        assert!(
+            pos < self.len,
+            "tape position {pos} out of range for length {}",
+            self.len
+        );
+    }
+}
+
+impl<C: Cell> Memory<C> for SparseTape<C> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, pos: usize) -> C {
+        self.check(pos);
+        match self.pages.get(&(pos / PAGE_LEN)) {
+            Some(page) => page[pos % PAGE_LEN],
+            None => C::ZERO,
+        }
+    }
+
+    #[inline(always)]
+    fn set(&mut self, pos: usize, value: C) {
+        self.check(pos);
+        if let Some(page) = self.pages.get_mut(&(pos / PAGE_LEN)) {
+            page[pos % PAGE_LEN] = value;
+        } else if value != C::ZERO {
+            let mut page = vec![C::ZERO; PAGE_LEN].into_boxed_slice();
+            page[pos % PAGE_LEN] = value;
+            self.pages.insert(pos / PAGE_LEN, page);
+        }
+    }
+
+    fn fill(&mut self, start: usize, len: usize, value: C) {
+        for pos in start..start + len {
+            self.set(pos, value);
+        }
+    }
+
+    fn copy_within(&mut self, src: usize, len: usize, dst: usize) {
+        if dst <= src {
+            for idx in 0..len {
+                self.set(dst + idx, self.get(src + idx));
+            }
+        } else {
+            for idx in (0..len).rev() {
+                self.set(dst + idx, self.get(src + idx));
+            }
+        }
+    }
+}
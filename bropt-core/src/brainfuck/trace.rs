@@ -0,0 +1,124 @@
+//! Spans around the compiler stages, emitted through `tracing` when the feature is enabled,
+//! and the optimizer's decisions, logged through `log` when that feature is and limited by the
+//! fuel `--opt-fuel` sets.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::ir::{BaseInst, Inst, SourceMap};
+
+/// Size a stage's input or output is reported with: bytes of source or instructions of IR.
+pub(crate) trait InstCount {
+    fn inst_count(&self) -> usize;
+}
+
+impl InstCount for &str {
+    fn inst_count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl InstCount for Vec<BaseInst> {
+    fn inst_count(&self) -> usize {
+        self.iter()
+            .map(|inst| match inst {
+                BaseInst::Block(inner, ..) => 1 + inner.inst_count(),
+                _ => 1,
+            })
+            .sum()
+    }
+}
+
+impl InstCount for Vec<Inst> {
+    fn inst_count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl InstCount for (Vec<Inst>, SourceMap) {
+    fn inst_count(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl<T: InstCount> InstCount for Result<T, String> {
+    fn inst_count(&self) -> usize {
+        self.as_ref().map_or(0, T::inst_count)
+    }
+}
+
+/// Runs one stage inside a `pass` span recording its name, input and output sizes and elapsed time.
+#[cfg(feature = "tracing")]
+pub(crate) fn pass<T: InstCount, U: InstCount>(name: &'static str, input: T, stage: impl FnOnce(T) -> U) -> U {
+    let span = tracing::debug_span!(
+        "pass",
+        name,
+        input = input.inst_count(),
+        output = tracing::field::Empty,
+        elapsed_us = tracing::field::Empty
+    );
+    let _entered = span.enter();
+    let start = std::time::Instant::now();
+    let output = stage(input);
+    span.record("elapsed_us", start.elapsed().as_micros() as u64);
+    span.record("output", output.inst_count());
+    output
+}
+
+/// Runs one stage; spans are only emitted with the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn pass<T: InstCount, U: InstCount>(_name: &'static str, input: T, stage: impl FnOnce(T) -> U) -> U {
+    stage(input)
+}
+
+/// Rewrites the passes may still make, or `usize::MAX` for no limit. It is shared by every
+/// thread, like the log the decisions go to.
+static FUEL: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Runs `stage` letting the passes make only the first `fuel` rewrites, when given.
+pub(crate) fn with_fuel<T>(fuel: Option<usize>, stage: impl FnOnce() -> T) -> T {
+    let Some(fuel) = fuel else {
+        return stage();
+    };
+    let saved = FUEL.swap(fuel.min(usize::MAX - 1), Ordering::Relaxed);
+    let output = stage();
+    FUEL.store(saved, Ordering::Relaxed);
+    output
+}
+
+/// Spends one unit of fuel, returning whether the pass may make its rewrite.
+pub(crate) fn spend_fuel() -> bool {
+    FUEL.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |fuel| match fuel {
+        usize::MAX => Some(fuel),
+        0 => None,
+        _ => Some(fuel - 1),
+    })
+    .is_ok()
+}
+
+/// Decides whether a pass makes a rewrite, spending fuel for it, and logs the ones it makes at
+/// debug level; the logging compiles to nothing without the `log` feature.
+#[cfg(feature = "log")]
+macro_rules! decision {
+    ($($arg:tt)*) => {{
+        let allowed = $crate::brainfuck::trace::spend_fuel();
+        if allowed {
+            log::debug!(target: "bropt::opt", $($arg)*);
+        }
+        allowed
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! decision {
+    ($($arg:tt)*) => {{
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+        $crate::brainfuck::trace::spend_fuel()
+    }};
+}
+
+pub(crate) use decision;
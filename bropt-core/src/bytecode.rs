@@ -0,0 +1,215 @@
+//! Compiled programs as bytes, so they can be saved to `.bop` files and run or linked later
+//! without recompiling.
+//!
+//! The format is little-endian: the magic `BROPTPRG`, a version byte, the cell width in bytes,
+//! a flags byte whose lowest bit marks a program compiled for a zeroed tape and whose next two
+//! bits hold what `,` stores at the end of input as 0 for zero, 1 for minus one and 2 for
+//! unchanged, the optimization level, an extensions byte whose lowest bit enables `?` and next bit
+//! `:`, the length of the source and the instruction count, then each instruction as its opcode,
+//! `inc`, `delta` and `arg`, followed by one source map entry per instruction with `u32::MAX` for
+//! none.
+//!
+//! Jump targets are not trusted: loading checks that the brackets balance and relinks them.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::brainfuck::{CellWidth, Eof, Extensions, Inst, InstType, SourceMap, link_brackets, thread_jumps_with_map};
+use crate::engine::Program;
+use crate::snapshot::Reader;
+
+const MAGIC: &[u8; 8] = b"BROPTPRG";
+const VERSION: u8 = 2;
+
+/// Opcodes in the order their bytes number them.
+const OPCODES: [InstType; 16] = [
+    InstType::ShiftInc,
+    InstType::Output,
+    InstType::Input,
+    InstType::Random,
+    InstType::Seek,
+    InstType::Skip,
+    InstType::Set,
+    InstType::Mulzero,
+    InstType::Mul,
+    InstType::Fill,
+    InstType::BlockMove,
+    InstType::Open,
+    InstType::Close,
+    InstType::ErrOutput,
+    InstType::SkipInc,
+    InstType::BoundedSeek,
+];
+
+/// Bytes of an instruction and of its source map entry.
+const INST_BYTES: usize = 12;
+
+/// The flags byte of `prog`.
+fn flags(prog: &Program) -> u8 {
+    let eof = match prog.eof() {
+        Eof::Zero => 0,
+        Eof::MinusOne => 1,
+        Eof::Unchanged => 2,
+    };
+    !prog.preloaded() as u8 | eof << 1
+}
+
+/// An instruction as its opcode, `inc`, `delta` and `arg`.
+fn encode(inst: &Inst) -> [u8; 8] {
+    let opcode = OPCODES.iter().position(|&cmd| cmd == inst.cmd).unwrap();
+    let mut bytes = [opcode as u8, inst.inc, 0, 0, 0, 0, 0, 0];
+    bytes[2..4].copy_from_slice(&inst.delta.to_le_bytes());
+    bytes[4..].copy_from_slice(&inst.arg.to_le_bytes());
+    bytes
+}
+
+impl Program {
+    /// Serializes the compiled program, which [`Program::from_bytes`] loads back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 21 + self.insts().len() * INST_BYTES);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[
+            VERSION,
+            self.cell().bytes() as u8,
+            flags(self),
+            self.opt_level(),
+            self.extensions().rand as u8 | (self.extensions().stderr as u8) << 1,
+        ]);
+        bytes.extend_from_slice(&(self.source_len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.insts().len() as u64).to_le_bytes());
+        for inst in self.insts() {
+            bytes.extend_from_slice(&encode(inst));
+        }
+        for ip in 0..self.insts().len() {
+            let pos = self.source_map().loop_at(ip).unwrap_or(u32::MAX);
+            bytes.extend_from_slice(&pos.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// A 64-bit FNV-1a hash of the instructions and the cell width, tape, end of input and
+    /// extensions they run with, encoded as [`Program::to_bytes`] encodes them, for caching compiled programs and
+    /// checking that two builds compile a source the same way. The hash stays the same across
+    /// platforms and across versions that keep the `.bop` format. The source map, source length
+    /// and optimization level are left out, so sources that compile to the same instructions
+    /// share a fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        feed(&[
+            VERSION,
+            self.cell().bytes() as u8,
+            flags(self),
+            self.extensions().rand as u8 | (self.extensions().stderr as u8) << 1,
+        ]);
+        feed(&(self.insts().len() as u64).to_le_bytes());
+        for inst in self.insts() {
+            feed(&encode(inst));
+        }
+        hash
+    }
+
+    /// Loads a program serialized by [`Program::to_bytes`], rejecting instructions the compiler
+    /// could not have produced.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, String> {
+        if !bytes.starts_with(MAGIC) {
+            return Err("not a compiled bropt program".into());
+        }
+        let mut reader = Reader {
+            bytes,
+            pos: MAGIC.len(),
+            what: "compiled program",
+        };
+        let &[version, width, flags, opt_level, extensions] = reader.take(5)? else {
+            unreachable!()
+        };
+        if version != VERSION {
+            return Err(format!("unsupported compiled program version {version}"));
+        }
+        let cell = match width {
+            1 => CellWidth::U8,
+            2 => CellWidth::U16,
+            4 => CellWidth::U32,
+            _ => return Err(format!("unsupported cell width of {width} bytes")),
+        };
+        let eof = match flags >> 1 & 3 {
+            0 => Eof::Zero,
+            1 => Eof::MinusOne,
+            2 => Eof::Unchanged,
+            _ => return Err("unsupported end of input behavior".into()),
+        };
+        if opt_level > 2 {
+            return Err(format!("unsupported optimization level {opt_level}"));
+        }
+        let source_len = reader.u64()? as usize;
+        let len = reader.u64()? as usize;
+        if len > (bytes.len() - reader.pos) / INST_BYTES {
+            return Err("compiled program is truncated".into());
+        }
+        let mut insts: Vec<Inst> = Vec::with_capacity(len);
+        let mut open = Vec::new();
+        for ip in 0..len {
+            let field = reader.take(8)?;
+            let inst = Inst {
+                cmd: *OPCODES
+                    .get(field[0] as usize)
+                    .ok_or_else(|| format!("instruction {ip} has unknown opcode {}", field[0]))?,
+                inc: field[1],
+                delta: i16::from_le_bytes([field[2], field[3]]),
+                arg: i32::from_le_bytes(field[4..8].try_into().unwrap()),
+            };
+            let valid = match inst.cmd {
+                InstType::Fill => inst.delta >= 1,
+                InstType::BoundedSeek => inst.delta >= 0,
+                InstType::BlockMove => inst.delta >= 1 && inst.arg.unsigned_abs() >= inst.delta as u32,
+                InstType::Open => {
+                    open.push(ip);
+                    true
+                }
+                InstType::Close => open
+                    .pop()
+                    .is_some_and(|start| insts[start].inc == inst.inc && insts[start].delta == inst.delta),
+                _ => true,
+            };
+            if !valid {
+                return Err(format!("instruction {ip} is malformed"));
+            }
+            insts.push(inst);
+        }
+        if !open.is_empty() {
+            return Err("compiled program has unbalanced loops".into());
+        }
+        // Each run of `SkipInc`s must end in the `Skip` that runs them.
+        if let Some(ip) = (0..len).find(|&ip| {
+            insts[ip].cmd == InstType::SkipInc
+                && !insts
+                    .get(ip + 1)
+                    .is_some_and(|next| matches!(next.cmd, InstType::SkipInc | InstType::Skip))
+        }) {
+            return Err(format!("instruction {ip} is malformed"));
+        }
+        let mut map = Vec::with_capacity(len);
+        for _ in 0..len {
+            let pos = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+            map.push((pos != u32::MAX).then_some(pos));
+        }
+        link_brackets(&mut insts);
+        let mut map = SourceMap::new(map);
+        let zeroed = flags & 1 != 0;
+        if opt_level >= 1 {
+            (insts, map) = thread_jumps_with_map(insts, &map, zeroed);
+        }
+        let extensions = Extensions {
+            rand: extensions & 1 != 0,
+            stderr: extensions & 2 != 0,
+        };
+        Ok(Program::new(
+            insts, map, cell, zeroed, eof, opt_level, extensions, source_len,
+        ))
+    }
+}
@@ -0,0 +1,55 @@
+//! The benchmark programs from the `benches` directory, built in with their inputs and the output
+//! each should print, so `bropt bench`, `bropt verify --corpus` and anything else timing bropt
+//! run the same copies.
+//!
+//! The expected outputs are those of 8-bit cells wrapping around and `,` storing 0 at the end of
+//! input.
+
+/// A benchmark program with its input and expected output.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    /// Lowercase name, as `bropt bench` takes it.
+    pub name: &'static str,
+    pub code: &'static str,
+    /// Bytes fed to `,`, empty for programs that read nothing.
+    pub input: &'static [u8],
+    pub output: &'static [u8],
+}
+
+macro_rules! entry {
+    ($name:literal, $file:literal) => {
+        entry!($name, $file, &[])
+    };
+    ($name:literal, $file:literal, input) => {
+        entry!($name, $file, include_bytes!(concat!("../benches/", $file, ".in")))
+    };
+    ($name:literal, $file:literal, $input:expr) => {
+        Entry {
+            name: $name,
+            code: include_str!(concat!("../benches/", $file, ".b")),
+            input: $input,
+            output: include_bytes!(concat!("../benches/", $file, ".out")),
+        }
+    };
+}
+
+/// Every program of the corpus, by name.
+pub const CORPUS: &[Entry] = &[
+    entry!("awib", "awib-0.4", input),
+    entry!("collatz", "Collatz", input),
+    entry!("counter", "Counter"),
+    entry!("dbfi", "SelfInt", input),
+    entry!("easyopt", "EasyOpt"),
+    entry!("factor", "Factor", input),
+    entry!("hanoi", "Hanoi"),
+    entry!("life", "Life", input),
+    entry!("long", "Long"),
+    entry!("mandelbrot", "Mandelbrot"),
+    entry!("prime8", "Prime8", input),
+    entry!("sudoku", "Sudoku", input),
+];
+
+/// The entry called `name`, ignoring case.
+pub fn get(name: &str) -> Option<&'static Entry> {
+    CORPUS.iter().find(|entry| entry.name.eq_ignore_ascii_case(name))
+}
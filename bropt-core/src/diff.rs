@@ -0,0 +1,166 @@
+//! Diffs of the optimized IR of two programs, for seeing what a source edit changes once the
+//! optimizer is done with it, or how a minified program compares with its original.
+//!
+//! Trees are compared in the text form [`print_ir`](crate::brainfuck::print_ir) writes, one
+//! instruction per line with loop bodies indented, so a changed loop shows up inside the lines of
+//! the loops around it. Each hunk names the source position of the loop it starts in on either
+//! side, as far as the source maps know them.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::brainfuck::BaseInst;
+use crate::brainfuck::irtext::ir_lines;
+
+/// Lines of unchanged context shown around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Returns a unified diff from `old` to `new`, headed by their names, or `None` when the two
+/// print the same.
+pub fn diff_ir(old: &[BaseInst], new: &[BaseInst], old_name: &str, new_name: &str) -> Option<String> {
+    let old = ir_lines(old);
+    let new = ir_lines(new);
+    let edits = edit_script(&old, &new);
+    if edits.iter().all(|&edit| edit == Edit::Keep) {
+        return None;
+    }
+    // Line of `old` and of `new` each edit starts at.
+    let mut starts = Vec::with_capacity(edits.len() + 1);
+    let (mut a, mut b) = (0, 0);
+    for &edit in &edits {
+        starts.push((a, b));
+        match edit {
+            Edit::Keep => (a, b) = (a + 1, b + 1),
+            Edit::Delete => a += 1,
+            Edit::Insert => b += 1,
+        }
+    }
+    starts.push((a, b));
+    let mut diff = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut idx = 0;
+    while let Some(first) = edits[idx..].iter().position(|&edit| edit != Edit::Keep) {
+        let begin = (idx + first).saturating_sub(CONTEXT).max(idx);
+        // A hunk runs until more than twice the context of unchanged lines separates changes.
+        let mut end = idx + first;
+        let mut keeps = 0;
+        for (at, &edit) in edits.iter().enumerate().skip(end) {
+            if edit == Edit::Keep {
+                keeps += 1;
+                if keeps > 2 * CONTEXT {
+                    break;
+                }
+            } else {
+                keeps = 0;
+                end = at + 1;
+            }
+        }
+        let end = (end + CONTEXT).min(edits.len());
+        let (old_start, new_start) = starts[begin];
+        let (old_end, new_end) = starts[end];
+        diff.push_str(&format!(
+            "@@ -{} +{} @@{}\n",
+            range(old_start, old_end),
+            range(new_start, new_end),
+            location(
+                old.get(old_start).and_then(|line| line.1),
+                new.get(new_start).and_then(|line| line.1)
+            )
+        ));
+        for at in begin..end {
+            let (a, b) = starts[at];
+            let (mark, line) = match edits[at] {
+                Edit::Keep => (' ', &old[a].0),
+                Edit::Delete => ('-', &old[a].0),
+                Edit::Insert => ('+', &new[b].0),
+            };
+            diff.push(mark);
+            diff.push_str(line);
+            diff.push('\n');
+        }
+        idx = end;
+    }
+    Some(diff)
+}
+
+/// A hunk's lines `start..end` as the 1-based start and count unified diffs use.
+fn range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        len => format!("{},{len}", start + 1),
+    }
+}
+
+fn location(old: Option<u32>, new: Option<u32>) -> String {
+    match (old, new) {
+        (Some(old), Some(new)) if old == new => format!(" in the loop at byte {old}"),
+        (Some(old), Some(new)) => format!(" in the loop at byte {old}, byte {new} in the new program"),
+        (Some(old), None) => format!(" in the loop at byte {old}"),
+        (None, Some(new)) => format!(" in the loop at byte {new} of the new program"),
+        (None, None) => String::new(),
+    }
+}
+
+/// The shortest edit script turning the lines of `old` into those of `new`, by Myers' algorithm.
+fn edit_script(old: &[(String, Option<u32>)], new: &[(String, Option<u32>)]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    // Furthest line of `old` reached on each diagonal `k = x - y`, indexed by `k + offset`.
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // The part of `v` diagonals `-d - 1..=d + 1` use, kept before each round `d` to walk back.
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize].0 == new[y as usize].0 {
+                (x, y) = (x + 1, y + 1);
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // `v` starts at diagonal `-d - 1`.
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            (x, y) = (x - 1, y - 1);
+        }
+        if d > 0 {
+            edits.push(if x == prev_x { Edit::Insert } else { Edit::Delete });
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.reverse();
+    edits
+}
@@ -0,0 +1,1256 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::DerefMut;
+
+use crate::bfasm::assemble;
+#[cfg(all(unix, feature = "mmap"))]
+use crate::brainfuck::MappedTape;
+use crate::brainfuck::trace::{self, InstCount};
+use crate::brainfuck::{
+    BaseInst, BatchTape, ByteRead, ByteWrite, Cell, CellWidth, Eof, ExecHooks, Extensions, Inst, InstType, Labels,
+    Memory, NoHooks, OutputChunk, PartialInput, ResourceExhausted, Rng, Seed, SourceMap, SparseTape, Unbounded,
+    Wrapping, append, execute_batch, execute_proven, flatten_with_map, get_offset, optimize_with_passes,
+    peephole_with_map, pointer_range, proven_loops, resume_with_hooks, thread_jumps_with_map, try_parse_with,
+    unbounded, unsafe_execute, validate,
+};
+use crate::graph::cell_graph;
+use crate::report::{html_report, outline};
+use crate::snapshot::{self, Snapshot};
+
+/// How much of a run is bounds-checked.
+#[derive(Debug, Clone, Copy)]
+enum Checks {
+    /// Every access, which instrumented and sparse runs need.
+    All,
+    /// Every access outside the loops [`proven_loops`] bounds.
+    Proven,
+    /// None, with the pointer starting at the given cell.
+    Unchecked(isize),
+}
+
+/// A compiled program. The instructions and source map are shared between clones, so a clone per
+/// thread or per run costs no copy, and [`Program::extend`] copies them only while they are shared.
+#[derive(Debug, Clone)]
+pub struct Program {
+    insts: Arc<Vec<Inst>>,
+    source_map: Arc<SourceMap>,
+    cell: CellWidth,
+    offset: isize,
+    range: Option<(isize, isize)>,
+    zeroed: bool,
+    eof: Eof,
+    opt_level: u8,
+    extensions: Extensions,
+    source_len: usize,
+    tree_len: usize,
+}
+
+impl Program {
+    /// Assembles a program from flat instructions and the settings they were compiled with.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        insts: Vec<Inst>,
+        source_map: SourceMap,
+        cell: CellWidth,
+        zeroed: bool,
+        eof: Eof,
+        opt_level: u8,
+        extensions: Extensions,
+        source_len: usize,
+    ) -> Program {
+        let offset = get_offset(&insts);
+        let range = pointer_range(&insts);
+        Program {
+            insts: Arc::new(insts),
+            source_map: Arc::new(source_map),
+            cell,
+            offset,
+            range,
+            zeroed,
+            eof,
+            opt_level,
+            extensions,
+            source_len,
+            tree_len: 0,
+        }
+    }
+
+    pub fn insts(&self) -> &[Inst] {
+        &self.insts
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    pub fn cell(&self) -> CellWidth {
+        self.cell
+    }
+
+    /// Whether the program was compiled for a tape that may start out nonzero, as
+    /// [`Bropt::preloaded`] does.
+    pub fn preloaded(&self) -> bool {
+        !self.zeroed
+    }
+
+    /// What `,` was compiled to store at the end of input, which the engine running the program
+    /// must agree on.
+    pub fn eof(&self) -> Eof {
+        self.eof
+    }
+
+    pub fn opt_level(&self) -> u8 {
+        self.opt_level
+    }
+
+    pub fn extensions(&self) -> Extensions {
+        self.extensions
+    }
+
+    /// Length in bytes of the source the program was compiled from.
+    pub fn source_len(&self) -> usize {
+        self.source_len
+    }
+
+    /// Number of tree IR nodes the source parsed to, before optimizing, which is the largest the
+    /// program gets while compiling. Zero for a program read with [`Program::from_bytes`].
+    pub fn tree_len(&self) -> usize {
+        self.tree_len
+    }
+
+    /// Compiles `code` with this program's settings and appends it, without optimizing across
+    /// the seam.
+    pub fn extend(&mut self, code: &str) -> Result<(), String> {
+        let prog = trace::pass("parse", code, |code| try_parse_with(code, self.extensions))?;
+        self.tree_len += prog.inst_count();
+        let (tail, tail_map) = lower(prog, self.opt_level, self.cell, false, self.eof, &mut |_, prog| prog);
+        self.append_code(tail, tail_map.with_labels(Labels::scan(code)), code.len());
+        self.range = pointer_range(&self.insts);
+        Ok(())
+    }
+
+    /// Links `programs` into one that runs them one after another on the same tape, as if their
+    /// sources had been concatenated, without recompiling any of them.
+    ///
+    /// All must use the same cell width and [`Eof`], and every program but the first must be
+    /// compiled with [`Bropt::preloaded`], since it starts on the tape the one before it left. The
+    /// result keeps the optimization level of the first and the extensions of all. Source map
+    /// positions count through the sources one after another.
+    pub fn concat(programs: &[Program]) -> Result<Program, String> {
+        let (first, rest) = programs.split_first().ok_or("no programs to link")?;
+        let mut linked = first.clone();
+        for (idx, prog) in rest.iter().enumerate() {
+            if prog.cell != first.cell {
+                return Err(format!(
+                    "program {} uses {:?} cells but program 1 uses {:?} cells",
+                    idx + 2,
+                    prog.cell,
+                    first.cell
+                ));
+            }
+            if prog.eof != first.eof {
+                return Err(format!(
+                    "program {} stores {:?} at the end of input but program 1 stores {:?}",
+                    idx + 2,
+                    prog.eof,
+                    first.eof
+                ));
+            }
+            if prog.zeroed {
+                return Err(format!(
+                    "program {} assumes a zeroed tape, so it can only come first; compile it as preloaded",
+                    idx + 2
+                ));
+            }
+            linked.append_code(prog.insts.to_vec(), SourceMap::clone(&prog.source_map), prog.source_len);
+            linked.tree_len += prog.tree_len;
+            linked.extensions.rand |= prog.extensions.rand;
+            linked.extensions.stderr |= prog.extensions.stderr;
+        }
+        linked.range = pointer_range(&linked.insts);
+        Ok(linked)
+    }
+
+    /// Appends compiled code that followed this program's source, leaving `range` to the caller.
+    fn append_code(&mut self, tail: Vec<Inst>, tail_map: SourceMap, tail_len: usize) {
+        self.offset = self.offset.max(get_offset(&tail));
+        Arc::make_mut(&mut self.source_map).append(self.insts.len(), tail_map, self.source_len);
+        self.source_len += tail_len;
+        append(Arc::make_mut(&mut self.insts), tail);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tape {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    SparseU8(SparseTape<u8>),
+    SparseU16(SparseTape<u16>),
+    SparseU32(SparseTape<u32>),
+    #[cfg(all(unix, feature = "mmap"))]
+    MappedU8(MappedTape<u8>),
+    #[cfg(all(unix, feature = "mmap"))]
+    MappedU16(MappedTape<u16>),
+    #[cfg(all(unix, feature = "mmap"))]
+    MappedU32(MappedTape<u32>),
+}
+
+impl Tape {
+    pub fn cell(&self) -> CellWidth {
+        match self {
+            Tape::U8(_) | Tape::SparseU8(_) => CellWidth::U8,
+            Tape::U16(_) | Tape::SparseU16(_) => CellWidth::U16,
+            Tape::U32(_) | Tape::SparseU32(_) => CellWidth::U32,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU8(_) => CellWidth::U8,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU16(_) => CellWidth::U16,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU32(_) => CellWidth::U32,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Tape::U8(cells) => cells.len(),
+            Tape::U16(cells) => cells.len(),
+            Tape::U32(cells) => cells.len(),
+            Tape::SparseU8(cells) => cells.len(),
+            Tape::SparseU16(cells) => cells.len(),
+            Tape::SparseU32(cells) => cells.len(),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU8(cells) => cells.len(),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU16(cells) => cells.len(),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU32(cells) => cells.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Value of the cell at `pos`, widened to `u32`. Panics when `pos` is out of range.
+    pub fn get(&self, pos: usize) -> u32 {
+        match self {
+            Tape::U8(cells) => cells[pos] as u32,
+            Tape::U16(cells) => cells[pos] as u32,
+            Tape::U32(cells) => cells[pos],
+            Tape::SparseU8(cells) => cells.get(pos) as u32,
+            Tape::SparseU16(cells) => cells.get(pos) as u32,
+            Tape::SparseU32(cells) => cells.get(pos),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU8(cells) => cells[pos] as u32,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU16(cells) => cells[pos] as u32,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU32(cells) => cells[pos],
+        }
+    }
+
+    /// Stores `value` truncated to the cell width at `pos`. Panics when `pos` is out of range.
+    pub fn set(&mut self, pos: usize, value: u32) {
+        match self {
+            Tape::U8(cells) => cells[pos] = value as u8,
+            Tape::U16(cells) => cells[pos] = value as u16,
+            Tape::U32(cells) => cells[pos] = value,
+            Tape::SparseU8(cells) => cells.set(pos, value as u8),
+            Tape::SparseU16(cells) => cells.set(pos, value as u16),
+            Tape::SparseU32(cells) => cells.set(pos, value),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU8(cells) => cells[pos] = value as u8,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU16(cells) => cells[pos] = value as u16,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU32(cells) => cells[pos] = value,
+        }
+    }
+
+    /// Writes one cell of every page of a dense or mapped tape, so it is committed before the
+    /// program runs.
+    fn prefault(&mut self) {
+        match self {
+            Tape::U8(cells) => touch_pages(cells),
+            Tape::U16(cells) => touch_pages(cells),
+            Tape::U32(cells) => touch_pages(cells),
+            Tape::SparseU8(_) | Tape::SparseU16(_) | Tape::SparseU32(_) => {}
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU8(cells) => touch_pages(cells),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU16(cells) => touch_pages(cells),
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU32(cells) => touch_pages(cells),
+        }
+    }
+
+    /// A 64-bit hash of the cell width and the nonzero cells, so tapes holding the same values
+    /// match whatever their length or storage. Stable across versions, for comparing runs.
+    pub fn digest(&self) -> u64 {
+        snapshot::digest(self)
+    }
+}
+
+/// Rewrites a cell every 4 KiB, the smallest page size, with what it holds. The writes are
+/// volatile, since the compiler may otherwise drop stores of zero to freshly zeroed memory.
+fn touch_pages<C: Cell>(cells: &mut [C]) {
+    let stride = (4096 / size_of::<C>()).max(1);
+    for cell in cells.iter_mut().step_by(stride) {
+        let value = *cell;
+        unsafe { core::ptr::write_volatile(cell, value) };
+    }
+}
+
+/// The final tape and pointer of one run of [`Bropt::run_batch`], as [`Bropt::run`] returns them.
+pub type BatchRun = Result<(Tape, usize), String>;
+
+/// Where a run driven by [`Bropt::run_until_input`] stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pause {
+    /// The program ended with this tape and pointer.
+    Finished(Tape, usize),
+    /// The program reached a `,` after reading every byte given so far. Pass the snapshot to
+    /// [`Bropt::resume_until_input`] with more input, or to [`Bropt::resume`] with a reader that
+    /// ends to let the program see the end of input.
+    NeedsInput(Snapshot),
+    /// The program reached a `.` with the requested chunk of output already full. Pass the
+    /// snapshot to [`Bropt::resume_until_output`] for the next chunk.
+    OutputReady(Snapshot),
+}
+
+impl Pause {
+    /// Why the run of `prog` that left `snapshot` stopped.
+    fn of(prog: &Program, snapshot: Snapshot) -> Pause {
+        match prog.insts.get(snapshot.ip) {
+            None => Pause::Finished(snapshot.tape, snapshot.dp),
+            Some(inst) if inst.cmd == InstType::Input => Pause::NeedsInput(snapshot),
+            Some(_) => Pause::OutputReady(snapshot),
+        }
+    }
+}
+
+/// How the tape of a run is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TapeKind {
+    /// A `Vec` of every cell.
+    #[default]
+    Dense,
+    /// Pages allocated on first write. Always bounds-checked.
+    Sparse,
+    /// An anonymous mapping between guard pages, committed as it is touched.
+    #[cfg(all(unix, feature = "mmap"))]
+    Mapped,
+    /// A mapping like `Mapped` backed by transparent huge pages where the kernel has them, for
+    /// fewer TLB misses on large tapes.
+    #[cfg(all(unix, feature = "mmap"))]
+    HugePages,
+}
+
+/// What [`Bropt::safety`] proves about where a program's pointer can go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Safety {
+    /// The pointer stays within these cells of the start, and all of them are on the tape.
+    Proven(isize, isize),
+    /// The pointer stays within these cells of the start, but some of them are off the tape.
+    OffTape(isize, isize),
+    /// Where the pointer goes depends on the tape, because of the scans and drifting loops
+    /// [`unbounded`] lists.
+    Unbounded,
+}
+
+/// Tape length of [`BroptBuilder::conformance`]: as far right as a pointer can go.
+pub const CONFORMANCE_LEN: usize = isize::MAX as usize;
+
+#[derive(Debug, Clone)]
+pub struct Bropt {
+    tape_len: usize,
+    cell: CellWidth,
+    eof: Eof,
+    opt_level: u8,
+    safe: bool,
+    tape: TapeKind,
+    max_memory: Option<usize>,
+    preloaded: bool,
+    extensions: Extensions,
+    seed: Seed,
+    opt_fuel: Option<usize>,
+    prefault: bool,
+}
+
+impl Default for Bropt {
+    fn default() -> Self {
+        Bropt {
+            tape_len: 65536,
+            cell: CellWidth::U8,
+            eof: Eof::Zero,
+            opt_level: 2,
+            safe: true,
+            tape: TapeKind::Dense,
+            max_memory: None,
+            preloaded: false,
+            extensions: Extensions::default(),
+            seed: Seed::default(),
+            opt_fuel: None,
+            prefault: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BroptBuilder {
+    config: Bropt,
+}
+
+impl BroptBuilder {
+    pub fn tape_len(mut self, tape_len: usize) -> Self {
+        self.config.tape_len = tape_len;
+        self
+    }
+
+    pub fn cell(mut self, cell: CellWidth) -> Self {
+        self.config.cell = cell;
+        self
+    }
+
+    pub fn cell_u8(self) -> Self {
+        self.cell(CellWidth::U8)
+    }
+
+    pub fn cell_u16(self) -> Self {
+        self.cell(CellWidth::U16)
+    }
+
+    pub fn cell_u32(self) -> Self {
+        self.cell(CellWidth::U32)
+    }
+
+    pub fn eof(mut self, eof: Eof) -> Self {
+        self.config.eof = eof;
+        self
+    }
+
+    pub fn opt_level(mut self, opt_level: u8) -> Self {
+        self.config.opt_level = opt_level;
+        self
+    }
+
+    /// Bounds-checks every cell access, which is the default. Safe runs still skip the checks for
+    /// programs [`pointer_range`] proves stay on the tape and inside the loops
+    /// [`proven_loops`] bounds; `false` skips them for every program.
+    pub fn safe(mut self, safe: bool) -> Self {
+        self.config.safe = safe;
+        self
+    }
+
+    pub fn tape(mut self, tape: TapeKind) -> Self {
+        self.config.tape = tape;
+        self
+    }
+
+    /// Refuses runs whose tape could grow past `bytes`. Sparse and mapped tapes count at their
+    /// full length, since a program may touch every cell.
+    pub fn max_memory(mut self, bytes: usize) -> Self {
+        self.config.max_memory = Some(bytes);
+        self
+    }
+
+    /// Compiles programs for tapes that may start with nonzero cells, as
+    /// [`run_preloaded`](Bropt::run_preloaded) needs, giving up the optimizations that rely on
+    /// the tape starting out zeroed.
+    pub fn preloaded(mut self, preloaded: bool) -> Self {
+        self.config.preloaded = preloaded;
+        self
+    }
+
+    /// Accepts the commands beyond the standard eight that `extensions` enables.
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.config.extensions = extensions;
+        self
+    }
+
+    /// Seeds the generator `?` draws from, so runs with the same seed store the same bytes.
+    pub fn seed(mut self, seed: Seed) -> Self {
+        self.config.seed = seed;
+        self
+    }
+
+    /// Lets the optimizer make only its first `fuel` rewrites and leave the rest of the program
+    /// as it is, so searching for the smallest fuel that breaks a program finds the rewrite at
+    /// fault. The count is shared with anything else optimizing at the same time.
+    pub fn opt_fuel(mut self, fuel: usize) -> Self {
+        self.config.opt_fuel = Some(fuel);
+        self
+    }
+
+    /// Touches every page of a dense or mapped tape before the run starts, so a benchmark does not
+    /// time the page faults of the cells the program reaches first. Sparse tapes are left as they
+    /// are, since touching them would allocate every page.
+    pub fn prefault(mut self, prefault: bool) -> Self {
+        self.config.prefault = prefault;
+        self
+    }
+
+    /// Switches to the semantics nested interpreters such as dbfi rely on: 8-bit cells that wrap,
+    /// `,` leaving the cell unchanged at end of input, and a sparse tape of [`CONFORMANCE_LEN`]
+    /// cells, so a program can move as far right as it likes while only the pages it writes take
+    /// memory. Later calls can still change any of these.
+    pub fn conformance(self) -> Self {
+        self.cell_u8()
+            .eof(Eof::Unchanged)
+            .tape(TapeKind::Sparse)
+            .tape_len(CONFORMANCE_LEN)
+    }
+
+    pub fn build(self) -> Bropt {
+        self.config
+    }
+}
+
+impl Bropt {
+    pub fn builder() -> BroptBuilder {
+        BroptBuilder::default()
+    }
+
+    pub fn tape_len(&self) -> usize {
+        self.tape_len
+    }
+
+    pub fn cell(&self) -> CellWidth {
+        self.cell
+    }
+
+    pub fn eof(&self) -> Eof {
+        self.eof
+    }
+
+    pub fn opt_level(&self) -> u8 {
+        self.opt_level
+    }
+
+    pub fn safe(&self) -> bool {
+        self.safe
+    }
+
+    pub fn tape(&self) -> TapeKind {
+        self.tape
+    }
+
+    pub fn max_memory(&self) -> Option<usize> {
+        self.max_memory
+    }
+
+    pub fn preloaded(&self) -> bool {
+        self.preloaded
+    }
+
+    pub fn extensions(&self) -> Extensions {
+        self.extensions
+    }
+
+    pub fn seed(&self) -> Seed {
+        self.seed
+    }
+
+    pub fn opt_fuel(&self) -> Option<usize> {
+        self.opt_fuel
+    }
+
+    pub fn prefault(&self) -> bool {
+        self.prefault
+    }
+
+    pub fn compile(&self, code: &str) -> Result<Program, String> {
+        let mut tree_len = 0;
+        let (insts, source_map) = trace::pass("compile", code, |code| -> Result<(Vec<Inst>, SourceMap), String> {
+            let prog = trace::pass("parse", code, |code| try_parse_with(code, self.extensions))?;
+            tree_len = prog.inst_count();
+            Ok(self.lower(prog, &mut |_, prog| prog))
+        })?;
+        let source_map = source_map.with_labels(Labels::scan(code));
+        Ok(self.program(insts, source_map, code.len(), tree_len))
+    }
+
+    /// Compiles a [`bfasm`](crate::bfasm) program with this configuration.
+    pub fn compile_asm(&self, source: &str) -> Result<Program, String> {
+        let mut tree_len = 0;
+        let (insts, source_map) = trace::pass("compile", source, |source| -> Result<(Vec<Inst>, SourceMap), String> {
+            let prog = trace::pass("assemble", source, assemble)?;
+            tree_len = prog.inst_count();
+            Ok(self.lower(prog, &mut |_, prog| prog))
+        })?;
+        Ok(self.program(insts, source_map, source.len(), tree_len))
+    }
+
+    /// Optimizes a tree built in code or deserialized with this configuration, after checking it
+    /// with [`validate`].
+    pub fn optimize(&self, prog: Vec<BaseInst>) -> Result<Vec<BaseInst>, String> {
+        validate(&prog)?;
+        self.optimize_with_passes(prog, &mut |_, prog| prog)
+    }
+
+    /// [`optimize`](Self::optimize) that runs `extra` after every pass, as
+    /// [`optimize_with_passes`] does. The trees `extra` returns are not checked.
+    pub fn optimize_with_passes(
+        &self,
+        prog: Vec<BaseInst>,
+        extra: &mut dyn FnMut(&str, Vec<BaseInst>) -> Vec<BaseInst>,
+    ) -> Result<Vec<BaseInst>, String> {
+        validate(&prog)?;
+        Ok(trace::with_fuel(self.opt_fuel, || {
+            optimize_with_passes(prog, self.opt_level, self.cell, !self.preloaded, self.eof, extra)
+        }))
+    }
+
+    /// Optimizes and flattens a tree built in code, such as by
+    /// [`IrBuilder`](crate::brainfuck::IrBuilder), with this configuration, after checking it with
+    /// [`validate`]. The program has no source, so its source map is
+    /// empty.
+    pub fn compile_ir(&self, prog: Vec<BaseInst>) -> Result<Program, String> {
+        self.compile_ir_with_passes(prog, &mut |_, prog| prog)
+    }
+
+    /// [`compile_ir`](Self::compile_ir) that runs `extra` after every pass, as
+    /// [`optimize_with_passes`] does. The trees `extra` returns are not checked.
+    pub fn compile_ir_with_passes(
+        &self,
+        prog: Vec<BaseInst>,
+        extra: &mut dyn FnMut(&str, Vec<BaseInst>) -> Vec<BaseInst>,
+    ) -> Result<Program, String> {
+        validate(&prog)?;
+        let tree_len = prog.inst_count();
+        let (insts, source_map) = trace::pass("compile", prog, |prog| self.lower(prog, extra));
+        Ok(self.program(insts, source_map, 0, tree_len))
+    }
+
+    fn lower(
+        &self,
+        prog: Vec<BaseInst>,
+        extra: &mut dyn FnMut(&str, Vec<BaseInst>) -> Vec<BaseInst>,
+    ) -> (Vec<Inst>, SourceMap) {
+        trace::with_fuel(self.opt_fuel, || {
+            lower(prog, self.opt_level, self.cell, !self.preloaded, self.eof, extra)
+        })
+    }
+
+    fn program(&self, insts: Vec<Inst>, source_map: SourceMap, source_len: usize, tree_len: usize) -> Program {
+        let prog = Program::new(
+            insts,
+            source_map,
+            self.cell,
+            !self.preloaded,
+            self.eof,
+            self.opt_level,
+            self.extensions,
+            source_len,
+        );
+        Program { tree_len, ..prog }
+    }
+
+    /// Renders a Graphviz graph of which cells of `code` feed which with this configuration.
+    pub fn graph(&self, code: &str) -> Result<String, String> {
+        trace::with_fuel(self.opt_fuel, || cell_graph(code, self.opt_level, self.cell))
+    }
+
+    /// Renders an HTML report of what each loop of `code` compiles to with this configuration.
+    pub fn report(&self, code: &str) -> Result<String, String> {
+        trace::with_fuel(self.opt_fuel, || html_report(code, self.opt_level, self.cell))
+    }
+
+    /// Renders the loop tree of `code`, classifying each loop with this configuration.
+    pub fn outline(&self, code: &str) -> Result<String, String> {
+        trace::with_fuel(self.opt_fuel, || outline(code, self.opt_level, self.cell))
+    }
+
+    /// Whether `prog` is proven to stay on this engine's tape, which is what lets a run skip
+    /// its bounds checks.
+    pub fn safety(&self, prog: &Program) -> Safety {
+        match prog.range {
+            Some((low, high)) if low >= 0 && high < self.tape_len as isize => Safety::Proven(low, high),
+            Some((low, high)) => Safety::OffTape(low, high),
+            None => Safety::Unbounded,
+        }
+    }
+
+    /// Renders the [`safety`](Self::safety) of `prog`, with what keeps it from being proven and
+    /// which bounds checks its runs keep.
+    pub fn analyze(&self, prog: &Program) -> String {
+        let mut text = match self.safety(prog) {
+            Safety::Proven(low, high) => format!(
+                "Proven memory-safe on a {}-cell tape: the pointer stays within cells {}..={}.\n",
+                self.tape_len, low, high
+            ),
+            Safety::OffTape(low, high) => format!(
+                "Not proven memory-safe on a {}-cell tape: the pointer reaches cells {}..={}.\n",
+                self.tape_len, low, high
+            ),
+            Safety::Unbounded => format!(
+                "Not proven memory-safe on a {}-cell tape: where the pointer goes depends on the tape, because of\n",
+                self.tape_len
+            ),
+        };
+        enum Cause {
+            Scans(Option<String>, usize),
+            Drift(String),
+        }
+        // Scans are counted by the loop they are in, and drifting loops are listed one by one.
+        let mut causes = Vec::new();
+        for cause in unbounded(&prog.insts) {
+            match cause {
+                Unbounded::Scan(ip) => {
+                    let place = prog.source_map.describe_loop(ip);
+                    let seen = causes.iter_mut().find_map(|cause| match cause {
+                        Cause::Scans(other, count) if *other == place => Some(count),
+                        _ => None,
+                    });
+                    match seen {
+                        Some(count) => *count += 1,
+                        None => causes.push(Cause::Scans(place, 1)),
+                    }
+                }
+                Unbounded::Drift(ip) => causes.push(Cause::Drift(
+                    prog.source_map
+                        .describe_loop(ip)
+                        .unwrap_or_else(|| format!("the loop at instruction {}", ip)),
+                )),
+            }
+        }
+        for cause in causes {
+            text.push_str(&match cause {
+                Cause::Scans(place, count) => {
+                    let place = place.map_or("outside any loop".to_string(), |place| format!("in {}", place));
+                    match count {
+                        1 => format!("  a scan {}\n", place),
+                        _ => format!("  {} scans {}\n", count, place),
+                    }
+                }
+                Cause::Drift(place) => format!(
+                    "  {}, which ends its iterations away from where it starts them\n",
+                    place
+                ),
+            });
+        }
+        let total = prog.insts.iter().filter(|inst| inst.cmd == InstType::Open).count();
+        let proven = proven_loops(&prog.insts).iter().flatten().count();
+        text.push_str(&match self.checks(prog) {
+            Checks::All => "Runs check every access, as they always do on a sparse tape.\n".to_string(),
+            Checks::Unchecked(_) if self.safe => "Runs skip every bounds check.\n".to_string(),
+            Checks::Unchecked(_) => "Runs skip every bounds check, since checks are turned off.\n".to_string(),
+            Checks::Proven if proven == 0 => "Runs check every access.\n".to_string(),
+            Checks::Proven => format!(
+                "Runs check bounds outside the {} of {} loops proven to stay within their cells.\n",
+                proven, total
+            ),
+        });
+        text
+    }
+
+    pub fn run<R: ByteRead, W: ByteWrite>(
+        &self,
+        prog: &Program,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<(Tape, usize), String> {
+        let snapshot = self.run_tape(
+            prog,
+            self.new_tape()?,
+            (0, 0),
+            self.seed.rng(),
+            input,
+            output,
+            &mut NoHooks,
+            self.checks(prog),
+        )?;
+        Ok((snapshot.tape, snapshot.dp))
+    }
+
+    /// [`run`](Self::run) on a tape whose cells from the program's cell `at` on start out as the
+    /// bytes of `data`, one byte per cell, so a program can work on external data without
+    /// reading it through `,`. `prog` must be compiled by an engine built with
+    /// [`preloaded`](BroptBuilder::preloaded).
+    pub fn run_preloaded<R: ByteRead, W: ByteWrite>(
+        &self,
+        prog: &Program,
+        data: &[u8],
+        at: usize,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<(Tape, usize), String> {
+        let checks = self.checks(prog);
+        let base = match checks {
+            Checks::Unchecked(offset) => offset as usize,
+            _ => 0,
+        };
+        let tape = self.preloaded_tape(prog, base + at, data)?;
+        let snapshot = self.run_tape(prog, tape, (0, 0), self.seed.rng(), input, output, &mut NoHooks, checks)?;
+        Ok((snapshot.tape, snapshot.dp))
+    }
+
+    /// Runs `prog` once for each of `inputs`, each on its own zeroed tape and writing to the
+    /// matching one of `outputs`, and returns what [`run`](Self::run) would for each.
+    ///
+    /// The runs share one interleaved dense tape and advance together while their branches
+    /// agree, so programs whose control flow does not depend on the input pay for dispatch once.
+    /// Where the runs disagree they split into smaller batches, and a run left on its own
+    /// finishes on a checked dense tape.
+    pub fn run_batch<R: ByteRead, W: ByteWrite>(
+        &self,
+        prog: &Program,
+        inputs: &mut [R],
+        outputs: &mut [W],
+    ) -> Result<Vec<BatchRun>, String> {
+        if inputs.len() != outputs.len() {
+            return Err(format!("{} inputs but {} outputs", inputs.len(), outputs.len()));
+        }
+        self.check_program(prog)?;
+        let bytes = self
+            .tape_len
+            .saturating_mul(self.cell.bytes())
+            .saturating_mul(inputs.len());
+        if let Some(limit) = self.max_memory.filter(|&limit| bytes > limit) {
+            return Err(ResourceExhausted {
+                limit,
+                requested: bytes,
+            }
+            .to_string());
+        }
+        Ok(match self.cell {
+            CellWidth::U8 => self.run_batch_cells(prog, inputs, outputs, Tape::U8),
+            CellWidth::U16 => self.run_batch_cells(prog, inputs, outputs, Tape::U16),
+            CellWidth::U32 => self.run_batch_cells(prog, inputs, outputs, Tape::U32),
+        })
+    }
+
+    fn run_batch_cells<C: Cell, R: ByteRead, W: ByteWrite>(
+        &self,
+        prog: &Program,
+        inputs: &mut [R],
+        outputs: &mut [W],
+        wrap: fn(Vec<C>) -> Tape,
+    ) -> Vec<BatchRun> {
+        // Unchecked runs start where the program cannot step left of the tape, as `run` would.
+        let base = match self.checks(prog) {
+            Checks::Unchecked(offset) => offset as usize,
+            _ => 0,
+        };
+        let mut results: Vec<Option<BatchRun>> = inputs.iter().map(|_| None).collect();
+        let mut batches = vec![(
+            (0..inputs.len()).collect::<Vec<_>>(),
+            BatchTape::<C>::new(self.tape_len, inputs.len()),
+            (0, base),
+            self.seed.rng(),
+        )];
+        while let Some((ids, mut tape, start, mut rng)) = batches.pop() {
+            let (ip, dp) = if ids.len() > 1 {
+                execute_batch::<C, Wrapping, R, W>(
+                    &prog.insts,
+                    &mut tape,
+                    start,
+                    self.eof,
+                    &ids,
+                    inputs,
+                    outputs,
+                    &mut rng,
+                )
+            } else {
+                start
+            };
+            if ip >= prog.insts.len() {
+                for (lane, &id) in ids.iter().enumerate() {
+                    results[id] = Some(Ok((wrap(tape.lane(lane)), dp)));
+                }
+                continue;
+            }
+            // Split by the cell the lanes disagree on; when they agree, the instruction left the
+            // tape and every run reports that on its own.
+            let split: Vec<bool> = (0..ids.len())
+                .map(|lane| dp < tape.len() && tape.get(lane, dp) == C::ZERO)
+                .collect();
+            if ids.len() > 1 && split.contains(&true) && split.contains(&false) {
+                let other = tape.split_off(&split);
+                let (mut moved, mut kept) = (Vec::new(), Vec::new());
+                for (&id, &split) in ids.iter().zip(&split) {
+                    if split { moved.push(id) } else { kept.push(id) }
+                }
+                batches.push((kept, tape, (ip, dp), rng));
+                batches.push((moved, other, (ip, dp), rng));
+                continue;
+            }
+            for (lane, &id) in ids.iter().enumerate() {
+                let result = self
+                    .run_tape(
+                        prog,
+                        wrap(tape.lane(lane)),
+                        (ip, dp),
+                        rng,
+                        &mut inputs[id],
+                        &mut outputs[id],
+                        &mut NoHooks,
+                        Checks::All,
+                    )
+                    .map(|snapshot| (snapshot.tape, snapshot.dp));
+                results[id] = Some(result);
+            }
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// The start of a run with `data` preloaded from cell `at` like [`run_preloaded`](Self::run_preloaded),
+    /// to pass to [`resume`](Self::resume) for an instrumented run.
+    pub fn preload(&self, prog: &Program, data: &[u8], at: usize) -> Result<Snapshot, String> {
+        let tape = self.preloaded_tape(prog, at, data)?;
+        Ok(Snapshot {
+            ip: 0,
+            dp: 0,
+            tape,
+            rng: self.seed.rng(),
+        })
+    }
+
+    fn preloaded_tape(&self, prog: &Program, at: usize, data: &[u8]) -> Result<Tape, String> {
+        if prog.zeroed && data.iter().any(|&byte| byte != 0) {
+            return Err("program was compiled for a zeroed tape and cannot run on preloaded data".into());
+        }
+        let mut tape = self.new_tape()?;
+        if at.checked_add(data.len()).is_none_or(|end| end > tape.len()) {
+            return Err(format!(
+                "{} bytes of initial data at cell {} do not fit a {}-cell tape",
+                data.len(),
+                at,
+                tape.len()
+            ));
+        }
+        for (idx, &byte) in data.iter().enumerate() {
+            tape.set(at + idx, byte as u32);
+        }
+        Ok(tape)
+    }
+
+    /// Runs `prog` reporting every step to `hooks`. Instrumented runs are always bounds-checked.
+    pub fn run_with_hooks<R: ByteRead, W: ByteWrite, H: ExecHooks>(
+        &self,
+        prog: &Program,
+        input: &mut R,
+        output: &mut W,
+        hooks: &mut H,
+    ) -> Result<(Tape, usize), String> {
+        let snapshot = self.run_resumable(prog, input, output, hooks)?;
+        Ok((snapshot.tape, snapshot.dp))
+    }
+
+    /// [`run_with_hooks`](Self::run_with_hooks) returning where the run ended, which is before the
+    /// end of the program if [`ExecHooks::should_stop`] stopped it.
+    pub fn run_resumable<R: ByteRead, W: ByteWrite, H: ExecHooks>(
+        &self,
+        prog: &Program,
+        input: &mut R,
+        output: &mut W,
+        hooks: &mut H,
+    ) -> Result<Snapshot, String> {
+        self.run_tape(
+            prog,
+            self.new_tape()?,
+            (0, 0),
+            self.seed.rng(),
+            input,
+            output,
+            hooks,
+            Checks::All,
+        )
+    }
+
+    /// Continues a run of `prog` from `snapshot`, bounds-checked and reporting to `hooks`.
+    pub fn resume<R: ByteRead, W: ByteWrite, H: ExecHooks>(
+        &self,
+        prog: &Program,
+        snapshot: Snapshot,
+        input: &mut R,
+        output: &mut W,
+        hooks: &mut H,
+    ) -> Result<Snapshot, String> {
+        if snapshot.tape.cell() != self.cell {
+            return Err(format!(
+                "snapshot of {:?} cells cannot resume on {:?} cells",
+                snapshot.tape.cell(),
+                self.cell
+            ));
+        }
+        let start = (snapshot.ip, snapshot.dp);
+        self.run_tape(
+            prog,
+            snapshot.tape,
+            start,
+            snapshot.rng,
+            input,
+            output,
+            hooks,
+            Checks::All,
+        )
+    }
+
+    /// Runs `prog` until it finishes or reaches a `,` once every byte of `input` is read, for
+    /// interactive programs driven by a caller that receives input a message at a time. The run
+    /// is bounds-checked.
+    pub fn run_until_input<W: ByteWrite>(&self, prog: &Program, input: &[u8], output: &mut W) -> Result<Pause, String> {
+        let start = self.preload(prog, &[], 0)?;
+        self.resume_until_input(prog, start, input, output)
+    }
+
+    /// Continues a run paused by [`run_until_input`](Self::run_until_input) with the input that
+    /// has arrived since.
+    pub fn resume_until_input<W: ByteWrite>(
+        &self,
+        prog: &Program,
+        snapshot: Snapshot,
+        input: &[u8],
+        output: &mut W,
+    ) -> Result<Pause, String> {
+        let snapshot = self.resume(prog, snapshot, &mut PartialInput::new(input), output, &mut NoHooks)?;
+        Ok(Pause::of(prog, snapshot))
+    }
+
+    /// Runs `prog` until it finishes or is about to write more than `len` bytes, returning the
+    /// output so far, so a caller can pull the output of a program that never stops writing a
+    /// chunk at a time. The run is bounds-checked.
+    pub fn run_until_output<R: ByteRead>(
+        &self,
+        prog: &Program,
+        input: &mut R,
+        len: usize,
+    ) -> Result<(Vec<u8>, Pause), String> {
+        let start = self.preload(prog, &[], 0)?;
+        self.resume_until_output(prog, start, input, len)
+    }
+
+    /// Continues a run paused by [`run_until_output`](Self::run_until_output) for the next `len`
+    /// bytes of output. A reader that waits for input, such as [`PartialInput`], can pause it
+    /// with [`Pause::NeedsInput`] too.
+    pub fn resume_until_output<R: ByteRead>(
+        &self,
+        prog: &Program,
+        snapshot: Snapshot,
+        input: &mut R,
+        len: usize,
+    ) -> Result<(Vec<u8>, Pause), String> {
+        let mut chunk = OutputChunk::new(len);
+        let snapshot = self.resume(prog, snapshot, input, &mut chunk, &mut NoHooks)?;
+        Ok((chunk.into_bytes(), Pause::of(prog, snapshot)))
+    }
+
+    /// Reads a snapshot written by [`Snapshot::to_bytes`] for `prog` onto a tape of this configuration.
+    pub fn load_snapshot(&self, prog: &Program, bytes: &[u8]) -> Result<Snapshot, String> {
+        let mut tape = self.new_tape()?;
+        let (ip, dp, rng) = snapshot::read(bytes, prog, &mut tape)?;
+        Ok(Snapshot { ip, dp, tape, rng })
+    }
+
+    fn check_program(&self, prog: &Program) -> Result<(), String> {
+        if prog.cell != self.cell && prog.cell == CellWidth::U8 {
+            return Err(format!(
+                "program compiled for {:?} cells cannot run on {:?} cells",
+                prog.cell, self.cell
+            ));
+        }
+        if prog.eof != self.eof {
+            return Err(format!(
+                "program compiled for {:?} at the end of input cannot run with {:?}",
+                prog.eof, self.eof
+            ));
+        }
+        Ok(())
+    }
+
+    /// Which accesses an uninstrumented run of `prog` needs to bounds-check.
+    fn checks(&self, prog: &Program) -> Checks {
+        if self.tape == TapeKind::Sparse {
+            Checks::All
+        } else if !self.safe {
+            Checks::Unchecked(prog.offset)
+        } else {
+            match self.safety(prog) {
+                Safety::Proven(..) => Checks::Unchecked(0),
+                Safety::OffTape(..) | Safety::Unbounded => Checks::Proven,
+            }
+        }
+    }
+
+    fn new_tape(&self) -> Result<Tape, String> {
+        let bytes = self.tape_len.saturating_mul(self.cell.bytes());
+        if let Some(limit) = self.max_memory.filter(|&limit| bytes > limit) {
+            return Err(ResourceExhausted {
+                limit,
+                requested: bytes,
+            }
+            .to_string());
+        }
+        let mut tape = match (self.tape, self.cell) {
+            (TapeKind::Dense, CellWidth::U8) => Tape::U8(vec![0; self.tape_len]),
+            (TapeKind::Dense, CellWidth::U16) => Tape::U16(vec![0; self.tape_len]),
+            (TapeKind::Dense, CellWidth::U32) => Tape::U32(vec![0; self.tape_len]),
+            (TapeKind::Sparse, CellWidth::U8) => Tape::SparseU8(SparseTape::new(self.tape_len)),
+            (TapeKind::Sparse, CellWidth::U16) => Tape::SparseU16(SparseTape::new(self.tape_len)),
+            (TapeKind::Sparse, CellWidth::U32) => Tape::SparseU32(SparseTape::new(self.tape_len)),
+            #[cfg(all(unix, feature = "mmap"))]
+            (TapeKind::Mapped, CellWidth::U8) => Tape::MappedU8(MappedTape::new(self.tape_len)?),
+            #[cfg(all(unix, feature = "mmap"))]
+            (TapeKind::Mapped, CellWidth::U16) => Tape::MappedU16(MappedTape::new(self.tape_len)?),
+            #[cfg(all(unix, feature = "mmap"))]
+            (TapeKind::Mapped, CellWidth::U32) => Tape::MappedU32(MappedTape::new(self.tape_len)?),
+            #[cfg(all(unix, feature = "mmap"))]
+            (TapeKind::HugePages, CellWidth::U8) => Tape::MappedU8(MappedTape::with_huge_pages(self.tape_len)?),
+            #[cfg(all(unix, feature = "mmap"))]
+            (TapeKind::HugePages, CellWidth::U16) => Tape::MappedU16(MappedTape::with_huge_pages(self.tape_len)?),
+            #[cfg(all(unix, feature = "mmap"))]
+            (TapeKind::HugePages, CellWidth::U32) => Tape::MappedU32(MappedTape::with_huge_pages(self.tape_len)?),
+        };
+        if self.prefault {
+            tape.prefault();
+        }
+        Ok(tape)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_tape<R: ByteRead, W: ByteWrite, H: ExecHooks>(
+        &self,
+        prog: &Program,
+        tape: Tape,
+        start: (usize, usize),
+        mut rng: Rng,
+        input: &mut R,
+        output: &mut W,
+        hooks: &mut H,
+        checks: Checks,
+    ) -> Result<Snapshot, String> {
+        self.check_program(prog)?;
+        let ((ip, dp), tape) = match tape {
+            Tape::U8(t) => self
+                .run_cells(prog, t, start, input, output, &mut rng, hooks, checks)
+                .map(|(stop, t)| (stop, Tape::U8(t)))?,
+            Tape::U16(t) => self
+                .run_cells(prog, t, start, input, output, &mut rng, hooks, checks)
+                .map(|(stop, t)| (stop, Tape::U16(t)))?,
+            Tape::U32(t) => self
+                .run_cells(prog, t, start, input, output, &mut rng, hooks, checks)
+                .map(|(stop, t)| (stop, Tape::U32(t)))?,
+            Tape::SparseU8(t) => {
+                let (stop, t) = self.run_sparse_cells(prog, t, start, input, output, &mut rng, hooks)?;
+                (stop, Tape::SparseU8(t))
+            }
+            Tape::SparseU16(t) => {
+                let (stop, t) = self.run_sparse_cells(prog, t, start, input, output, &mut rng, hooks)?;
+                (stop, Tape::SparseU16(t))
+            }
+            Tape::SparseU32(t) => {
+                let (stop, t) = self.run_sparse_cells(prog, t, start, input, output, &mut rng, hooks)?;
+                (stop, Tape::SparseU32(t))
+            }
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU8(t) => self
+                .run_cells(prog, t, start, input, output, &mut rng, hooks, checks)
+                .map(|(stop, t)| (stop, Tape::MappedU8(t)))?,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU16(t) => self
+                .run_cells(prog, t, start, input, output, &mut rng, hooks, checks)
+                .map(|(stop, t)| (stop, Tape::MappedU16(t)))?,
+            #[cfg(all(unix, feature = "mmap"))]
+            Tape::MappedU32(t) => self
+                .run_cells(prog, t, start, input, output, &mut rng, hooks, checks)
+                .map(|(stop, t)| (stop, Tape::MappedU32(t)))?,
+        };
+        Ok(Snapshot { ip, dp, tape, rng })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_cells<C: Cell, T: DerefMut<Target = [C]>, R: ByteRead, W: ByteWrite, H: ExecHooks>(
+        &self,
+        prog: &Program,
+        mut data: T,
+        start: (usize, usize),
+        input: &mut R,
+        output: &mut W,
+        rng: &mut Rng,
+        hooks: &mut H,
+        checks: Checks,
+    ) -> Result<((usize, usize), T), String> {
+        let stop = match checks {
+            Checks::All => resume_with_hooks::<C, Wrapping, _, H, R, W>(
+                &prog.insts,
+                &mut data[..],
+                start,
+                self.eof,
+                input,
+                output,
+                rng,
+                hooks,
+            )
+            .map_err(|err| err.with_loops(&prog.insts, &prog.source_map))?,
+            Checks::Proven => {
+                let dp =
+                    execute_proven::<C, Wrapping, _, R, W>(&prog.insts, &mut data[..], self.eof, input, output, rng)
+                        .map_err(|err| err.with_loops(&prog.insts, &prog.source_map))?;
+                (prog.insts.len(), dp)
+            }
+            Checks::Unchecked(offset) => {
+                if offset as usize >= self.tape_len {
+                    return Err("tape is too short for the program".into());
+                }
+                let dp = unsafe {
+                    unsafe_execute::<C, Wrapping, R, W>(&prog.insts, &mut data, offset, self.eof, input, output, rng)
+                };
+                (prog.insts.len(), dp)
+            }
+        };
+        Ok((stop, data))
+    }
+
+    // Sparse tapes are always bounds-checked since their pages are not contiguous.
+    #[allow(clippy::too_many_arguments)]
+    fn run_sparse_cells<C: Cell, R: ByteRead, W: ByteWrite, H: ExecHooks>(
+        &self,
+        prog: &Program,
+        mut data: SparseTape<C>,
+        start: (usize, usize),
+        input: &mut R,
+        output: &mut W,
+        rng: &mut Rng,
+        hooks: &mut H,
+    ) -> Result<((usize, usize), SparseTape<C>), String> {
+        let stop = resume_with_hooks::<C, Wrapping, _, H, R, W>(
+            &prog.insts,
+            &mut data,
+            start,
+            self.eof,
+            input,
+            output,
+            rng,
+            hooks,
+        )
+        .map_err(|err| err.with_loops(&prog.insts, &prog.source_map))?;
+        Ok((stop, data))
+    }
+}
+
+/// Optimizes and flattens `prog`, fusing neighbouring instructions and threading jumps from `-O1`
+/// on. `zeroed` says whether the tape starts out zeroed, `eof` what `,` stores at the end of
+/// input, and `extra` runs after every pass.
+fn lower(
+    prog: Vec<BaseInst>,
+    opt_level: u8,
+    cell: CellWidth,
+    zeroed: bool,
+    eof: Eof,
+    extra: &mut dyn FnMut(&str, Vec<BaseInst>) -> Vec<BaseInst>,
+) -> (Vec<Inst>, SourceMap) {
+    let optimized = optimize_with_passes(prog, opt_level, cell, zeroed, eof, extra);
+    let mut flat = trace::pass("flatten", optimized, flatten_with_map);
+    if opt_level >= 1 {
+        flat = trace::pass("peephole", flat, |(insts, map)| peephole_with_map(insts, &map, cell));
+        flat = trace::pass("thread_jumps", flat, |(insts, map)| {
+            thread_jumps_with_map(insts, &map, zeroed)
+        });
+    }
+    flat
+}
@@ -0,0 +1,143 @@
+//! Execution traces: a line for every instruction a run executes and every byte it reads or
+//! writes, and a streaming comparison of two traces for finding where two runs part ways.
+//!
+//! An instruction's line is `ip <ip> dp <dp> <instruction>`, with the instruction as
+//! [`Inst`] displays it, and a byte's line is `ip <ip> dp <cell> read <byte>`, `read eof`,
+//! `write <byte>` or `write-err <byte>`, after the line of the instruction that moved it.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::brainfuck::{ExecHooks, Inst, IoEvent};
+
+/// Writes the trace of a run to `out`. The first write that fails stops the run, and
+/// [`finish`](Self::finish) returns its error.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W) -> Self {
+        TraceWriter { out, error: None }
+    }
+
+    /// Flushes the trace and returns the writer, or the first error writing it.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush().map(|()| self.out),
+        }
+    }
+
+    fn line(&mut self, args: fmt::Arguments) {
+        if self.error.is_none()
+            && let Err(err) = self.out.write_fmt(args)
+        {
+            self.error = Some(err);
+        }
+    }
+}
+
+impl<W: Write> ExecHooks for TraceWriter<W> {
+    fn on_instruction(&mut self, ip: usize, inst: &Inst, dp: usize) {
+        self.line(format_args!("ip {} dp {} {}\n", ip, dp, inst));
+    }
+
+    fn on_io(&mut self, ip: usize, dp: usize, event: IoEvent) {
+        match event {
+            IoEvent::Read(Some(byte)) => self.line(format_args!("ip {} dp {} read {}\n", ip, dp, byte)),
+            IoEvent::Read(None) => self.line(format_args!("ip {} dp {} read eof\n", ip, dp)),
+            IoEvent::Write(byte) => self.line(format_args!("ip {} dp {} write {}\n", ip, dp, byte)),
+            IoEvent::WriteErr(byte) => self.line(format_args!("ip {} dp {} write-err {}\n", ip, dp, byte)),
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.error.is_some()
+    }
+}
+
+/// The first line where two traces differ, from [`diff_traces`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence {
+    /// Number of the first differing line, counting from 1.
+    pub line: u64,
+    /// The lines both traces share right before it, oldest first.
+    pub before: Vec<String>,
+    /// The first trace from the differing line on, empty if it ended there.
+    pub old: Vec<String>,
+    /// The second trace from the differing line on, empty if it ended there.
+    pub new: Vec<String>,
+}
+
+impl fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first = self.line - self.before.len() as u64;
+        for (number, line) in (first..).zip(&self.before) {
+            writeln!(f, "  {:>10} {}", number, line)?;
+        }
+        for (sign, lines) in [('-', &self.old), ('+', &self.new)] {
+            if lines.is_empty() {
+                writeln!(f, "{} {:>10} (end of trace)", sign, self.line)?;
+            }
+            for (number, line) in (self.line..).zip(lines) {
+                writeln!(f, "{} {:>10} {}", sign, number, line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads `old` and `new` a line at a time and returns where they first differ, with up to
+/// `context` lines around it, or `None` when they are the same. Only the context is kept in
+/// memory, so traces of any length can be compared.
+pub fn diff_traces<A: BufRead, B: BufRead>(
+    mut old: A,
+    mut new: B,
+    context: usize,
+) -> io::Result<Option<TraceDivergence>> {
+    let mut before = VecDeque::with_capacity(context + 1);
+    let (mut a, mut b) = (String::new(), String::new());
+    let mut line = 0;
+    loop {
+        a.clear();
+        b.clear();
+        old.read_line(&mut a)?;
+        new.read_line(&mut b)?;
+        line += 1;
+        if a != b {
+            return Ok(Some(TraceDivergence {
+                line,
+                before: before.into(),
+                old: following(&mut old, &a, context)?,
+                new: following(&mut new, &b, context)?,
+            }));
+        }
+        if a.is_empty() {
+            return Ok(None);
+        }
+        if context > 0 {
+            if before.len() == context {
+                before.pop_front();
+            }
+            before.push_back(a.trim_end_matches('\n').into());
+        }
+    }
+}
+
+/// `first`, the line just read from `trace`, and up to `context` lines after it.
+fn following<R: BufRead>(trace: &mut R, first: &str, context: usize) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    if first.is_empty() {
+        return Ok(lines);
+    }
+    lines.push(first.trim_end_matches('\n').into());
+    let mut next = String::new();
+    while lines.len() <= context && trace.read_line(&mut next)? > 0 {
+        lines.push(next.trim_end_matches('\n').into());
+        next.clear();
+    }
+    Ok(lines)
+}
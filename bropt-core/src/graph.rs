@@ -0,0 +1,166 @@
+//! Graphs of which cells feed which, for reading the data layout of generated code.
+//!
+//! Cells are numbered relative to where the pointer was when it last became unknown, after a
+//! scan or a loop that does not return the pointer to where it started. Each stretch of code
+//! between such points is a region, drawn as its own cluster, since cell numbers from different
+//! regions cannot be compared.
+//!
+//! Loops and cells named in the source's [label annotations](crate::brainfuck::labels) show
+//! their names, cells only in the first region, where cell numbers are still tape positions.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::brainfuck::{BaseInst, CellWidth, Labels, optimize, try_parse};
+
+/// Renders `code` optimized at `level` as a Graphviz DOT graph of its cells.
+///
+/// An edge `a -> b` labeled `*w` means a multiplication adds `w` times cell `a` to cell `b`,
+/// `move` that a block move carries `a` into `b`, and a dashed `loop` edge that `b` changes inside
+/// a loop that runs while `a` is nonzero. Cells are also marked with `in`, `out`, `err` or `rand`
+/// when `,`, `.`, `:` or `?` use them.
+pub fn cell_graph(code: &str, level: u8, cell: CellWidth) -> Result<String, String> {
+    let prog = optimize(try_parse(code)?, level, cell);
+    let mut graph = Graph {
+        regions: Vec::new(),
+        controls: Vec::new(),
+        labels: Labels::scan(code),
+    };
+    let start = graph.region(String::from("start"));
+    graph.block(&prog, start, 0, None);
+    Ok(graph.render())
+}
+
+struct Region {
+    label: String,
+    roles: BTreeMap<i32, BTreeSet<&'static str>>,
+    edges: BTreeMap<(i32, i32), BTreeSet<String>>,
+}
+
+struct Graph {
+    regions: Vec<Region>,
+    /// Region and cell of every stable loop around the current instruction.
+    controls: Vec<(usize, i32)>,
+    labels: Labels,
+}
+
+impl Graph {
+    fn region(&mut self, label: String) -> usize {
+        self.regions.push(Region {
+            label,
+            roles: BTreeMap::new(),
+            edges: BTreeMap::new(),
+        });
+        self.regions.len() - 1
+    }
+
+    /// Walks `prog` from cell `ptr` of region `region`. `pos` is the source offset of the
+    /// innermost loop around it.
+    fn block(&mut self, prog: &[BaseInst], mut region: usize, mut ptr: i32, pos: Option<u32>) {
+        for inst in prog {
+            match inst {
+                BaseInst::Inc(_) => self.write(region, ptr),
+                BaseInst::Shift(delta) => ptr += delta,
+                BaseInst::Output => self.role(region, ptr, "out"),
+                BaseInst::ErrOutput => self.role(region, ptr, "err"),
+                BaseInst::Input => self.role(region, ptr, "in"),
+                BaseInst::Random => self.role(region, ptr, "rand"),
+                BaseInst::Reset | BaseInst::Fill(..) => {}
+                BaseInst::Mul(offset, weight) => {
+                    self.edge(region, ptr, ptr + offset, format!("*{}", *weight as i8));
+                    self.write(region, ptr + offset);
+                }
+                BaseInst::BlockMove(offset, len) => {
+                    for idx in 0..*len as i32 {
+                        self.edge(region, ptr + idx, ptr + offset + idx, String::from("move"));
+                        self.write(region, ptr + offset + idx);
+                    }
+                }
+                BaseInst::Seek(..) | BaseInst::BoundedSeek(..) | BaseInst::Skip(..) => {
+                    (region, ptr) = (self.region(format!("after a scan{}", self.at(pos))), 0);
+                }
+                BaseInst::Block(body, true, inner) => {
+                    self.controls.push((region, ptr));
+                    self.block(body, region, ptr, inner.or(pos));
+                    self.controls.pop();
+                }
+                BaseInst::Block(body, false, inner) => {
+                    let inner = inner.or(pos);
+                    let body_region = self.region(format!("inside the loop{}", self.at(inner)));
+                    self.block(body, body_region, 0, inner);
+                    (region, ptr) = (self.region(format!("after the loop{}", self.at(inner))), 0);
+                }
+            }
+        }
+    }
+
+    /// Where the loop at `pos` is, by name too if it has one.
+    fn at(&self, pos: Option<u32>) -> String {
+        match pos {
+            Some(pos) => match self.labels.loop_name(pos) {
+                Some(name) => format!(" {name} at byte {pos}"),
+                None => format!(" at byte {pos}"),
+            },
+            None => String::new(),
+        }
+    }
+
+    fn role(&mut self, region: usize, cell: i32, role: &'static str) {
+        self.regions[region].roles.entry(cell).or_default().insert(role);
+    }
+
+    fn edge(&mut self, region: usize, from: i32, to: i32, label: String) {
+        self.regions[region].edges.entry((from, to)).or_default().insert(label);
+    }
+
+    /// Records that `cell` changes, which depends on every enclosing loop in the same region.
+    fn write(&mut self, region: usize, cell: i32) {
+        for idx in 0..self.controls.len() {
+            let (control_region, control) = self.controls[idx];
+            if control_region == region && control != cell {
+                self.edge(region, control, cell, String::from("loop"));
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut dot = String::from("digraph cells {\n    node [shape=box];\n");
+        for (idx, region) in self.regions.iter().enumerate() {
+            let mut cells: BTreeSet<i32> = region.roles.keys().copied().collect();
+            cells.extend(region.edges.keys().flat_map(|&(from, to)| [from, to]));
+            if cells.is_empty() {
+                continue;
+            }
+            dot.push_str(&format!(
+                "    subgraph cluster_{idx} {{\n        label=\"{}\";\n",
+                region.label
+            ));
+            for cell in cells {
+                let mut label = format!("{cell}");
+                if idx == 0
+                    && let Some(name) = usize::try_from(cell).ok().and_then(|cell| self.labels.cell_name(cell))
+                {
+                    label.push(' ');
+                    label.push_str(name);
+                }
+                if let Some(roles) = region.roles.get(&cell) {
+                    label.push_str("\\n");
+                    label.push_str(&roles.iter().copied().collect::<Vec<_>>().join(","));
+                }
+                dot.push_str(&format!("        \"{idx}:{cell}\" [label=\"{label}\"];\n"));
+            }
+            for (&(from, to), labels) in &region.edges {
+                let label = labels.iter().map(String::as_str).collect::<Vec<_>>().join(", ");
+                let style = if label == "loop" { ", style=dashed" } else { "" };
+                dot.push_str(&format!(
+                    "        \"{idx}:{from}\" -> \"{idx}:{to}\" [label=\"{label}\"{style}];\n"
+                ));
+            }
+            dot.push_str("    }\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
@@ -0,0 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod bfasm;
+pub mod brainfuck;
+pub mod bytecode;
+#[cfg(feature = "corpus")]
+pub mod corpus;
+pub mod diff;
+pub mod engine;
+#[cfg(feature = "std")]
+pub mod exectrace;
+pub mod graph;
+pub mod obfuscate;
+pub mod report;
+pub mod rustgen;
+pub mod snapshot;
+pub mod textgen;
+
+pub use engine::{Bropt, Pause, Safety, TapeKind};
+pub use snapshot::Snapshot;
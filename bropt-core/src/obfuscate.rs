@@ -0,0 +1,140 @@
+//! Semantics-preserving obfuscation of brainfuck source.
+//!
+//! Every cell of the input program is moved to an even position so the odd cell to its right
+//! is a scratch cell that is zero whenever the program is between instructions. The scratch
+//! cell makes room for rewriting constants as multiplication loops and for inserting loops
+//! that never run. The obfuscated program therefore needs twice as many cells.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::brainfuck::{BaseInst, Rng, Seed, decompile, try_parse};
+
+/// Chance out of 256 of inserting noise before each instruction.
+const NOISE_RATE: u64 = 64;
+
+/// Smallest increment magnitude rewritten as a multiplication loop.
+const MIN_LOOP_CONST: i32 = 6;
+
+/// Rewrites `code` into an equivalent but harder to read program, chosen by `seed`.
+///
+/// Runs of increments and shifts are reordered, constants become multiplication loops on the
+/// scratch cell, and canceling pairs such as `+-` and loops over the zero scratch cell are
+/// sprinkled in. Output for any input is unchanged; only the tape layout differs.
+pub fn obfuscate(code: &str, seed: Seed) -> Result<String, String> {
+    let prog = try_parse(code)?;
+    let mut rng = seed.rng();
+    decompile(&rewrite(prog, &mut rng))
+}
+
+fn rewrite(prog: Vec<BaseInst>, rng: &mut Rng) -> Vec<BaseInst> {
+    let mut out = Vec::new();
+    // Increments by offset from the start of the current straight-line run, and the shift at its end.
+    let mut run: BTreeMap<i32, i32> = BTreeMap::new();
+    let mut ptr = 0;
+    for inst in prog {
+        match inst {
+            BaseInst::Inc(inc) => *run.entry(ptr).or_insert(0) += inc as i8 as i32,
+            BaseInst::Shift(delta) => ptr += delta,
+            other => {
+                flush_run(&mut out, &mut run, &mut ptr, rng);
+                push_noise(&mut out, rng);
+                match other {
+                    BaseInst::Block(body, flag, pos) => out.push(BaseInst::Block(rewrite(body, rng), flag, pos)),
+                    other => out.push(other),
+                }
+            }
+        }
+    }
+    flush_run(&mut out, &mut run, &mut ptr, rng);
+    out
+}
+
+/// Emits the pending run with its increments in a random order, then moves to where it ended.
+fn flush_run(out: &mut Vec<BaseInst>, run: &mut BTreeMap<i32, i32>, ptr: &mut i32, rng: &mut Rng) {
+    let mut incs: Vec<(i32, i32)> = core::mem::take(run).into_iter().collect();
+    for idx in (1..incs.len()).rev() {
+        incs.swap(idx, rng.below(idx as u64 + 1) as usize);
+    }
+    let mut pos = 0;
+    for (offset, value) in incs {
+        push_noise(out, rng);
+        push_shift(out, offset - pos);
+        pos = offset;
+        push_const(out, value, rng);
+    }
+    push_noise(out, rng);
+    push_shift(out, *ptr - pos);
+    *ptr = 0;
+}
+
+/// Adds `value` to the current cell, as a loop `>a[<b>-]<` plus a remainder when it is large.
+///
+/// Increments are sign-extended bytes, so the loop counts and the remainder are kept within
+/// `i8` to stay exact on every cell width.
+fn push_const(out: &mut Vec<BaseInst>, value: i32, rng: &mut Rng) {
+    let mut rest = value;
+    let magnitude = value.abs();
+    let (low, high) = ((magnitude + 126) / 127, (magnitude / 2).min(127));
+    if magnitude >= MIN_LOOP_CONST && low.max(2) <= high && rng.below(2) == 0 {
+        let outer = low.max(2) + rng.below((high - low.max(2) + 1) as u64) as i32;
+        let inner = value / outer;
+        rest = value - outer * inner;
+        let body = vec![
+            BaseInst::Shift(-1),
+            BaseInst::Inc(inner as i8 as u8),
+            BaseInst::Shift(1),
+            BaseInst::Inc(u8::MAX),
+        ];
+        out.push(BaseInst::Shift(1));
+        out.push(BaseInst::Inc(outer as u8));
+        out.push(BaseInst::Block(body, true, None));
+        out.push(BaseInst::Shift(-1));
+    }
+    while rest != 0 {
+        let step = rest.clamp(i8::MIN as i32, i8::MAX as i32);
+        out.push(BaseInst::Inc(step as i8 as u8));
+        rest -= step;
+    }
+}
+
+/// Sometimes inserts an operation pair that cancels out or a loop over the zero scratch cell.
+fn push_noise(out: &mut Vec<BaseInst>, rng: &mut Rng) {
+    if rng.below(256) >= NOISE_RATE {
+        return;
+    }
+    match rng.below(3) {
+        0 => {
+            let inc = 1 + rng.below(3) as u8;
+            out.push(BaseInst::Inc(inc));
+            out.push(BaseInst::Inc(inc.wrapping_neg()));
+        }
+        1 => {
+            let delta = 1 + rng.below(2) as i32;
+            out.push(BaseInst::Shift(2 * delta));
+            out.push(BaseInst::Shift(-2 * delta));
+        }
+        _ => {
+            let mut body = Vec::new();
+            for _ in 0..1 + rng.below(4) {
+                body.push(match rng.below(3) {
+                    0 => BaseInst::Inc((rng.below(16) as u8).wrapping_sub(8) | 1),
+                    1 => BaseInst::Shift(rng.below(5) as i32 - 2),
+                    _ => BaseInst::Output,
+                });
+            }
+            out.push(BaseInst::Shift(1));
+            out.push(BaseInst::Block(body, false, None));
+            out.push(BaseInst::Shift(-1));
+        }
+    }
+}
+
+/// Shifts between original cells, which sit two apart after interleaving the scratch cells.
+fn push_shift(out: &mut Vec<BaseInst>, delta: i32) {
+    if delta != 0 {
+        out.push(BaseInst::Shift(2 * delta));
+    }
+}
@@ -0,0 +1,188 @@
+//! Reports showing what each loop of a program compiles to, and where a run spent its time.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::brainfuck::{BaseInst, CellWidth, Inst, InstType, Labels, SourceMap, optimize, parse, try_parse};
+
+const STYLE: &str = "body{background:#1e1e1e;color:#d4d4d4}\
+pre{font:14px/1.4 monospace;white-space:pre-wrap;word-break:break-all}\
+.inc{color:#9cdcfe}.shift{color:#c586c0}.io{color:#ce9178;font-weight:bold}.comment{color:#6a9955}\
+.loop{border-radius:3px}.loop:hover{outline:1px solid #d4d4d4}\
+.set{background:#264f78}.mul{background:#3a5a2a}.seek{background:#5a4a1a}.skip{background:#5a2a4a}\
+.interpreted{background:#3a3a3a}\
+.legend span{padding:0 6px;margin-right:6px}";
+
+/// Renders `code` as a standalone HTML page where every loop is colored by what it compiles
+/// to at `level` and hovering it shows the instructions, after the loop's name if the source
+/// gives it one.
+///
+/// Each loop is optimized on its own, so folds that depend on the surrounding code, such as
+/// removing a loop whose cell is known to be zero, are not reflected.
+pub fn html_report(code: &str, level: u8, cell: CellWidth) -> Result<String, String> {
+    let labels = Labels::scan(code);
+    let mut opens = Vec::new();
+    for (open, close, _) in loops(code)? {
+        let folded = optimize(parse(&code[open..=close]), level, cell);
+        let mut title = String::new();
+        if let Some(name) = name_at(&labels, open) {
+            title.push_str(&format!("{name}: "));
+        }
+        push_escaped(&mut title, &format!("{folded:?}"));
+        opens.push((open, classify(&folded), title));
+    }
+
+    let mut html = String::with_capacity(code.len() * 2);
+    html.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>bropt report</title><style>");
+    html.push_str(STYLE);
+    html.push_str("</style></head><body>\n<p class=\"legend\">");
+    for kind in ["set", "mul", "seek", "skip", "interpreted"] {
+        html.push_str(&format!("<span class=\"{kind}\">{kind}</span>"));
+    }
+    html.push_str("</p>\n<pre>");
+    let mut opens = opens.into_iter().peekable();
+    let mut class = "";
+    for (idx, ch) in code.char_indices() {
+        let next = match ch {
+            '+' | '-' => "inc",
+            '<' | '>' => "shift",
+            '.' | ',' => "io",
+            '[' | ']' => "",
+            _ => "comment",
+        };
+        if next != class || next.is_empty() {
+            if !class.is_empty() {
+                html.push_str("</span>");
+            }
+            if let Some((_, kind, title)) = opens.next_if(|&(open, ..)| open == idx) {
+                html.push_str(&format!("<span class=\"loop {kind}\" title=\"{title}\">"));
+            }
+            if !next.is_empty() {
+                html.push_str(&format!("<span class=\"{next}\">"));
+            }
+            class = next;
+        }
+        push_escaped(&mut html, ch.encode_utf8(&mut [0; 4]));
+        if ch == ']' {
+            html.push_str("</span>");
+        }
+    }
+    if !class.is_empty() {
+        html.push_str("</span>");
+    }
+    html.push_str("</pre>\n</body></html>\n");
+    Ok(html)
+}
+
+/// Renders the loop structure of `code` as an indented tree, one line per loop with its byte
+/// range, nesting depth, size and what it compiles to at `level`, and its name if the source
+/// gives it one.
+///
+/// Loops are classified the same way as in [`html_report`].
+pub fn outline(code: &str, level: u8, cell: CellWidth) -> Result<String, String> {
+    let labels = Labels::scan(code);
+    let mut text = String::new();
+    for (open, close, depth) in loops(code)? {
+        let folded = optimize(parse(&code[open..=close]), level, cell);
+        let name = name_at(&labels, open).map_or(String::new(), |name| format!(" {name}"));
+        text.push_str(&format!(
+            "{:indent$}[{open}..={close}]{name} depth {depth}, {} bytes: {}\n",
+            "",
+            close - open + 1,
+            classify(&folded),
+            indent = depth * 2,
+        ));
+    }
+    Ok(text)
+}
+
+/// Renders `samples`, the number of times each instruction of `prog` was seen running, as the
+/// folded stacks inferno and flamegraph.pl draw: one line per loop nesting path with its count,
+/// under a root frame `root`. A loop's frame is its name and the byte of its `[`, `loop` standing
+/// in for the name when the source gives none, or `loop#N` for instruction N when its position
+/// is unknown.
+pub fn folded_stacks(prog: &[Inst], map: &SourceMap, samples: &[u64], root: &str) -> String {
+    let mut stacks = BTreeMap::new();
+    let mut path = Vec::new();
+    for (ip, inst) in prog.iter().enumerate() {
+        if inst.cmd == InstType::Open {
+            path.push(match map.loop_at(ip) {
+                Some(pos) => format!("{}@{pos}", map.labels().loop_name(pos).unwrap_or("loop")),
+                None => format!("loop#{ip}"),
+            });
+        }
+        if let Some(&count) = samples.get(ip).filter(|&&count| count > 0) {
+            let mut stack = String::from(root);
+            for frame in &path {
+                stack.push(';');
+                stack.push_str(frame);
+            }
+            *stacks.entry(stack).or_insert(0) += count;
+        }
+        if inst.cmd == InstType::Close {
+            path.pop();
+        }
+    }
+    let mut text = String::new();
+    for (stack, count) in stacks {
+        text.push_str(&format!("{stack} {count}\n"));
+    }
+    text
+}
+
+/// Byte ranges of every loop in `code` as `(open, close, depth)`, ordered by their `[`.
+fn loops(code: &str) -> Result<Vec<(usize, usize, usize)>, String> {
+    try_parse(code)?;
+    let mut loops = Vec::new();
+    let mut stack = Vec::new();
+    for (idx, &byte) in code.as_bytes().iter().enumerate() {
+        match byte {
+            b'[' => {
+                stack.push(loops.len());
+                loops.push((idx, idx, stack.len() - 1));
+            }
+            b']' => {
+                let loop_idx = stack.pop().unwrap();
+                loops[loop_idx].1 = idx;
+            }
+            _ => {}
+        }
+    }
+    Ok(loops)
+}
+
+fn name_at(labels: &Labels, open: usize) -> Option<&str> {
+    labels.loop_name(u32::try_from(open).ok()?)
+}
+
+/// Names what a loop folded on its own became, from the most to the least general instruction.
+fn classify(folded: &[BaseInst]) -> &'static str {
+    if folded.iter().any(|inst| matches!(inst, BaseInst::Block(..))) {
+        "interpreted"
+    } else if folded.iter().any(|inst| matches!(inst, BaseInst::Skip(..))) {
+        "skip"
+    } else if folded
+        .iter()
+        .any(|inst| matches!(inst, BaseInst::Seek(..) | BaseInst::BoundedSeek(..)))
+    {
+        "seek"
+    } else if folded.iter().any(|inst| matches!(inst, BaseInst::Mul(..))) {
+        "mul"
+    } else {
+        "set"
+    }
+}
+
+fn push_escaped(html: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            _ => html.push(ch),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Translates the [`BaseInst`] tree into a standalone Rust program, for people who want to keep
+//! working on a brainfuck program as Rust.
+//!
+//! Every instruction becomes a statement on a `Vec` of cells indexed by a `usize` pointer, and
+//! every loop a `while`, so the result reads like the optimized program and runs without bropt.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::brainfuck::{BaseInst, CellWidth, Eof, walk};
+
+/// Returns the source of a `main.rs` that runs `prog` on a tape of `tape_len` cells of width
+/// `cell`, storing what `eof` says at end of input. The program reads stdin and writes stdout,
+/// and panics if the pointer leaves the tape.
+pub fn gen_rust(prog: &[BaseInst], cell: CellWidth, eof: Eof, tape_len: usize) -> String {
+    let (mut io, mut moves) = (Io::default(), false);
+    walk(prog, &mut |inst, _| match inst {
+        BaseInst::Output => io.write = true,
+        BaseInst::ErrOutput => io.write_err = true,
+        BaseInst::Input => io.read = true,
+        BaseInst::Random => io.random = true,
+        BaseInst::Shift(_) | BaseInst::Seek(_) | BaseInst::BoundedSeek(..) | BaseInst::Skip(..) => moves = true,
+        _ => {}
+    });
+    let ty = match cell {
+        CellWidth::U8 => "u8",
+        CellWidth::U16 => "u16",
+        CellWidth::U32 => "u32",
+    };
+    let mut out = Gen {
+        code: io.header(),
+        mask: cell.mask(),
+        eof,
+    };
+    out.code
+        .push_str(&format!("type Cell = {ty};\n\nconst TAPE_LEN: usize = {tape_len};\n\n"));
+    out.code.push_str(&io.render(eof));
+    out.code.push_str("fn main() {\n");
+    if io.used() {
+        out.line(1, "let mut io = Io::new();");
+    }
+    out.line(1, "let mut cells: Vec<Cell> = vec![0; TAPE_LEN];");
+    out.line(
+        1,
+        if moves {
+            "let mut p: usize = 0;"
+        } else {
+            "let p: usize = 0;"
+        },
+    );
+    // Moves after the last instruction that uses the tape would only draw unused-assignment
+    // warnings.
+    let end = prog
+        .iter()
+        .rposition(|inst| !matches!(inst, BaseInst::Shift(_)))
+        .map_or(0, |idx| idx + 1);
+    out.block(&prog[..end], 1);
+    if io.write || io.write_err {
+        out.line(1, "io.output.flush().unwrap();");
+    }
+    out.code.push_str("}\n");
+    out.code
+}
+
+/// Which kinds of I/O the program does, so the generated `Io` only has what it uses.
+#[derive(Default)]
+struct Io {
+    read: bool,
+    write: bool,
+    write_err: bool,
+    random: bool,
+}
+
+impl Io {
+    fn used(&self) -> bool {
+        self.read || self.write || self.write_err || self.random
+    }
+
+    fn header(&self) -> String {
+        let mut imports = Vec::new();
+        if self.write || self.write_err {
+            imports.extend(["BufWriter", "StdoutLock"]);
+        }
+        if self.read {
+            imports.extend(["Read", "StdinLock"]);
+        }
+        if self.write || self.write_err || self.read {
+            imports.push("Write");
+        }
+        imports.sort_unstable();
+        match imports.len() {
+            0 => String::new(),
+            _ => format!("use std::io::{{{}}};\n\n", imports.join(", ")),
+        }
+    }
+
+    /// The `Io` struct with a method for each kind of I/O the program does.
+    fn render(&self, eof: Eof) -> String {
+        if !self.used() {
+            return String::new();
+        }
+        let (mut fields, mut init, mut methods) = (String::new(), String::new(), String::new());
+        if self.read {
+            fields.push_str("    input: StdinLock<'static>,\n");
+            init.push_str("            input: std::io::stdin().lock(),\n");
+            let (cell, at_eof) = match eof {
+                Eof::Zero => ("", "0"),
+                Eof::MinusOne => ("", "Cell::MAX"),
+                Eof::Unchanged => (", cell: Cell", "cell"),
+            };
+            methods.push_str(&format!(
+                "\n    /// Reads a byte, or gives what the program expects at end of input.\n    \
+                 fn read(&mut self{cell}) -> Cell {{\n{}        let mut byte = [0];\n        \
+                 match self.input.read(&mut byte) {{\n            Ok(1) => byte[0] as Cell,\n            \
+                 _ => {at_eof},\n        }}\n    }}\n",
+                if self.write || self.write_err {
+                    "        self.output.flush().unwrap();\n"
+                } else {
+                    ""
+                },
+            ));
+        }
+        if self.write || self.write_err {
+            fields.push_str("    output: BufWriter<StdoutLock<'static>>,\n");
+            init.push_str("            output: BufWriter::new(std::io::stdout().lock()),\n");
+        }
+        if self.write {
+            methods.push_str(
+                "\n    fn write(&mut self, cell: Cell) {\n        self.output.write_all(&[cell as u8]).unwrap();\n    }\n",
+            );
+        }
+        if self.write_err {
+            methods.push_str(
+                "\n    /// Writes to stderr, flushing stdout first so the two streams stay in order.\n    \
+                 fn write_err(&mut self, cell: Cell) {\n        self.output.flush().unwrap();\n        \
+                 std::io::stderr().write_all(&[cell as u8]).unwrap();\n    }\n",
+            );
+        }
+        if self.random {
+            fields.push_str("    state: u64,\n");
+            init.push_str(
+                "            state: std::time::SystemTime::now()\n                \
+                 .duration_since(std::time::UNIX_EPOCH)\n                \
+                 .map_or(1, |time| time.as_nanos() as u64 | 1),\n",
+            );
+            methods.push_str(
+                "\n    /// A random byte from xorshift64*.\n    fn random(&mut self) -> Cell {\n        \
+                 self.state ^= self.state >> 12;\n        self.state ^= self.state << 25;\n        \
+                 self.state ^= self.state >> 27;\n        \
+                 (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as Cell\n    }\n",
+            );
+        }
+        format!(
+            "struct Io {{\n{fields}}}\n\nimpl Io {{\n    fn new() -> Io {{\n        Io {{\n{init}        }}\n    }}\n{methods}}}\n\n"
+        )
+    }
+}
+
+struct Gen {
+    code: String,
+    mask: u32,
+    eof: Eof,
+}
+
+impl Gen {
+    fn line(&mut self, depth: usize, line: &str) {
+        for _ in 0..depth {
+            self.code.push_str("    ");
+        }
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+
+    fn block(&mut self, prog: &[BaseInst], depth: usize) {
+        let mut idx = 0;
+        while idx < prog.len() {
+            let inst = &prog[idx];
+            idx += 1;
+            match inst {
+                BaseInst::Inc(inc) => self.line(depth, &format!("cells[p] = cells[p]{};", add(*inc))),
+                BaseInst::Shift(delta) => self.shift(depth, *delta),
+                BaseInst::Output => self.line(depth, "io.write(cells[p]);"),
+                BaseInst::ErrOutput => self.line(depth, "io.write_err(cells[p]);"),
+                BaseInst::Input if self.eof == Eof::Unchanged => self.line(depth, "cells[p] = io.read(cells[p]);"),
+                BaseInst::Input => self.line(depth, "cells[p] = io.read();"),
+                BaseInst::Random => self.line(depth, "cells[p] = io.random();"),
+                BaseInst::Reset => self.line(depth, "cells[p] = 0;"),
+                BaseInst::Mul(..) => {
+                    // The loop a run of multiplications came from only touched their targets
+                    // while the current cell was nonzero, and the targets may be off the tape
+                    // otherwise.
+                    let run = prog[idx - 1..]
+                        .iter()
+                        .take_while(|inst| matches!(inst, BaseInst::Mul(..)))
+                        .count();
+                    self.line(depth, "if cells[p] != 0 {");
+                    for inst in &prog[idx - 1..idx - 1 + run] {
+                        if let BaseInst::Mul(offset, weight) = inst {
+                            self.mul(depth + 1, *offset, *weight);
+                        }
+                    }
+                    self.line(depth, "}");
+                    idx += run - 1;
+                }
+                BaseInst::Seek(stride) | BaseInst::BoundedSeek(stride, _) => {
+                    self.line(depth, "while cells[p] != 0 {");
+                    self.shift(depth + 1, *stride);
+                    self.line(depth, "}");
+                }
+                BaseInst::Skip(stride, incs) => {
+                    self.line(depth, "while cells[p] != 0 {");
+                    for &(inc, offset) in incs {
+                        let dst = at(offset as i32);
+                        self.line(depth + 1, &format!("cells[{dst}] = cells[{dst}]{};", add(inc)));
+                    }
+                    self.shift(depth + 1, *stride);
+                    self.line(depth, "}");
+                }
+                BaseInst::Fill(_, 0, _) | BaseInst::BlockMove(_, 0) => {}
+                BaseInst::Fill(offset, len, value) => {
+                    let value = (*value as i8 as u32) & self.mask;
+                    let start = at(*offset);
+                    self.line(depth, &format!("cells[{start}..{start} + {len}].fill({value});"));
+                }
+                BaseInst::BlockMove(offset, len) => {
+                    self.line(depth, &format!("cells.copy_within(p..p + {len}, {});", at(*offset)));
+                    self.line(depth, &format!("cells[p..p + {len}].fill(0);"));
+                }
+                BaseInst::Block(body, ..) => {
+                    self.line(depth, "while cells[p] != 0 {");
+                    self.block(body, depth + 1);
+                    self.line(depth, "}");
+                }
+            }
+        }
+    }
+
+    fn mul(&mut self, depth: usize, offset: i32, weight: u8) {
+        let dst = at(offset);
+        let weight = weight as i8;
+        let product = match weight.unsigned_abs() {
+            1 => String::from("cells[p]"),
+            factor => format!("cells[p].wrapping_mul({factor})"),
+        };
+        let op = if weight < 0 { "sub" } else { "add" };
+        self.line(depth, &format!("cells[{dst}] = cells[{dst}].wrapping_{op}({product});"));
+    }
+
+    fn shift(&mut self, depth: usize, delta: i32) {
+        if delta < 0 {
+            self.line(depth, &format!("p -= {};", delta.unsigned_abs()));
+        } else {
+            self.line(depth, &format!("p += {delta};"));
+        }
+    }
+}
+
+/// The cell `offset` away from the pointer.
+fn at(offset: i32) -> String {
+    match offset {
+        0 => String::from("p"),
+        offset if offset < 0 => format!("p - {}", offset.unsigned_abs()),
+        offset => format!("p + {offset}"),
+    }
+}
+
+/// A wrapping add of the sign-extended `inc`, written as a subtraction when it is negative.
+fn add(inc: u8) -> String {
+    match inc as i8 {
+        inc if inc < 0 => format!(".wrapping_sub({})", inc.unsigned_abs()),
+        inc => format!(".wrapping_add({inc})"),
+    }
+}
@@ -0,0 +1,315 @@
+//! Serialized execution state, so a stopped run can be resumed by a later process.
+//!
+//! The format is little-endian: the magic `BROPTSNP`, a version byte, the cell width in bytes,
+//! a fingerprint of the program, the instruction, pointer, tape length and state of the `?`
+//! generator, then the runs of nonzero cells as a count followed by each run's start, length
+//! and cells. [`diff`] compares two snapshots from those runs alone.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(all(unix, feature = "mmap"))]
+use crate::brainfuck::MappedTape;
+use crate::brainfuck::{Cell, Rng, SparseTape};
+use crate::engine::{Program, Tape};
+
+const MAGIC: &[u8; 8] = b"BROPTSNP";
+const VERSION: u8 = 2;
+
+/// Where a run stopped, the tape it left and the generator `?` draws from, which
+/// [`Bropt::resume`](crate::Bropt::resume) continues from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub ip: usize,
+    pub dp: usize,
+    pub tape: Tape,
+    pub rng: Rng,
+}
+
+impl Snapshot {
+    /// Whether the run reached the end of `prog` rather than being stopped early.
+    pub fn finished(&self, prog: &Program) -> bool {
+        self.ip >= prog.insts().len()
+    }
+
+    /// Serializes the snapshot of a run of `prog`, storing only the nonzero cells.
+    pub fn to_bytes(&self, prog: &Program) -> Vec<u8> {
+        let width = self.tape.cell().bytes();
+        let runs = nonzero_runs(&self.tape);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(width as u8);
+        for value in [
+            fingerprint(prog),
+            self.ip as u64,
+            self.dp as u64,
+            self.tape.len() as u64,
+            self.rng.state(),
+            runs.len() as u64,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for (start, cells) in runs {
+            bytes.extend_from_slice(&(start as u64).to_le_bytes());
+            bytes.extend_from_slice(&(cells.len() as u64).to_le_bytes());
+            for cell in cells {
+                bytes.extend_from_slice(&cell.to_le_bytes()[..width]);
+            }
+        }
+        bytes
+    }
+}
+
+/// Loads the cells of a serialized snapshot of `prog` into the zeroed `tape` and returns its
+/// instruction, pointer and generator.
+pub(crate) fn read(bytes: &[u8], prog: &Program, tape: &mut Tape) -> Result<(usize, usize, Rng), String> {
+    let (header, mut reader) = read_header(bytes)?;
+    if header.width != tape.cell().bytes() {
+        return Err(format!(
+            "snapshot of {}-byte cells cannot load onto {:?} cells",
+            header.width,
+            tape.cell()
+        ));
+    }
+    if header.fingerprint != fingerprint(prog) {
+        return Err("snapshot was taken from a different program or configuration".into());
+    }
+    if header.ip > prog.insts().len() || header.dp >= tape.len() || header.len > tape.len() {
+        return Err(format!(
+            "snapshot of a {}-cell tape does not fit a {}-cell tape",
+            header.len,
+            tape.len()
+        ));
+    }
+    read_cells(&mut reader, &header, |pos, cell| tape.set(pos, cell))?;
+    Ok((header.ip, header.dp, Rng::new(header.rng)))
+}
+
+/// A cell holding different values in two snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellChange {
+    pub pos: usize,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// How two snapshots of the same program differ, from [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    /// Bytes in a cell of both tapes.
+    pub width: usize,
+    /// Instruction and pointer of the first snapshot.
+    pub old: (usize, usize),
+    /// Instruction and pointer of the second snapshot.
+    pub new: (usize, usize),
+    /// The cells that differ, in order of position.
+    pub cells: Vec<CellChange>,
+}
+
+/// Compares two serialized snapshots of the same program. Only the nonzero cells they store
+/// are read, so the tapes are never built and may be of any length.
+pub fn diff(old: &[u8], new: &[u8]) -> Result<SnapshotDiff, String> {
+    let stored = |bytes| -> Result<_, String> {
+        let (header, mut reader) = read_header(bytes)?;
+        let mut cells = BTreeMap::new();
+        read_cells(&mut reader, &header, |pos, cell| {
+            cells.insert(pos, cell);
+        })?;
+        Ok((header, cells))
+    };
+    let (old_header, mut old_cells) = stored(old)?;
+    let (new_header, new_cells) = stored(new)?;
+    if old_header.width != new_header.width || old_header.fingerprint != new_header.fingerprint {
+        return Err("snapshots were taken from different programs or configurations".into());
+    }
+    let mut cells = Vec::new();
+    for (pos, new) in new_cells {
+        let old = old_cells.remove(&pos).unwrap_or(0);
+        if old != new {
+            cells.push(CellChange { pos, old, new });
+        }
+    }
+    cells.extend(old_cells.into_iter().map(|(pos, old)| CellChange { pos, old, new: 0 }));
+    cells.sort_unstable_by_key(|change| change.pos);
+    Ok(SnapshotDiff {
+        width: old_header.width,
+        old: (old_header.ip, old_header.dp),
+        new: (new_header.ip, new_header.dp),
+        cells,
+    })
+}
+
+/// The fixed fields at the start of a snapshot.
+struct Header {
+    width: usize,
+    fingerprint: u64,
+    ip: usize,
+    dp: usize,
+    len: usize,
+    rng: u64,
+}
+
+/// Checks the magic and version and reads the fixed fields, leaving the reader at the runs.
+fn read_header(bytes: &[u8]) -> Result<(Header, Reader<'_>), String> {
+    if !bytes.starts_with(MAGIC) {
+        return Err("not a bropt snapshot".into());
+    }
+    let mut reader = Reader {
+        bytes,
+        pos: MAGIC.len(),
+        what: "snapshot",
+    };
+    let version = reader.take(1)?[0];
+    if version != VERSION {
+        return Err(format!("unsupported snapshot version {version}"));
+    }
+    let width = reader.take(1)?[0] as usize;
+    if ![1, 2, 4].contains(&width) {
+        return Err(format!("snapshot of unsupported {width}-byte cells"));
+    }
+    let header = Header {
+        width,
+        fingerprint: reader.u64()?,
+        ip: reader.u64()? as usize,
+        dp: reader.u64()? as usize,
+        len: reader.u64()? as usize,
+        rng: reader.u64()?,
+    };
+    Ok((header, reader))
+}
+
+/// Passes each stored cell to `set` with its position.
+fn read_cells(reader: &mut Reader, header: &Header, mut set: impl FnMut(usize, u32)) -> Result<(), String> {
+    let width = header.width;
+    for _ in 0..reader.u64()? {
+        let start = reader.u64()? as usize;
+        let count = reader.u64()? as usize;
+        if start.checked_add(count).is_none_or(|end| end > header.len) {
+            return Err("snapshot cells lie outside its tape".into());
+        }
+        for pos in start..start + count {
+            let mut cell = [0; 4];
+            cell[..width].copy_from_slice(reader.take(width)?);
+            set(pos, u32::from_le_bytes(cell));
+        }
+    }
+    Ok(())
+}
+
+/// Reads little-endian fields, naming `what` it reads when the bytes run out.
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) pos: usize,
+    pub(crate) what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        let taken = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| format!("{} is truncated", self.what))?;
+        self.pos = end;
+        Ok(taken)
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Folds `bytes` into an FNV-1a hash.
+fn mix(hash: &mut u64, bytes: &[u8]) {
+    for &byte in bytes {
+        *hash = (*hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+}
+
+/// FNV-1a over the instructions and cell width, so a snapshot is only resumed by the program
+/// it was taken from.
+fn fingerprint(prog: &Program) -> u64 {
+    let mut hash = FNV_OFFSET;
+    mix(&mut hash, &[prog.cell().bytes() as u8]);
+    for inst in prog.insts() {
+        mix(&mut hash, &[inst.cmd as u8, inst.inc]);
+        mix(&mut hash, &inst.delta.to_le_bytes());
+        mix(&mut hash, &inst.arg.to_le_bytes());
+    }
+    hash
+}
+
+/// FNV-1a over the cell width and the runs of nonzero cells, encoded as [`Snapshot::to_bytes`]
+/// stores them, for [`Tape::digest`].
+pub(crate) fn digest(tape: &Tape) -> u64 {
+    let width = tape.cell().bytes();
+    let mut hash = FNV_OFFSET;
+    mix(&mut hash, &[width as u8]);
+    for (start, cells) in nonzero_runs(tape) {
+        mix(&mut hash, &(start as u64).to_le_bytes());
+        mix(&mut hash, &(cells.len() as u64).to_le_bytes());
+        for cell in cells {
+            mix(&mut hash, &cell.to_le_bytes()[..width]);
+        }
+    }
+    hash
+}
+
+fn nonzero_runs(tape: &Tape) -> Vec<(usize, Vec<u32>)> {
+    let mut runs = Vec::new();
+    match tape {
+        Tape::U8(cells) => slice_runs(cells, 0, &mut runs),
+        Tape::U16(cells) => slice_runs(cells, 0, &mut runs),
+        Tape::U32(cells) => slice_runs(cells, 0, &mut runs),
+        Tape::SparseU8(cells) => sparse_runs(cells, &mut runs),
+        Tape::SparseU16(cells) => sparse_runs(cells, &mut runs),
+        Tape::SparseU32(cells) => sparse_runs(cells, &mut runs),
+        #[cfg(all(unix, feature = "mmap"))]
+        Tape::MappedU8(cells) => mapped_runs(cells, &mut runs),
+        #[cfg(all(unix, feature = "mmap"))]
+        Tape::MappedU16(cells) => mapped_runs(cells, &mut runs),
+        #[cfg(all(unix, feature = "mmap"))]
+        Tape::MappedU32(cells) => mapped_runs(cells, &mut runs),
+    }
+    runs
+}
+
+fn sparse_runs<C: Cell + Into<u32>>(tape: &SparseTape<C>, runs: &mut Vec<(usize, Vec<u32>)>) {
+    for (start, cells) in tape.iter_pages() {
+        slice_runs(&cells[..cells.len().min(tape.len() - start)], start, runs);
+    }
+}
+
+#[cfg(all(unix, feature = "mmap"))]
+fn mapped_runs<C: Cell + Into<u32>>(tape: &MappedTape<C>, runs: &mut Vec<(usize, Vec<u32>)>) {
+    for (start, cells) in tape.iter_pages() {
+        slice_runs(cells, start, runs);
+    }
+}
+
+/// Appends the runs of nonzero cells in `cells`, which starts at tape position `base`, merging
+/// with the previous run when it ends right where `cells` starts.
+fn slice_runs<C: Cell + Into<u32>>(cells: &[C], base: usize, runs: &mut Vec<(usize, Vec<u32>)>) {
+    let mut idx = 0;
+    while idx < cells.len() {
+        if cells[idx] == C::ZERO {
+            idx += 1;
+            continue;
+        }
+        let start = base + idx;
+        let mut run = Vec::new();
+        while idx < cells.len() && cells[idx] != C::ZERO {
+            run.push(cells[idx].into());
+            idx += 1;
+        }
+        match runs.last_mut() {
+            Some((prev, prev_cells)) if *prev + prev_cells.len() == start => prev_cells.extend(run),
+            _ => runs.push((start, run)),
+        }
+    }
+}
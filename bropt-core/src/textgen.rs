@@ -0,0 +1,63 @@
+//! Generates brainfuck that prints given bytes.
+
+use alloc::string::String;
+
+/// Loop counters tried when building a step as a multiplication loop.
+const MAX_FACTOR: usize = 16;
+
+/// Returns a program that prints `bytes` using only the cell under the pointer and the one to
+/// its right, both of which must start at zero.
+///
+/// Each step from the previous byte to the next is written either as plain `+`/`-` or as a
+/// loop `>a[<b>-]<` adding `a * b` followed by a plain remainder, whichever is shorter.
+pub fn gen_text(bytes: &[u8]) -> String {
+    let mut code = String::new();
+    let mut cur = 0u8;
+    for &byte in bytes {
+        push_step(&mut code, byte.wrapping_sub(cur));
+        code.push('.');
+        cur = byte;
+    }
+    code
+}
+
+fn push_step(code: &mut String, delta: u8) {
+    // (loop counter, per-iteration amount, whether the loop decrements, remainder)
+    let mut best = (0, 0, false, delta);
+    let mut best_cost = plain_cost(delta);
+    for outer in 2..=MAX_FACTOR {
+        for inner in 1..=MAX_FACTOR {
+            for down in [false, true] {
+                let product = (outer * inner) as u8;
+                let product = if down { product.wrapping_neg() } else { product };
+                let rest = delta.wrapping_sub(product);
+                let cost = outer + inner + 7 + plain_cost(rest);
+                if cost < best_cost {
+                    best = (outer, inner, down, rest);
+                    best_cost = cost;
+                }
+            }
+        }
+    }
+    let (outer, inner, down, rest) = best;
+    if outer > 0 {
+        code.push('>');
+        push_repeated(code, '+', outer);
+        code.push_str("[<");
+        push_repeated(code, if down { '-' } else { '+' }, inner);
+        code.push_str(">-]<");
+    }
+    if rest < 128 {
+        push_repeated(code, '+', rest as usize);
+    } else {
+        push_repeated(code, '-', rest.wrapping_neg() as usize);
+    }
+}
+
+fn plain_cost(delta: u8) -> usize {
+    delta.min(delta.wrapping_neg()) as usize
+}
+
+fn push_repeated(code: &mut String, ch: char, count: usize) {
+    code.extend(core::iter::repeat_n(ch, count));
+}
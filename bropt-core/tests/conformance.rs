@@ -0,0 +1,125 @@
+//! dbfi, Daniel B Cristofani's brainfuck interpreter written in brainfuck, running a copy of itself
+//! pins down the semantics of `BroptBuilder::conformance`: an interpreter nested two deep only
+//! prints the right thing if cells wrap at 8 bits, end of input leaves the cell alone and the tape
+//! reaches as far right as it is used.
+
+use bropt_core::Bropt;
+use bropt_core::brainfuck::{CellWidth, Eof, ExecHooks, Inst, PASSES, SliceReader, parse, run_pass};
+use bropt_core::engine::{Program, TapeKind};
+
+const DBFI: &str = include_str!("../benches/SelfInt.b");
+
+/// What dbfi reads: a program and its input separated by `!`. The program is dbfi again, without
+/// its closing comment, given `,+.,.` to run on `A`, which prints `BB` only if the second `,`
+/// leaves the cell as it was.
+fn nested_input() -> Vec<u8> {
+    let inner: String = DBFI
+        .split("\n[input")
+        .next()
+        .unwrap()
+        .chars()
+        .filter(|ch| "+-<>[].,".contains(*ch))
+        .collect();
+    format!("{inner}!,+.,.!A").into_bytes()
+}
+
+fn run_nested(engine: &Bropt, prog: &Program) -> Result<Vec<u8>, String> {
+    let input = nested_input();
+    let mut output = Vec::new();
+    engine.run(prog, &mut SliceReader::new(&input), &mut output)?;
+    Ok(output)
+}
+
+#[test]
+fn dbfi_runs_itself_under_the_conformance_profile() {
+    let engine = Bropt::builder().conformance().build();
+    let prog = engine.compile(DBFI).unwrap();
+    assert_eq!(run_nested(&engine, &prog).unwrap(), b"BB");
+}
+
+#[test]
+fn no_pass_breaks_self_interpretation() {
+    // The tape's storage does not change what a pass does, and a dense one keeps the unoptimized
+    // runs quick.
+    let engine = Bropt::builder()
+        .conformance()
+        .tape(TapeKind::Dense)
+        .tape_len(65536)
+        .opt_level(0)
+        .build();
+    let mut broken = Vec::new();
+    for pass in PASSES {
+        let tree = run_pass(pass, parse(DBFI), CellWidth::U8, true, Eof::Unchanged).unwrap();
+        let result = engine.compile_ir(tree).and_then(|prog| run_nested(&engine, &prog));
+        if result.as_deref() != Ok(b"BB".as_slice()) {
+            broken.push(format!("{pass}: {result:?}"));
+        }
+    }
+    assert!(broken.is_empty(), "passes breaking conformance:\n{}", broken.join("\n"));
+}
+
+/// Stops a run after a number of instructions, so a miscompiled loop fails the test instead of
+/// hanging it.
+struct StepLimit(u64);
+
+impl ExecHooks for StepLimit {
+    fn on_instruction(&mut self, _ip: usize, _inst: &Inst, _dp: usize) {
+        self.0 = self.0.saturating_sub(1);
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.0 == 0
+    }
+}
+
+#[test]
+fn reading_past_the_end_of_input_matches_unoptimized() {
+    // Each loop polls `,` until the input runs out, which leaves the cell as it was, then prints
+    // `1`. A pass treating the read as a write would drop the `+` that ends the loop.
+    let ones = "+".repeat(49);
+    let programs = [format!("-[+,]{ones}."), format!("+--+-+>+--,[+,]>{ones}.")];
+    let run = |level: u8, code: &str, input: &[u8]| {
+        let engine = Bropt::builder().conformance().opt_level(level).build();
+        let prog = engine.compile(code).unwrap();
+        let mut output = Vec::new();
+        let mut limit = StepLimit(1 << 20);
+        engine
+            .run_with_hooks(&prog, &mut SliceReader::new(input), &mut output, &mut limit)
+            .unwrap();
+        output
+    };
+    for code in &programs {
+        for input in [&b""[..], b"\n", b"abc"] {
+            assert_eq!(run(0, code, input), b"1", "{code} on {input:?} at -O0");
+            assert_eq!(run(2, code, input), b"1", "{code} on {input:?} at -O2");
+        }
+    }
+}
+
+#[test]
+fn clearing_a_cell_before_reading_into_it_matches_unoptimized() {
+    // Each loop clears or sets a cell and reads into it, so once the input runs out the cell keeps
+    // what the loop left in it. A pass moving the clear out of the loop would keep the last byte
+    // read instead.
+    let programs = [
+        ">+<+[>[-],<-]>.",
+        ">+<+++[>[-],<-]>.",
+        ">+<+++[>[-]+,<-]>.",
+        "+++[>[-],.<-]",
+    ];
+    let run = |level: u8, code: &str, input: &[u8]| {
+        let engine = Bropt::builder().conformance().opt_level(level).build();
+        let prog = engine.compile(code).unwrap();
+        let mut output = Vec::new();
+        let mut limit = StepLimit(1 << 20);
+        engine
+            .run_with_hooks(&prog, &mut SliceReader::new(input), &mut output, &mut limit)
+            .unwrap();
+        output
+    };
+    for code in programs {
+        for input in [&b"A"[..], b"AB", b"abc\n"] {
+            assert_eq!(run(2, code, input), run(0, code, input), "{code} on {input:?}");
+        }
+    }
+}
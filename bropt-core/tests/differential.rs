@@ -0,0 +1,187 @@
+//! Differential tests for the optimizer: every golden pass input, every program in `benches/`
+//! and a few snippets run at -O0 and -O2 under each `Eof`, on input that runs out early and on
+//! input that lasts, and both runs must print the same thing.
+//!
+//! The golden files only pin down what a pass writes, so they miss a pass that writes the wrong
+//! thing. Running the trees catches it. Each tree starts a few cells into the tape and is followed
+//! by code printing the cells around where it stops, so a wrong value left on the tape shows up
+//! in the output too. Runs are cut short after a number of instructions. When the unoptimized
+//! run ends the optimized one must end with the same output, and otherwise the shorter output
+//! only has to be a prefix of the longer one.
+
+use std::fs;
+use std::path::Path;
+
+use bropt_core::Bropt;
+use bropt_core::brainfuck::{BaseInst, CellWidth, Eof, ExecHooks, Inst, SliceReader, parse_ir};
+use bropt_core::engine::Program;
+
+/// Most instructions a golden tree runs, plenty for the loops in them that end.
+const TREE_STEPS: u64 = 1 << 14;
+
+/// Most instructions a bench runs, which is a prefix of most of them but keeps unoptimized debug
+/// builds quick.
+const BENCH_STEPS: u64 = 1 << 20;
+
+/// Input the benches without an `.in` file get, short enough for programs polling `,` to reach
+/// its end.
+const INPUT: &[u8] = b"bropt\n12\n";
+
+/// Inputs the golden trees and snippets run on: none, a single byte and a few lines, so the end
+/// of input comes at once, after one `,` and after several.
+const INPUTS: [&[u8]; 3] = [b"", b"A", INPUT];
+
+/// Programs whose optimization depends on the `Eof` they run under.
+const SNIPPETS: &[&str] = &[
+    // A cell cleared before every `,` into it, which keeps the cell at the end of input.
+    ">+<+[>[-],<-]>.",
+    ">+<+++[>[-],<-]>.",
+    ">+<+++[>[-]+,<-]>.",
+    "+++[>[-],.<-]",
+    // A `,` into a cell the loop counts, which keeps the count at the end of input.
+    "+[>+,<-]>.",
+    "+++[>+,<-]>.",
+    // Loops polling `,` until the input runs out.
+    "-[+,]+++.",
+    "+[,.]",
+    ",[.,]+.",
+];
+
+/// Cells printed on each side of where a golden tree stops.
+const WINDOW: i32 = 8;
+
+/// Stops a run after a number of instructions.
+struct StepLimit(u64);
+
+impl ExecHooks for StepLimit {
+    fn on_instruction(&mut self, _ip: usize, _inst: &Inst, _dp: usize) {
+        self.0 = self.0.saturating_sub(1);
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// What a run printed and whether it ended within `steps` instructions, or `None` if it failed,
+/// as it does when it leaves the tape.
+fn run(engine: &Bropt, prog: &Program, input: &[u8], steps: u64) -> Option<(Vec<u8>, bool)> {
+    let mut output = Vec::new();
+    let mut limit = StepLimit(steps);
+    engine
+        .run_with_hooks(prog, &mut SliceReader::new(input), &mut output, &mut limit)
+        .ok()?;
+    Some((output, limit.0 > 0))
+}
+
+/// Runs `compile` at -O0 and -O2 under each `Eof` and describes every pair of runs that disagree.
+/// Unless `endless` is set, programs that do not end unoptimized within `steps` are skipped: an
+/// endless loop may become a single instruction spinning forever, which no step limit stops.
+fn compare(
+    name: &str,
+    cell: CellWidth,
+    input: &[u8],
+    steps: u64,
+    endless: bool,
+    compile: impl Fn(&Bropt) -> Result<Program, String>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    for eof in [Eof::Zero, Eof::MinusOne, Eof::Unchanged] {
+        let run_at = |level: u8| {
+            let engine = Bropt::builder().cell(cell).eof(eof).opt_level(level).build();
+            let prog = compile(&engine).unwrap_or_else(|err| panic!("{name}: {err}"));
+            run(&engine, &prog, input, steps)
+        };
+        // A run leaving the tape unoptimized may be fine optimized, once its dead accesses are gone.
+        let Some((expected, finished)) = run_at(0) else {
+            continue;
+        };
+        if !finished && !endless {
+            continue;
+        }
+        let optimized = run_at(2);
+        let agrees = match &optimized {
+            Some((actual, ended)) if finished => *ended && *actual == expected,
+            Some((actual, _)) => actual.starts_with(&expected) || expected.starts_with(actual),
+            None => false,
+        };
+        if !agrees {
+            failures.push(format!(
+                "{name} under {eof:?}:\n  -O0 {:?}\n  -O2 {:?}",
+                String::from_utf8_lossy(&expected),
+                optimized.map(|(actual, _)| String::from_utf8_lossy(&actual).into_owned())
+            ));
+        }
+    }
+    failures
+}
+
+#[test]
+fn golden_trees_agree_unoptimized_and_optimized() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/passes");
+    let mut inputs: Vec<_> = fs::read_dir(&root)
+        .unwrap()
+        .flat_map(|dir| fs::read_dir(dir.unwrap().path()).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "in"))
+        .collect();
+    inputs.sort();
+    let mut failures = Vec::new();
+    for input in inputs {
+        let text = fs::read_to_string(&input).unwrap();
+        let cell = match text.lines().map(str::trim).find(|line| line.starts_with("# cell ")) {
+            Some("# cell 16") => CellWidth::U16,
+            Some("# cell 32") => CellWidth::U32,
+            _ => CellWidth::U8,
+        };
+        let mut tree = parse_ir(&text).unwrap_or_else(|err| panic!("{}: {err}", input.display()));
+        tree.insert(0, BaseInst::Shift(WINDOW));
+        tree.push(BaseInst::Shift(-WINDOW));
+        for _ in -WINDOW..=WINDOW {
+            tree.extend([BaseInst::Output, BaseInst::Shift(1)]);
+        }
+        let path = input.strip_prefix(&root).unwrap().display();
+        for input in INPUTS {
+            let name = format!("{path} on {:?}", String::from_utf8_lossy(input));
+            failures.extend(compare(&name, cell, input, TREE_STEPS, false, |engine| {
+                engine.compile_ir(tree.clone())
+            }));
+        }
+    }
+    assert!(failures.is_empty(), "optimized runs differ:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn benches_agree_unoptimized_and_optimized() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches");
+    let mut programs: Vec<_> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "b"))
+        .collect();
+    programs.sort();
+    let mut failures = Vec::new();
+    for program in programs {
+        let code = fs::read_to_string(&program).unwrap();
+        let input = fs::read(program.with_extension("in")).unwrap_or_else(|_| INPUT.to_vec());
+        let name = program.file_name().unwrap().to_string_lossy().into_owned();
+        failures.extend(compare(&name, CellWidth::U8, &input, BENCH_STEPS, true, |engine| {
+            engine.compile(&code)
+        }));
+    }
+    assert!(failures.is_empty(), "optimized runs differ:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn snippets_agree_unoptimized_and_optimized() {
+    let mut failures = Vec::new();
+    for code in SNIPPETS {
+        for input in INPUTS {
+            let name = format!("{code} on {:?}", String::from_utf8_lossy(input));
+            failures.extend(compare(&name, CellWidth::U8, input, TREE_STEPS, false, |engine| {
+                engine.compile(code)
+            }));
+        }
+    }
+    assert!(failures.is_empty(), "optimized runs differ:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,35 @@
+//! Compiling is deterministic, so a program's fingerprint depends only on its source and settings.
+
+use bropt_core::Bropt;
+use bropt_core::brainfuck::Eof;
+use bropt_core::engine::Program;
+
+const FACTOR: &str = include_str!("../benches/Factor.b");
+
+#[test]
+fn compiling_twice_gives_the_same_fingerprint() {
+    let engine = Bropt::builder().build();
+    let first = engine.compile(FACTOR).unwrap();
+    let second = engine.compile(FACTOR).unwrap();
+    assert_eq!(first.to_bytes(), second.to_bytes());
+    assert_eq!(first.fingerprint(), second.fingerprint());
+    let loaded = Program::from_bytes(&first.to_bytes()).unwrap();
+    assert_eq!(loaded.fingerprint(), first.fingerprint());
+}
+
+#[test]
+fn fingerprint_tracks_instructions_and_settings() {
+    let engine = Bropt::builder().build();
+    let prog = engine.compile(FACTOR).unwrap();
+    let commented = engine.compile(&format!("comments are ignored\n{FACTOR}")).unwrap();
+    assert_eq!(commented.fingerprint(), prog.fingerprint());
+    let other = engine.compile("+[.+]").unwrap();
+    assert_ne!(other.fingerprint(), prog.fingerprint());
+    let wide = Bropt::builder().cell_u16().build().compile(FACTOR).unwrap();
+    assert_ne!(wide.fingerprint(), prog.fingerprint());
+    let unchanged = Bropt::builder().eof(Eof::Unchanged).build().compile(FACTOR).unwrap();
+    assert_ne!(unchanged.fingerprint(), prog.fingerprint());
+    let loaded = Program::from_bytes(&unchanged.to_bytes()).unwrap();
+    assert_eq!(loaded.eof(), Eof::Unchanged);
+    assert_eq!(loaded.fingerprint(), unchanged.fingerprint());
+}
@@ -0,0 +1,112 @@
+//! Golden tests for the optimization passes, one at a time.
+//!
+//! Each `tests/passes/<pass>/<case>.in` holds a tree in the text form of
+//! `bropt_core::brainfuck::irtext`, and `<case>.out` the tree `<pass>` turns it into. Cases run on
+//! 8-bit cells and a zeroed tape unless the input has a `# cell 16` or `# cell 32` line, or a
+//! `# preloaded` line for a tape that may start with nonzero cells, or a `# eof unchanged` line
+//! for input whose end leaves the cell `,` reads into as it was. Running with `BLESS=1`
+//! rewrites the `.out` files from what the passes produce now.
+
+use std::fs;
+use std::path::Path;
+
+use bropt_core::brainfuck::{
+    CellWidth, Eof, PASSES, optimize_with, optimize_with_passes, parse, parse_ir, print_ir, run_pass,
+};
+
+#[test]
+fn passes_match_golden_files() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/passes");
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+    for pass in PASSES {
+        let dir = root.join(pass);
+        let mut inputs: Vec<_> = fs::read_dir(&dir)
+            .unwrap_or_else(|err| panic!("{}: {err}", dir.display()))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "in"))
+            .collect();
+        inputs.sort();
+        assert!(!inputs.is_empty(), "{pass} has no golden cases");
+        for input in inputs {
+            let text = fs::read_to_string(&input).unwrap();
+            let (mut cell, mut zeroed, mut eof) = (CellWidth::U8, true, Eof::Zero);
+            for line in text.lines() {
+                match line.trim() {
+                    "# cell 16" => cell = CellWidth::U16,
+                    "# cell 32" => cell = CellWidth::U32,
+                    "# preloaded" => zeroed = false,
+                    "# eof unchanged" => eof = Eof::Unchanged,
+                    _ => {}
+                }
+            }
+            let prog = parse_ir(&text).unwrap_or_else(|err| panic!("{}: {err}", input.display()));
+            let actual = print_ir(&run_pass(pass, prog, cell, zeroed, eof).unwrap());
+            let output = input.with_extension("out");
+            if bless {
+                fs::write(&output, &actual).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&output).unwrap_or_default();
+            if actual != expected {
+                failures.push(format!(
+                    "{}:\n--- expected\n{expected}--- actual\n{actual}",
+                    output.display()
+                ));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} golden files differ, run with BLESS=1 to update them:\n\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn printed_ir_parses_back() {
+    let text = "inc -1\nshift 3\nblock unstable {\n    mul 1, 2\n}\nskip 2, -1, 0\nblock stable {\n    seek 1\n}\nfill -2, 4, 7\nblockmove 8, 4\n";
+    assert_eq!(print_ir(&parse_ir(text).unwrap()), text);
+    assert_eq!(
+        parse_ir("block { inc -1, shift 1, inc 1, shift -1 }").unwrap(),
+        parse_ir("block {\n    inc 255\n    shift 1\n    inc 1\n    shift -1\n}").unwrap()
+    );
+    assert!(parse_ir("block { inc 1").is_err());
+    assert!(parse_ir("inc 1 }").is_err());
+    assert!(parse_ir("inc 300").is_err());
+}
+
+#[test]
+fn extra_passes_run_after_every_pass() {
+    let prog = parse("++[->+<]>[-]<,[.,]");
+    let mut seen = Vec::new();
+    let optimized = optimize_with_passes(prog.clone(), 2, CellWidth::U8, true, Eof::Zero, &mut |name, prog| {
+        seen.push(name.to_string());
+        prog
+    });
+    assert_eq!(
+        optimized,
+        optimize_with(prog.clone(), 2, CellWidth::U8, true, Eof::Zero)
+    );
+    assert!(PASSES.iter().all(|pass| seen.iter().any(|name| name == pass)));
+    assert_eq!(seen.last().map(String::as_str), Some("bound_seeks"));
+
+    let cleared = optimize_with_passes(prog, 2, CellWidth::U8, true, Eof::Zero, &mut |name, prog| {
+        if name == "normalize" { Vec::new() } else { prog }
+    });
+    assert!(cleared.is_empty());
+}
+
+#[test]
+fn no_pass_overflows_on_offsets_near_the_ends_of_i32() {
+    // Debug builds check for overflow, so any pass adding offsets with `+` would panic here.
+    let text = "inc 1\nshift 2147483647\ninc 1\nblock stable {\n    shift 1073741824, shift 1073741824\n    mul 1073741824, 1\n    fill 2147483647, 4, 1\n    blockmove -2147483648, 2\n    shift -2147483648\n    inc -1\n}\nreset, inc 1\nblock {\n    seek 1073741824\n    skip -2147483648, 1, 3\n    shift 2147483647\n}\nshift -2147483648, output\n";
+    let prog = parse_ir(text).unwrap();
+    for eof in [Eof::Zero, Eof::MinusOne, Eof::Unchanged] {
+        for pass in PASSES {
+            run_pass(pass, prog.clone(), CellWidth::U8, true, eof).unwrap();
+        }
+        optimize_with(prog.clone(), 2, CellWidth::U8, true, eof);
+    }
+}
@@ -0,0 +1,27 @@
+#ifndef BROPT_H
+#define BROPT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BroptProgram BroptProgram;
+
+typedef int (*bropt_read_fn)(void *userdata);
+typedef void (*bropt_write_fn)(uint8_t byte, void *userdata);
+
+BroptProgram *bropt_compile(const char *code, size_t len);
+void bropt_program_free(BroptProgram *prog);
+int bropt_run(const BroptProgram *prog, size_t length, const uint8_t *input, size_t input_len, uint8_t *output,
+              size_t output_cap, size_t *output_len);
+int bropt_run_cb(const BroptProgram *prog, size_t length, bropt_read_fn read, bropt_write_fn write, void *userdata);
+const char *bropt_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,215 @@
+//! The C API of bropt, built into the `libbropt_ffi` shared library and declared in
+//! `include/bropt.h`.
+
+use std::cell::RefCell;
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use bropt_core::brainfuck::{Inst, run_with_io, try_compile};
+
+pub struct BroptProgram {
+    prog: Vec<Inst>,
+}
+
+pub type BroptReadFn = Option<unsafe extern "C" fn(userdata: *mut c_void) -> c_int>;
+pub type BroptWriteFn = Option<unsafe extern "C" fn(byte: u8, userdata: *mut c_void)>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "\\0")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+fn panic_message(err: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = err.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panic occurred".to_string()
+    }
+}
+
+fn guard<T, F: FnOnce() -> Result<T, String>>(f: F) -> Option<T> {
+    clear_last_error();
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(msg)) => {
+            set_last_error(&msg);
+            None
+        }
+        Err(err) => {
+            set_last_error(&panic_message(err));
+            None
+        }
+    }
+}
+
+/// Compiles `len` bytes of brainfuck source at `code`.
+///
+/// Returns a new program handle, or NULL on failure (see `bropt_last_error`).
+/// The handle must be released with `bropt_program_free`.
+///
+/// # Safety
+/// `code` must point to `len` readable bytes (it may be NULL when `len` is 0).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bropt_compile(code: *const c_char, len: usize) -> *mut BroptProgram {
+    let code = if len == 0 {
+        &[][..]
+    } else if code.is_null() {
+        set_last_error("code is NULL");
+        return ptr::null_mut();
+    } else {
+        unsafe { slice::from_raw_parts(code as *const u8, len) }
+    };
+    let code = String::from_utf8_lossy(code);
+    match guard(|| try_compile(&code)) {
+        Some(prog) => Box::into_raw(Box::new(BroptProgram { prog })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Releases a program returned by `bropt_compile`. Passing NULL is a no-op.
+///
+/// # Safety
+/// `prog` must be NULL or a handle from `bropt_compile` that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bropt_program_free(prog: *mut BroptProgram) {
+    if !prog.is_null() {
+        drop(unsafe { Box::from_raw(prog) });
+    }
+}
+
+/// Runs `prog` on a tape of `length` cells, reading `,` from the `input` buffer
+/// (0 once exhausted) and writing `.` into the `output` buffer.
+///
+/// On return `*output_len` holds the number of bytes the program produced. If it
+/// exceeds `output_cap` the output is truncated and the call fails.
+/// Returns 0 on success and -1 on failure (see `bropt_last_error`).
+///
+/// # Safety
+/// `prog` must be a live handle, `input` must point to `input_len` readable bytes,
+/// `output` to `output_cap` writable bytes, and `output_len` must be writable.
+/// The buffers may be NULL when their length is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bropt_run(
+    prog: *const BroptProgram,
+    length: usize,
+    input: *const u8,
+    input_len: usize,
+    output: *mut u8,
+    output_cap: usize,
+    output_len: *mut usize,
+) -> c_int {
+    if prog.is_null() || output_len.is_null() {
+        set_last_error("prog or output_len is NULL");
+        return -1;
+    }
+    if (input.is_null() && input_len != 0) || (output.is_null() && output_cap != 0) {
+        set_last_error("buffer is NULL");
+        return -1;
+    }
+    let prog = unsafe { &(*prog).prog };
+    let input = if input_len == 0 {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(input, input_len) }
+    };
+    let output = if output_cap == 0 {
+        &mut [][..]
+    } else {
+        unsafe { slice::from_raw_parts_mut(output, output_cap) }
+    };
+    let mut in_idx = 0usize;
+    let mut out_idx = 0usize;
+    let result = guard(|| {
+        run_with_io(
+            prog,
+            length,
+            &mut || {
+                let byte = input.get(in_idx).copied();
+                in_idx += 1;
+                byte
+            },
+            &mut |byte| {
+                if let Some(slot) = output.get_mut(out_idx) {
+                    *slot = byte;
+                }
+                out_idx += 1;
+            },
+        );
+        Ok(())
+    });
+    unsafe { *output_len = out_idx };
+    match result {
+        Some(()) if out_idx > output_cap => {
+            set_last_error("output buffer too small");
+            -1
+        }
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Runs `prog` on a tape of `length` cells with callback I/O.
+///
+/// `read` returns the next input byte (0-255) or a negative value on EOF, which
+/// stores 0. `write` receives every output byte. Both receive `userdata`.
+/// Returns 0 on success and -1 on failure (see `bropt_last_error`).
+///
+/// # Safety
+/// `prog` must be a live handle and the callbacks must be safe to call with `userdata`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bropt_run_cb(
+    prog: *const BroptProgram,
+    length: usize,
+    read: BroptReadFn,
+    write: BroptWriteFn,
+    userdata: *mut c_void,
+) -> c_int {
+    if prog.is_null() {
+        set_last_error("prog is NULL");
+        return -1;
+    }
+    let (Some(read), Some(write)) = (read, write) else {
+        set_last_error("callback is NULL");
+        return -1;
+    };
+    let prog = unsafe { &(*prog).prog };
+    let result = guard(|| {
+        run_with_io(
+            prog,
+            length,
+            &mut || match unsafe { read(userdata) } {
+                byte @ 0..=255 => Some(byte as u8),
+                _ => None,
+            },
+            &mut |byte| unsafe { write(byte, userdata) },
+        );
+        Ok(())
+    });
+    match result {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Returns the message of the last error raised on the calling thread, or NULL.
+///
+/// The pointer stays valid until the next bropt call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn bropt_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null(),
+    })
+}
@@ -0,0 +1,58 @@
+//! `include_bf!`, which compiles a brainfuck file while the crate using it builds, so embedding
+//! a program costs nothing to compile at run time.
+
+use std::path::Path;
+
+use proc_macro::{TokenStream, TokenTree};
+
+/// Compiles the brainfuck file at the given path, relative to the `Cargo.toml` of the crate
+/// using it, for 8-bit cells at the highest optimization level, and expands to the optimized
+/// instructions as a `&'static [bropt_core::brainfuck::Inst]`. Run them with
+/// `bropt_core::brainfuck::run_with_io`, or `run_static` without allocating.
+#[proc_macro]
+pub fn include_bf(input: TokenStream) -> TokenStream {
+    let expanded = expand(input).unwrap_or_else(|err| format!("::core::compile_error!({:?})", err));
+    expanded.parse().unwrap()
+}
+
+fn expand(input: TokenStream) -> Result<String, String> {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").map_err(|err| format!("CARGO_MANIFEST_DIR: {}", err))?;
+    let path = Path::new(&dir).join(path_literal(input)?);
+    let path = path.to_str().ok_or("the path is not valid UTF-8")?;
+    let code = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let prog = bropt_core::brainfuck::try_compile(&code).map_err(|err| format!("{}: {}", path, err))?;
+    let insts: Vec<String> = prog
+        .iter()
+        .map(|inst| {
+            format!(
+                "::bropt_core::brainfuck::Inst::from_raw_parts(::bropt_core::brainfuck::InstType::{:?}, {}, {}, {})",
+                inst.cmd(),
+                inst.inc(),
+                inst.delta(),
+                inst.arg()
+            )
+        })
+        .collect();
+    // Including the file makes cargo rebuild the crate when it changes.
+    Ok(format!(
+        "{{ const _: &[u8] = ::core::include_bytes!({:?}); \
+         static PROG: &[::bropt_core::brainfuck::Inst] = unsafe {{ &[{}] }}; PROG }}",
+        path,
+        insts.join(", ")
+    ))
+}
+
+/// The contents of the string literal that is the only token of `input`.
+fn path_literal(input: TokenStream) -> Result<String, String> {
+    let mut tokens = input.into_iter();
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal.to_string(),
+        (Some(TokenTree::Group(group)), None) => return path_literal(group.stream()),
+        _ => return Err("include_bf! takes the path of a file as a string literal".into()),
+    };
+    match literal.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(path) if !path.contains('\\') => Ok(path.to_string()),
+        Some(_) => Err("include_bf! does not support escapes in the path".into()),
+        None => Err("include_bf! takes the path of a file as a string literal".into()),
+    }
+}
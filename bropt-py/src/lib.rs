@@ -0,0 +1,496 @@
+//! Python bindings for the bropt engine, which maturin builds into the `bropt` extension module.
+
+use std::sync::Mutex;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyMemoryError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyString, PyTuple};
+
+use bropt_core::brainfuck::{
+    BaseInst, ByteRead, ByteWrite, Eof, FlushPolicy, Inst, InstType, NoHooks, OutOfBounds, OutputChunk, OutputLimit,
+    PASSES, PartialInput, Rng, SliceReader, Stdin, Stdout, Wrapping, execute_proven, execute_with_hooks,
+    flatten as flatten_tree, resume_with_hooks, try_parse, validate,
+};
+use bropt_core::engine::{Bropt, Program as Compiled};
+
+// Raised with the arguments `(message, limit, requested)` when a run needs more than `max_memory` bytes.
+create_exception!(bropt, ResourceExhausted, PyMemoryError);
+
+fn panic_to_pyerr(err: Box<dyn std::any::Any + Send>) -> PyErr {
+    if let Some(s) = err.downcast_ref::<&str>() {
+        PyRuntimeError::new_err(*s)
+    } else if let Some(s) = err.downcast_ref::<String>() {
+        PyRuntimeError::new_err(s.clone())
+    } else {
+        PyRuntimeError::new_err("panic occurred")
+    }
+}
+
+fn extract_bytes(obj: Option<&Bound<'_, PyAny>>) -> PyResult<Vec<u8>> {
+    match obj {
+        Some(obj) => {
+            if let Ok(b) = obj.downcast::<PyBytes>() {
+                Ok(b.as_bytes().to_vec())
+            } else {
+                obj.extract::<Vec<u8>>()
+            }
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// A run that left the tape, raised with the loops it was in.
+fn out_of_bounds(prog: &Compiled, err: OutOfBounds) -> PyErr {
+    PyRuntimeError::new_err(err.with_loops(prog.insts(), prog.source_map()))
+}
+
+/// `out` as a `str` decoded as UTF-8 with `text=True`, otherwise as a `bytearray`.
+fn output_object(py: Python<'_>, out: &[u8], text: bool) -> Py<PyAny> {
+    if text {
+        PyString::new(py, &String::from_utf8_lossy(out)).into_any().unbind()
+    } else {
+        PyByteArray::new(py, out).into_any().unbind()
+    }
+}
+
+/// Frozen, so any number of threads can run it at once, also on free-threaded Python. Each run
+/// takes the program as it is when it starts; [`Program::extend`] appends under the lock,
+/// copying the instructions only while a run still holds the old ones.
+#[pyclass(frozen)]
+pub struct Program {
+    prog: Mutex<Compiled>,
+}
+
+impl Program {
+    /// The program as it is now, which shares its instructions and source map with this one.
+    fn compiled(&self) -> Compiled {
+        self.prog.lock().unwrap().clone()
+    }
+}
+
+#[pymethods]
+impl Program {
+    /// Compiles `code` and appends it without recompiling what is already there, so a session
+    /// can grow a program a line at a time.
+    pub fn extend(&self, code: &str) -> PyResult<()> {
+        self.prog.lock().unwrap().extend(code).map_err(PyRuntimeError::new_err)
+    }
+
+    /// `tape` preloads the first cells of the tape, which needs a program compiled with
+    /// `preloaded=True`. With `text=True` the output is decoded as UTF-8 into a `str`, replacing
+    /// invalid sequences. The GIL is released while the program runs, so threads sharing the
+    /// program run it in parallel. A run that leaves the tape raises `RuntimeError` naming the
+    /// loops it was in.
+    #[pyo3(signature = (length, input=None, max_memory=None, text=false, tape=None))]
+    pub fn run(
+        &self,
+        py: Python<'_>,
+        length: usize,
+        input: Option<&Bound<'_, PyAny>>,
+        max_memory: Option<usize>,
+        text: bool,
+        tape: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<(Py<PyAny>, Py<PyByteArray>, usize)> {
+        let input_bytes = extract_bytes(input)?;
+        let tape_bytes = extract_bytes(tape)?;
+        if tape_bytes.len() > length {
+            return Err(PyValueError::new_err(format!(
+                "tape of {} bytes does not fit in {} cells",
+                tape_bytes.len(),
+                length
+            )));
+        }
+        let prog = self.compiled();
+        if !prog.preloaded() && tape_bytes.iter().any(|&byte| byte != 0) {
+            return Err(PyValueError::new_err(
+                "program was compiled for a zeroed tape; compile it with preloaded=True",
+            ));
+        }
+        let exhausted = |limit: usize, requested: usize| {
+            let err = bropt_core::brainfuck::ResourceExhausted { limit, requested };
+            ResourceExhausted::new_err((err.to_string(), limit, requested))
+        };
+        // The tape is refused before it is allocated when it alone is over the limit.
+        if let Some(limit) = max_memory
+            && length > limit
+        {
+            return Err(exhausted(limit, length));
+        }
+        let result = py.allow_threads(|| {
+            std::panic::catch_unwind(|| {
+                let mut data = vec![0u8; length];
+                data[..tape_bytes.len()].copy_from_slice(&tape_bytes);
+                let (mut input, mut out, mut rng) = (SliceReader::new(&input_bytes), Vec::new(), Rng::default());
+                let mut limit = max_memory.map(|limit| OutputLimit::new(limit - length));
+                let dp = match &mut limit {
+                    Some(limit) => execute_with_hooks::<_, Wrapping, _, _, _, _>(
+                        prog.insts(),
+                        &mut data[..],
+                        Eof::Zero,
+                        &mut input,
+                        &mut out,
+                        &mut rng,
+                        limit,
+                    ),
+                    None => execute_proven::<_, Wrapping, _, _, _>(
+                        prog.insts(),
+                        &mut data[..],
+                        Eof::Zero,
+                        &mut input,
+                        &mut out,
+                        &mut rng,
+                    ),
+                };
+                let exceeded = limit.is_some_and(|limit| limit.exceeded());
+                dp.map(|dp| (out, data, dp, exceeded))
+            })
+        });
+        match result {
+            Ok(Ok((out, _, _, true))) => Err(exhausted(max_memory.unwrap(), length + out.len())),
+            Ok(Ok((out, data, ptr, false))) => {
+                Ok((output_object(py, &out, text), PyByteArray::new(py, &data).into(), ptr))
+            }
+            Ok(Err(err)) => Err(out_of_bounds(&prog, err)),
+            Err(err) => Err(panic_to_pyerr(err)),
+        }
+    }
+
+    /// Runs on a zeroed tape of `length` cells with `,` reading this process's stdin and `.`
+    /// writing each byte to its stdout as it is produced, so games can be played from a terminal.
+    /// The GIL is released while the program runs. Returns the final tape and pointer.
+    pub fn run_interactive(&self, py: Python<'_>, length: usize) -> PyResult<(Py<PyByteArray>, usize)> {
+        // Output Python buffered so far must come before the program's.
+        if let Ok(stdout) = py.import("sys")?.getattr("stdout") {
+            let _ = stdout.call_method0("flush");
+        }
+        let prog = self.compiled();
+        let result = py.allow_threads(|| {
+            std::panic::catch_unwind(|| {
+                let mut data = vec![0u8; length];
+                execute_proven::<_, Wrapping, _, _, _>(
+                    prog.insts(),
+                    &mut data[..],
+                    Eof::Zero,
+                    &mut Stdin,
+                    &mut Stdout::new(FlushPolicy::PerByte),
+                    &mut Rng::default(),
+                )
+                .map(|dp| (data, dp))
+            })
+        });
+        match result {
+            Ok(Ok((data, ptr))) => Ok((PyByteArray::new(py, &data).into(), ptr)),
+            Ok(Err(err)) => Err(out_of_bounds(&prog, err)),
+            Err(err) => Err(panic_to_pyerr(err)),
+        }
+    }
+
+    /// Starts an interactive run on a zeroed tape of `length` cells, which [`Session::send`]
+    /// drives a message at a time.
+    pub fn start(&self, length: usize) -> Session {
+        Session {
+            prog: self.compiled(),
+            data: vec![0; length],
+            ip: 0,
+            dp: 0,
+            rng: Rng::default(),
+        }
+    }
+}
+
+/// A run that pauses whenever the program waits for input nobody has sent yet, or for the caller
+/// to take its output. One thread drives it at a time: a call made while another thread's call
+/// is running raises `RuntimeError`.
+#[pyclass]
+pub struct Session {
+    prog: Compiled,
+    data: Vec<u8>,
+    ip: usize,
+    dp: usize,
+    rng: Rng,
+}
+
+impl Session {
+    fn step<R: ByteRead, W: ByteWrite>(&mut self, input: &mut R, output: &mut W) -> PyResult<()> {
+        let (ip, dp) = resume_with_hooks::<_, Wrapping, _, _, _, _>(
+            self.prog.insts(),
+            &mut self.data[..],
+            (self.ip, self.dp),
+            Eof::Zero,
+            input,
+            output,
+            &mut self.rng,
+            &mut NoHooks,
+        )
+        .map_err(|err| out_of_bounds(&self.prog, err))?;
+        (self.ip, self.dp) = (ip, dp);
+        Ok(())
+    }
+
+    /// Whether the program is stopped at a `,`.
+    fn needs_input(&self) -> bool {
+        self.prog
+            .insts()
+            .get(self.ip)
+            .is_some_and(|inst| inst.cmd() == InstType::Input)
+    }
+}
+
+#[pymethods]
+impl Session {
+    /// Runs until the program ends or waits for more than `input` holds, returning the output
+    /// since the last call and whether the program is waiting for input.
+    #[pyo3(signature = (input=None, text=false))]
+    pub fn send(
+        &mut self,
+        py: Python<'_>,
+        input: Option<&Bound<'_, PyAny>>,
+        text: bool,
+    ) -> PyResult<(Py<PyAny>, bool)> {
+        let input = extract_bytes(input)?;
+        let mut out = Vec::new();
+        self.step(&mut PartialInput::new(&input), &mut out)?;
+        Ok((output_object(py, &out, text), self.needs_input()))
+    }
+
+    /// Runs until the program ends, waits for input or has `size` more bytes of output, returning
+    /// that output and whether the program is waiting for input, so output can be pulled a chunk
+    /// at a time.
+    #[pyo3(signature = (size, text=false))]
+    pub fn read(&mut self, py: Python<'_>, size: usize, text: bool) -> PyResult<(Py<PyAny>, bool)> {
+        let mut chunk = OutputChunk::new(size);
+        self.step(&mut PartialInput::new(&[]), &mut chunk)?;
+        Ok((output_object(py, &chunk.into_bytes(), text), self.needs_input()))
+    }
+
+    /// Ends the input and runs the program to the end, returning the output since the last call,
+    /// the tape and the pointer as [`Program::run`] does.
+    #[pyo3(signature = (text=false))]
+    pub fn close(&mut self, py: Python<'_>, text: bool) -> PyResult<(Py<PyAny>, Py<PyByteArray>, usize)> {
+        let mut out = Vec::new();
+        self.step(&mut SliceReader::new(&[]), &mut out)?;
+        Ok((
+            output_object(py, &out, text),
+            PyByteArray::new(py, &self.data).into(),
+            self.dp,
+        ))
+    }
+
+    /// Whether the program has ended.
+    #[getter]
+    pub fn finished(&self) -> bool {
+        self.ip >= self.prog.insts().len()
+    }
+}
+
+/// The tree IR as Python objects: each instruction a tuple of its name, as the text IR spells
+/// it, and its operands, with increments, weights and fill values signed. A loop is
+/// `("block", body, balanced, offset)`, with `body` a list and `offset` its `[` in the source
+/// or `None`, and a skip's increments are a list of `(inc, offset)` pairs.
+fn tree_to_py(py: Python<'_>, prog: &[BaseInst]) -> PyResult<Vec<Py<PyAny>>> {
+    prog.iter()
+        .map(|inst| {
+            let obj = match inst {
+                BaseInst::Inc(inc) => ("inc", *inc as i8).into_pyobject(py)?,
+                BaseInst::Shift(delta) => ("shift", *delta).into_pyobject(py)?,
+                BaseInst::Output => ("output",).into_pyobject(py)?,
+                BaseInst::ErrOutput => ("erroutput",).into_pyobject(py)?,
+                BaseInst::Input => ("input",).into_pyobject(py)?,
+                BaseInst::Random => ("random",).into_pyobject(py)?,
+                BaseInst::Reset => ("reset",).into_pyobject(py)?,
+                BaseInst::Mul(offset, weight) => ("mul", *offset, *weight as i8).into_pyobject(py)?,
+                BaseInst::Seek(stride) => ("seek", *stride).into_pyobject(py)?,
+                BaseInst::BoundedSeek(stride, max) => ("boundedseek", *stride, *max).into_pyobject(py)?,
+                BaseInst::Skip(stride, incs) => {
+                    let incs: Vec<(i8, i16)> = incs.iter().map(|&(inc, offset)| (inc as i8, offset)).collect();
+                    ("skip", *stride, incs).into_pyobject(py)?
+                }
+                BaseInst::Fill(offset, len, value) => ("fill", *offset, *len, *value as i8).into_pyobject(py)?,
+                BaseInst::BlockMove(offset, len) => ("blockmove", *offset, *len).into_pyobject(py)?,
+                BaseInst::Block(body, balanced, offset) => {
+                    ("block", tree_to_py(py, body)?, *balanced, *offset).into_pyobject(py)?
+                }
+            };
+            Ok(obj.into_any().unbind())
+        })
+        .collect()
+}
+
+/// Reads a tree written as [`tree_to_py`] writes it, taking increments, weights and fill values
+/// as signed or unsigned bytes.
+fn tree_from_py(ir: &Bound<'_, PyAny>) -> PyResult<Vec<BaseInst>> {
+    fn byte(value: i16) -> PyResult<u8> {
+        match value {
+            -128..=255 => Ok(value as u8),
+            _ => Err(PyValueError::new_err(format!("{value} does not fit in a byte"))),
+        }
+    }
+    let mut prog = Vec::new();
+    for item in ir.try_iter()? {
+        let item = item?;
+        let tuple = item.downcast::<PyTuple>()?;
+        let name: String = tuple.get_item(0)?.extract()?;
+        prog.push(match name.as_str() {
+            "inc" => BaseInst::Inc(byte(tuple.extract::<(String, i16)>()?.1)?),
+            "shift" => BaseInst::Shift(tuple.extract::<(String, i32)>()?.1),
+            "output" => BaseInst::Output,
+            "erroutput" => BaseInst::ErrOutput,
+            "input" => BaseInst::Input,
+            "random" => BaseInst::Random,
+            "reset" => BaseInst::Reset,
+            "mul" => {
+                let (_, offset, weight) = tuple.extract::<(String, i32, i16)>()?;
+                BaseInst::Mul(offset, byte(weight)?)
+            }
+            "seek" => BaseInst::Seek(tuple.extract::<(String, i32)>()?.1),
+            "boundedseek" => {
+                let (_, stride, max) = tuple.extract::<(String, i32, i16)>()?;
+                BaseInst::BoundedSeek(stride, max)
+            }
+            "skip" => {
+                let (_, stride, incs) = tuple.extract::<(String, i32, Vec<(i16, i16)>)>()?;
+                let incs = incs.into_iter().map(|(inc, offset)| Ok((byte(inc)?, offset)));
+                BaseInst::Skip(stride, incs.collect::<PyResult<_>>()?)
+            }
+            "fill" => {
+                let (_, offset, len, value) = tuple.extract::<(String, i32, i16, i16)>()?;
+                BaseInst::Fill(offset, len, byte(value)?)
+            }
+            "blockmove" => {
+                let (_, offset, len) = tuple.extract::<(String, i32, i16)>()?;
+                BaseInst::BlockMove(offset, len)
+            }
+            "block" => {
+                let (_, body, balanced, offset) = tuple.extract::<(String, Bound<'_, PyAny>, bool, Option<u32>)>()?;
+                BaseInst::Block(tree_from_py(&body)?, balanced, offset)
+            }
+            _ => return Err(PyValueError::new_err(format!("unknown instruction {name:?}"))),
+        });
+    }
+    Ok(prog)
+}
+
+/// A flat instruction as `(name, arg, inc, delta)`, with `inc` signed.
+fn inst_to_py(inst: &Inst) -> (String, i32, i8, i16) {
+    (inst.cmd().to_string(), inst.arg(), inst.inc() as i8, inst.delta())
+}
+
+/// Passes written in Python, as `(after, func)` pairs that call `func` with the tree after every
+/// run of bropt's pass `after`.
+type PyPasses = Vec<(String, Py<PyAny>)>;
+
+/// Runs `stage` with the extra passes `passes` hooked in after the passes they name. A pass that
+/// raises or returns an invalid tree stops the ones after it, and its error is raised once
+/// `stage` is done.
+fn with_passes<T>(
+    py: Python<'_>,
+    passes: &PyPasses,
+    stage: impl FnOnce(&mut dyn FnMut(&str, Vec<BaseInst>) -> Vec<BaseInst>) -> T,
+) -> PyResult<T> {
+    if let Some((after, _)) = passes.iter().find(|(after, _)| !PASSES.contains(&after.as_str())) {
+        return Err(PyValueError::new_err(format!("unknown pass {after:?}")));
+    }
+    let mut error = None;
+    let result = stage(&mut |name, mut prog| {
+        for (_, func) in passes.iter().filter(|(after, _)| after == name) {
+            if error.is_some() {
+                break;
+            }
+            let rewritten = tree_to_py(py, &prog)
+                .and_then(|ir| func.call1(py, (ir,)))
+                .and_then(|ir| tree_from_py(ir.bind(py)))
+                .and_then(|new| match validate(&new) {
+                    Ok(()) => Ok(new),
+                    Err(err) => Err(PyValueError::new_err(format!("pass after {name}: {err}"))),
+                });
+            match rewritten {
+                Ok(new) => prog = new,
+                Err(err) => error = Some(err),
+            }
+        }
+        prog
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(result),
+    }
+}
+
+/// Parses `code` into the tree IR as Python objects, without optimizing it.
+#[pyfunction]
+fn parse(py: Python<'_>, code: &str) -> PyResult<Vec<Py<PyAny>>> {
+    let prog = try_parse(code).map_err(PyRuntimeError::new_err)?;
+    tree_to_py(py, &prog)
+}
+
+/// Runs bropt's optimizer over a tree from [`parse`] or built in Python, at `level` 0 to 2.
+/// `preloaded=True` drops the assumption that the tape starts zeroed. `passes` lists
+/// `(after, func)` pairs, and each `func` takes the tree after every run of the pass named
+/// `after` and returns the tree the pipeline goes on with.
+#[pyfunction]
+#[pyo3(signature = (ir, level=2, preloaded=false, passes=PyPasses::new()))]
+fn optimize(
+    py: Python<'_>,
+    ir: &Bound<'_, PyAny>,
+    level: u8,
+    preloaded: bool,
+    passes: PyPasses,
+) -> PyResult<Vec<Py<PyAny>>> {
+    let engine = Bropt::builder().opt_level(level).preloaded(preloaded).build();
+    let prog = tree_from_py(ir)?;
+    let prog = with_passes(py, &passes, |extra| engine.optimize_with_passes(prog, extra))?;
+    tree_to_py(py, &prog.map_err(PyValueError::new_err)?)
+}
+
+/// Lowers a tree to the flat instructions the interpreters run, as `(name, arg, inc, delta)`
+/// tuples, without the passes that fuse and thread them.
+#[pyfunction]
+fn flatten(ir: &Bound<'_, PyAny>) -> PyResult<Vec<(String, i32, i8, i16)>> {
+    let prog = tree_from_py(ir)?;
+    validate(&prog).map_err(PyValueError::new_err)?;
+    Ok(flatten_tree(prog).iter().map(inst_to_py).collect())
+}
+
+/// Compiles a tree into a program to run, optimizing it at `level` first, so trees rewritten
+/// in Python run on bropt's interpreters. `passes` hooks into the optimizer as in [`optimize`].
+#[pyfunction]
+#[pyo3(signature = (ir, level=0, preloaded=false, passes=PyPasses::new()))]
+fn compile_ir(
+    py: Python<'_>,
+    ir: &Bound<'_, PyAny>,
+    level: u8,
+    preloaded: bool,
+    passes: PyPasses,
+) -> PyResult<Program> {
+    let engine = Bropt::builder().opt_level(level).preloaded(preloaded).build();
+    let prog = tree_from_py(ir)?;
+    match with_passes(py, &passes, |extra| engine.compile_ir_with_passes(prog, extra))? {
+        Ok(prog) => Ok(Program { prog: Mutex::new(prog) }),
+        Err(err) => Err(PyValueError::new_err(err)),
+    }
+}
+
+/// `preloaded=True` compiles for runs given a `tape`, without assuming the tape starts zeroed.
+#[pyfunction]
+#[pyo3(signature = (code, preloaded=false))]
+fn compile(code: &str, preloaded: bool) -> PyResult<Program> {
+    let engine = Bropt::builder().preloaded(preloaded).build();
+    match engine.compile(code) {
+        Ok(prog) => Ok(Program { prog: Mutex::new(prog) }),
+        Err(err) => Err(PyRuntimeError::new_err(err)),
+    }
+}
+
+#[pymodule(gil_used = false)]
+fn bropt(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(compile_ir, m)?)?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(optimize, m)?)?;
+    m.add_function(wrap_pyfunction!(flatten, m)?)?;
+    m.add_class::<Program>()?;
+    m.add_class::<Session>()?;
+    m.add("ResourceExhausted", m.py().get_type::<ResourceExhausted>())?;
+    Ok(())
+}